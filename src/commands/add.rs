@@ -10,17 +10,19 @@
 //! - Verbose output of changes
 
 use std::collections::HashSet;
-use std::process;
+use std::fs;
 use std::path::Path;
 
-use crate::core::blob::BlobTrait;
 use crate::{core::*, utils::*};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
 use crate::core::index::{Index, IndexEntry};
 
 
 /// Core logic for adding files to the index.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `files` - List of file/directory paths to add.
 ///
 /// # Returns
@@ -33,25 +35,33 @@ use crate::core::index::{Index, IndexEntry};
 /// # Behavior
 /// 1. Registers all files recursively using `register_entries`.
 /// 2. Reads current index.
-/// 3. Hashes each file and updates the index accordingly.
+/// 3. Hashes each file whose stat data differs from its index entry, and updates the index accordingly.
 /// 4. Detects and logs additions, modifications, and deletions.
 ///
-/// # Exits
+/// # Errors
 /// * If any path cannot be canonicalized.
-/// * If any path is outside the repository root.
-pub fn add_core(files: &Vec<String>) -> (
+/// * `Error::InvalidArgument` if any path is outside the repository root, or a file
+///   name cannot be stored in a tree (see `tree::check_entry_name`).
+/// * If the index cannot be read or a file cannot be hashed or stored.
+#[allow(clippy::type_complexity)]
+pub fn add_core(repo: &Repository, files: &Vec<String>) -> Result<(
     Index,
     HashSet<IndexEntry>,
     HashSet<IndexEntry>,
     HashSet<IndexEntry>,
-) {
+)> {
 
-    let (new_entries, base_entries) = register_entries(files);
+    let (new_entries, base_entries) = register_entries(repo, files)?;
+    for entry in &new_entries {
+        for name in entry.path.split('/') {
+            tree::check_entry_name(name)?;
+        }
+    }
 
     // After registration, ALL entry.hash ARE EMPTY in new_entries !!!
 
-    let mut index = index::read_index();
-    let repo_path = utils::pwd();
+    let mut index = index::read_index(repo)?;
+    let repo_path = repo.path();
 
     let mut add_log: HashSet<IndexEntry> = Default::default();
     let mut remove_log: HashSet<IndexEntry> = Default::default();
@@ -60,36 +70,45 @@ pub fn add_core(files: &Vec<String>) -> (
     for entry in &new_entries  {
 
         // entry.hash is empty !!!
+        // Files are hashed (and later stored) in chunks, so large files never need to fit in memory.
         let file_path = format!("{}/{}", repo_path, entry.path);
-        let mut blob = blob::get_blob_from_file(&file_path);
         
         let path = entry.path.clone();
-        let hash = blob.hash.clone().unwrap();
+        let meta = fs::symlink_metadata(&file_path).map_err(Error::io(format!("Failed to stat {}", file_path)))?;
+
+        // stat-cache fast path: a file with unchanged stat data is not rehashed
+        if index.get(&path).is_some_and(|old_entry| old_entry.matches_stat(&meta)) {
+            continue;
+        }
 
         match index.get(&path) {
             None => {
                 // A new entry
-                let new_entry = IndexEntry { path: path.clone(), hash: hash.clone() };
+                let hash = blob::write_blob_file(repo, &file_path)?;
+                let new_entry = IndexEntry::from_metadata(&path, &hash, &file_path, &meta)?;
 
                 add_log.insert(new_entry.clone());
 
                 index.insert(path, new_entry);
-                blob.write_blob();
                 
                 continue;
             }
             Some(old_entry) => {
                 // An old entry
-                if old_entry.hash == hash {
+                let hash = blob::hash_blob_file(repo, &file_path)?;
+                let new_entry = IndexEntry::from_metadata(&path, &hash, &file_path, &meta)?;
+                if old_entry.hash == hash && old_entry.entry_type == new_entry.entry_type {
+                    // unchanged content, only the stat data may need refreshing
+                    index.insert(path, new_entry);
                     continue;
                 } else {
-                    let new_entry = IndexEntry { path: path.clone(), hash: hash.clone() };
+                    let hash = blob::write_blob_file(repo, &file_path)?;
+                    let new_entry = IndexEntry { hash, ..new_entry };
 
                     modify_log.insert(new_entry.clone());
 
                     index.remove(&path);
                     index.insert(path, new_entry);
-                    blob.write_blob();
                     
                     continue;
                 }
@@ -106,7 +125,7 @@ pub fn add_core(files: &Vec<String>) -> (
         if !new_entry_paths.contains(&old_entry.path) {
             for base_entry in &base_entries {
                 let old_entry_full = format!("{}/{}", repo_path, &old_entry.path);
-                if utils::is_subpath(&base_entry, &old_entry_full) {
+                if utils::is_subpath(base_entry, &old_entry_full) {
                     remove_log.insert(old_entry.clone());
                     return false;
                 }
@@ -117,30 +136,37 @@ pub fn add_core(files: &Vec<String>) -> (
         }
     });
 
-    (index, add_log, remove_log, modify_log)
+    Ok((index, add_log, remove_log, modify_log))
 }
 
 
 /// Adds the specified files or directories to the index.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `files` - List of file/directory paths to add.
 /// * `verbose` - If true, displays beautified output instead of just hashes.
 ///
 /// # Behavior
-/// 1. Calls `add_core` to compute index updates.
-/// 2. Writes updated index back to disk.
+/// 1. Locks the index, so that no other command updates it in between.
+/// 2. Calls `add_core` to compute index updates.
+/// 3. Writes updated index back to disk, releasing the lock.
 /// 3. Outputs detailed change log if `verbose` is enabled.
-pub fn add(files: Vec<String>, verbose: bool) {
+///
+/// # Errors
+/// * `Error::Locked` if another process holds the index lock.
+/// * See `add_core`; also if the index cannot be written.
+pub fn add(repo: &Repository, files: Vec<String>, verbose: bool) -> Result<()> {
 
+    let lock = index::lock_index(repo)?;
     let (
         index,
         add_log,
         remove_log,
         modify_log
-    ) = add_core(&files);
+    ) = add_core(repo, &files)?;
 
-    index::write_index(&index);
+    index::write_locked_index(repo, lock, &index)?;
 
     if !verbose {
         for entry in &add_log {
@@ -151,7 +177,7 @@ pub fn add(files: Vec<String>, verbose: bool) {
             "Added {} file(s), Removed {} file(s), Modified {} file(s).", 
             add_log.len(), remove_log.len(), modify_log.len()
         );
-        if !(add_log.len() == 0 && remove_log.len() == 0 && modify_log.len() == 0) {
+        if !(add_log.is_empty() && remove_log.is_empty() && modify_log.is_empty()) {
             eprintln!();
             for entry in &add_log {
                 eprintln!("    \x1b[32mAdd:\x1b[0m    {} ({})", entry.path, entry.hash);
@@ -165,13 +191,15 @@ pub fn add(files: Vec<String>, verbose: bool) {
             eprintln!();
         }
     }
-    
+
+    Ok(())
 }
 
 
 /// Recursively registers files from the given paths into a set of index entries.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `files` - List of file/directory paths to register.
 ///
 /// # Returns
@@ -179,32 +207,29 @@ pub fn add(files: Vec<String>, verbose: bool) {
 /// 1. Set of unhashed index entries (to be hashed later)
 /// 2. Set of base file paths used for filtering removals
 ///
-/// # Exits
+/// # Errors
 /// * If any path cannot be canonicalized.
-/// * If any path is outside the repository root.
-fn register_entries(files: &Vec<String>) -> (HashSet<IndexEntry>, HashSet<String>) {
+/// * `Error::InvalidArgument` if any path is outside the repository root.
+fn register_entries(repo: &Repository, files: &Vec<String>) -> Result<(HashSet<IndexEntry>, HashSet<String>)> {
 
-    let repo_path = utils::pwd();
+    let repo_path = repo.path();
 
     let mut new_entries: HashSet<IndexEntry> = Default::default();
     let mut base_entries: HashSet<String> = Default::default();
     
     for file in files {
-        let file_path = match Path::new(&file).canonicalize() {
-            Ok(p) => p.to_string_lossy().into_owned(),
-            Err(e) => {
-                eprintln!("Error when canonicalizing path {}: {}", file, e);
-                process::exit(1)
-            }
-        };
-        if !utils::is_subpath(&repo_path, &file_path) {
-            eprintln!("File {} does not belong to repository {}.", file_path, repo_path);
-            process::exit(1);
+        let file_path = Path::new(&file)
+            .canonicalize()
+            .map_err(Error::io(format!("Error when canonicalizing path {}", file)))?
+            .to_string_lossy()
+            .into_owned();
+        if !utils::is_subpath(repo_path, &file_path) {
+            return Err(Error::InvalidArgument(format!("File {} does not belong to repository {}.", file_path, repo_path)));
         }
 
-        index::register_files(&file_path, &utils::relative_path(&repo_path, &file_path), &mut new_entries, &true);
+        index::register_files(repo, &file_path, &utils::get_relative_path(repo_path, &file_path)?, &mut new_entries, &true)?;
         base_entries.insert(file_path);
     }
 
-    (new_entries, base_entries)
+    Ok((new_entries, base_entries))
 }
\ No newline at end of file
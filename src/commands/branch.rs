@@ -7,68 +7,40 @@
 //!
 //! Ensures safety by preventing deletion of the current branch or non-ancestor branches.
 
-use std::process;
-
-use crate::{core::reference, utils::{storage, utils}};
+use crate::core::{reference, revision};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
 
 
 /// Manages Git branches: create, delete, or list branches based on input arguments.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `name` - Optional vector containing names for creating or operating on branches.
 /// * `delete` - If true, deletes the specified branches.
 /// * `verbose` - If true, displays beautified output instead of minimal status.
 ///
 /// # Behavior
 /// 1. **Create mode**: Creates a new branch pointing to the current commit.
-///    - Requires exactly one name in `name`.
-///    - Fails if in detached HEAD state.
+///    - Requires exactly one name in `name`, optionally followed by a start point revision
+///      (e.g. `HEAD~2`) to create the branch at instead.
+///    - Fails if in detached HEAD state and no start point is given.
 /// 2. **Delete mode**: Deletes the specified branches.
 ///    - Prevents deleting the current branch.
 ///    - Only allows deletion of branches that are direct ancestors of the current branch.
 /// 3. **List mode**: Lists all branches with an asterisk next to the current branch.
 ///
-/// # Exits
-/// - If trying to delete current branch.
-/// - If trying to delete non-ancestor branch.
-/// - If trying to create branch while in detached HEAD state.
-/// - If trying to create more than one branch at once.
-pub fn branch(name: Option<Vec<String>>, delete: bool, verbose: bool) {
+/// # Errors
+/// - `Error::InvalidState` if trying to delete the current branch or a non-ancestor branch.
+/// - `Error::InvalidState` if trying to create or delete a branch while in detached HEAD state
+///   without a start point.
+/// - `Error::InvalidRevision` if the start point does not name a commit.
+/// - `Error::InvalidArgument` if trying to create more than one branch at once.
+pub fn branch(repo: &Repository, name: Option<Vec<String>>, delete: bool, verbose: bool) -> Result<()> {
     match (name, delete) {
         (Some(branches), true) => {
             // Deleting branch(es)
-            let current_branch: String;
-
-            match &reference::get_current_branch() {
-                None => {
-                    eprintln!("You are in 'detached HEAD' state. Cannot delete branch.");
-                    process::exit(1);
-                }
-                Some(branch_name) => {
-                    current_branch = branch_name.to_string();
-                }
-            }
-            
-            let mut can_delete = true;
-
-            for branch in &branches {
-                if !reference::is_prev_branch(branch, &current_branch) {
-                    eprintln!("branch {} is not direct ancestor of current branch {}.", branch, current_branch);
-                    can_delete = false;
-                } else if branch == &current_branch {
-                    eprintln!("Cannot delete current branch {}.", current_branch);
-                    can_delete = false;
-                }
-            }
-
-            if !can_delete {
-                eprintln!("Did not remove any branch.");
-                process::exit(1);
-            }
-
-            for branch in &branches {
-                storage::remove_file(&format!("{}/refs/heads/{}", utils::get_git_directory(), branch));
-            }
+            delete_branches(repo, &branches)?;
 
             if verbose {
                 for branch in &branches {
@@ -78,26 +50,13 @@ pub fn branch(name: Option<Vec<String>>, delete: bool, verbose: bool) {
 
         }
         (Some(branch), false) => {
-            // Creating branch
-            if branch.len() != 1 {
-                eprintln!("Can only create 1 branch.");
-                process::exit(1);
-            }
-            let name = branch.get(0).unwrap();
-
-            let current_branch: String;
-
-            match &reference::get_current_branch() {
-                None => {
-                    eprintln!("You are in 'detached HEAD' state. Cannot create branch.");
-                    process::exit(1);
-                }
-                Some(branch_name) => {
-                    current_branch = branch_name.to_string();
-                }
+            // Creating branch, optionally at a start point: `branch <name> [<start-point>]`
+            if branch.is_empty() || branch.len() > 2 {
+                return Err(Error::InvalidArgument("Can only create 1 branch.".to_string()));
             }
+            let name = branch.first().unwrap();
 
-            reference::create_head(name, &reference::get_head(&current_branch));
+            create_branch(repo, name, branch.get(1).map(String::as_str))?;
             
             if verbose {
                 eprintln!("Created branch {}.", name);
@@ -105,8 +64,8 @@ pub fn branch(name: Option<Vec<String>>, delete: bool, verbose: bool) {
         }
         (None, _) => {
             // Listing branches
-            let heads = reference::get_all_heads();
-            let current_branch = reference::get_current_branch();
+            let heads = reference::get_all_heads(repo)?;
+            let current_branch = reference::get_current_branch(repo)?;
             for head in heads {
                 let is_current_branch = match &current_branch {
                     None => false,
@@ -116,4 +75,75 @@ pub fn branch(name: Option<Vec<String>>, delete: bool, verbose: bool) {
             }
         }
     }
+    Ok(())
+}
+
+
+/// Creates a branch at the current commit, or at a start point.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `name` - Name of the new branch.
+/// * `start_point` - Optional revision (e.g. `HEAD~2`) of the commit to create the branch at.
+///
+/// # Returns
+/// * `String` - Hash of the commit the branch points to (empty if the current branch is unborn).
+///
+/// # Errors
+/// - `Error::InvalidState` if in detached HEAD state and no start point is given, or the
+///   branch already exists.
+/// - `Error::InvalidArgument` if the name is not a valid branch name (see `git check-ref-format`).
+/// - `Error::InvalidRevision` if the start point does not name a commit.
+pub fn create_branch(repo: &Repository, name: &str, start_point: Option<&str>) -> Result<String> {
+    let start_commit = match start_point {
+        Some(start_point) => revision::resolve_commit(repo, start_point)?,
+        None => match &reference::get_current_branch(repo)? {
+            None => {
+                return Err(Error::InvalidState("You are in 'detached HEAD' state. Cannot create branch.".to_string()));
+            }
+            Some(current_branch) => reference::get_head(repo, current_branch)?,
+        },
+    };
+
+    reference::create_head(repo, name, &start_commit)?;
+    Ok(start_commit)
+}
+
+
+/// Deletes branches, all or none of them.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `branches` - Names of the branches to delete.
+///
+/// # Errors
+/// - `Error::InvalidState` if in detached HEAD state, or trying to delete the current branch or
+///   a branch that is not a direct ancestor of it; no branch is deleted then.
+pub fn delete_branches(repo: &Repository, branches: &[String]) -> Result<()> {
+    let current_branch = match reference::get_current_branch(repo)? {
+        None => {
+            return Err(Error::InvalidState("You are in 'detached HEAD' state. Cannot delete branch.".to_string()));
+        }
+        Some(branch_name) => branch_name,
+    };
+
+    let mut problems: Vec<String> = Vec::new();
+
+    for branch in branches {
+        if !reference::is_prev_branch(repo, branch, &current_branch)? {
+            problems.push(format!("branch {} is not direct ancestor of current branch {}.", branch, current_branch));
+        } else if branch == &current_branch {
+            problems.push(format!("Cannot delete current branch {}.", current_branch));
+        }
+    }
+
+    if !problems.is_empty() {
+        problems.push("Did not remove any branch.".to_string());
+        return Err(Error::InvalidState(problems.join("\n")));
+    }
+
+    for branch in branches {
+        reference::delete_head(repo, branch)?;
+    }
+    Ok(())
 }
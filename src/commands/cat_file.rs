@@ -0,0 +1,159 @@
+//! Module: cat_file
+//!
+//! Implements inspection of the object store, similar to `git cat-file`.
+//! Prints the type, the size, or a human-readable rendering of any object named by a revision.
+//!
+//! Unlike the other commands, the output goes to stdout, so that it can be piped into other tools.
+
+use std::io::{self, Write};
+
+use crate::core::*;
+use crate::commands::ls_tree;
+use crate::core::commit::{Commit, CommitTrait};
+use crate::core::object::ObjectType;
+use crate::core::tree::{Tree, TreeTrait};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+
+
+/// What `cat-file` prints about the object.
+pub enum CatFileMode {
+    /// The type of the object (`blob`, `tree` or `commit`).
+    Type,
+    /// The size of the object's payload, in bytes.
+    Size,
+    /// The content of the object, rendered for humans.
+    Pretty,
+}
+
+
+/// Writes information about an object into `writer` (see `cat_file`).
+///
+/// Only the header of the object is read to print its type or size, and blobs are streamed,
+/// so that large files never need to fit in memory.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `mode` - What to print: the type, the size, or the content.
+/// * `object` - Revision naming the object.
+/// * `writer` - Destination of the output.
+///
+/// # Errors
+/// * If the revision does not name an existing object, or `writer` fails.
+pub fn cat_file_core(repo: &Repository, mode: CatFileMode, object: &str, writer: &mut impl Write) -> Result<()> {
+    let hash = revision::resolve_revision(repo, object)?;
+    let (object_type, size) = object::read_object_info(repo, &hash)?;
+
+    let write_error = || Error::io(format!("Failed to write object {}", hash));
+    match mode {
+        CatFileMode::Type => writeln!(writer, "{}", object_type.git_name()).map_err(write_error()),
+        CatFileMode::Size => writeln!(writer, "{}", size).map_err(write_error()),
+        CatFileMode::Pretty => pretty_print(repo, &hash, object_type, writer),
+    }
+}
+
+
+/// Prints information about an object.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `mode` - What to print: the type, the size, or the content.
+/// * `object` - Revision naming the object, e.g. `65df41d`, `HEAD~1`, `HEAD:src/main.rs`.
+///
+/// # Output Format
+/// - Blob: the raw content.
+/// - Tree: one `<mode> <type> <hash>\t<name>` line per entry, as Git prints them.
+/// - Commit: `tree`, `parent`, `author` and `date` lines, a blank line and the message.
+///
+/// # Errors
+/// * See `cat_file_core`; also if stdout cannot be written to.
+pub fn cat_file(repo: &Repository, mode: CatFileMode, object: String) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    cat_file_core(repo, mode, &object, &mut stdout)?;
+    stdout.flush().map_err(Error::io("Failed to write to stdout".to_string()))
+}
+
+
+/// Renders an object for humans (see `cat_file` for the format of each type).
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `hash` - Hash of the object.
+/// * `object_type` - Type of the object.
+/// * `writer` - Destination of the rendering.
+fn pretty_print(repo: &Repository, hash: &str, object_type: ObjectType, writer: &mut impl Write) -> Result<()> {
+    let output = match object_type {
+        ObjectType::Blob => return object::write_object_payload_to(repo, hash, ObjectType::Blob, writer),
+        ObjectType::Tree => {
+            let mut tree = Tree { hash: Some(hash.to_string()), data: None };
+            tree.read_tree(repo)?;
+
+            let mut res = String::new();
+            for entry in tree.data.unwrap() {
+                res.push_str(&ls_tree::format_tree_entry(&entry, &entry.name));
+                res.push('\n');
+            }
+            res.into_bytes()
+        }
+        ObjectType::Commit => {
+            let mut commit = Commit { hash: Some(hash.to_string()), data: None };
+            commit.read_commit(repo)?;
+            let commit_data = commit.data.unwrap();
+
+            let mut res = format!("tree {}\n", commit_data.tree_hash);
+            for parent in commit_data.parent_commits.iter().filter(|parent| !parent.is_empty()) {
+                res.push_str(&format!("parent {}\n", parent));
+            }
+            res.push_str(&format!("author {}\n", commit_data.user));
+            res.push_str(&format!("date {}\n", commit_data.time));
+            res.push('\n');
+
+            let mut res = res.into_bytes();
+            res.extend_from_slice(&commit_data.message);
+            if !commit_data.message.ends_with(b"\n") {
+                res.push(b'\n');
+            }
+            res
+        }
+    };
+    writer.write_all(&output).map_err(Error::io(format!("Failed to write object {}", hash)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::RepositoryFormat;
+    use crate::utils::hash::HashAlgorithm;
+
+    fn cat(repo: &Repository, mode: CatFileMode, object: &str) -> String {
+        let mut output = Vec::new();
+        cat_file_core(repo, mode, object, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn objects_are_printed_loose_and_packed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        std::fs::write(dir.path().join("file"), b"hello\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        let commit_hash = repo.commit("first").unwrap();
+
+        for packed in [false, true] {
+            if packed {
+                crate::commands::repack::repack(&repo, None, false).unwrap();
+            }
+            assert_eq!(cat(&repo, CatFileMode::Type, "HEAD:file"), "blob\n");
+            assert_eq!(cat(&repo, CatFileMode::Size, "HEAD:file"), "6\n");
+            assert_eq!(cat(&repo, CatFileMode::Pretty, "HEAD:file"), "hello\n");
+            // the hash of "blob 6\0hello\n", as Git names it
+            assert_eq!(
+                cat(&repo, CatFileMode::Pretty, "HEAD:"),
+                "100644 blob ce013625030ba8dba906f756967f9e9ca394464a\tfile\n"
+            );
+            assert_eq!(cat(&repo, CatFileMode::Type, &commit_hash), "commit\n");
+            assert!(cat(&repo, CatFileMode::Pretty, "HEAD").ends_with("\nfirst\n"));
+        }
+    }
+}
@@ -3,59 +3,79 @@
 //! Implements the checkout functionality for switching between branches or commits.
 //! Supports both branch checkout and detached HEAD state, with optional force flag to override uncommitted changes.
 
-use std::{collections::HashMap, process};
+use std::collections::HashMap;
 
 use super::branch;
-use crate::{core::{commit::{Commit, CommitTrait}, index::IndexEntry, *}, utils::*};
+use crate::{core::{commit::{Commit, CommitTrait}, index::IndexEntry, reference::Head, *}, utils::*};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
 
 
 /// Switches to the specified branch or commit.
 ///
 /// # Arguments
-/// * `target` - Name of the branch or SHA-1 hash of the commit to switch to.
+/// * `repo` - The repository to operate on.
+/// * `target` - Name of the branch, or revision (e.g. `HEAD~2`, `65df41d`) of the commit to switch to.
 /// * `force` - If true, allows checkout even if there are uncommitted changes.
 /// * `branch` - If true, creates a new branch with the given target name.
 /// * `verbose` - If true, displays beautified output instead of minimal status.
 ///
 /// # Behavior
 /// 1. Optionally creates a new branch if the `branch` flag is set.
-/// 2. Checks whether the target is a valid branch or commit.
-/// 3. Clears the working directory and restores it from the target commit.
-/// 4. Updates `.git/HEAD` to reflect the current branch or commit.
+/// 2. Switches to the target (see `checkout_core`).
 ///
-/// # Exits
-/// * If there are uncommitted changes and `force` is false.
-/// * If the target is neither a valid branch nor a valid commit.
-pub fn checkout(target: String, force: bool, branch: bool, verbose: bool) {
+/// # Errors
+/// * `Error::DirtyWorktree` if there are uncommitted changes and `force` is false.
+/// * `Error::InvalidRevision` if the target is neither a valid branch nor a revision naming a commit.
+pub fn checkout(repo: &Repository, target: String, force: bool, branch: bool, verbose: bool) -> Result<()> {
 
     if branch {
-        branch::branch(Some([target.clone()].to_vec()), false, verbose);
+        branch::branch(repo, Some([target.clone()].to_vec()), false, verbose)?;
     }
 
-    match reference::try_get_head(&target) {
+    let head = checkout_core(repo, &target, force)?;
+    if verbose {
+        match head {
+            Head::Branch(branch) => eprintln!("Now on head {}.", branch),
+            Head::Detached(commit_hash) => eprintln!("Now in 'detached HEAD' state on {}.", commit_hash),
+        }
+    }
+    Ok(())
+}
+
+
+/// Core logic for switching to a branch or commit.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `target` - Name of the branch, or revision (e.g. `HEAD~2`, `65df41d`) of the commit to switch to.
+/// * `force` - If true, allows checkout even if there are uncommitted changes.
+///
+/// # Returns
+/// * `Head` - What HEAD points to after the checkout.
+///
+/// # Behavior
+/// 1. Checks whether the target is a valid branch or commit.
+/// 2. Clears the working directory and restores it from the target commit.
+/// 3. Updates `.git/HEAD` to reflect the current branch or commit.
+///
+/// # Errors
+/// * `Error::DirtyWorktree` if there are uncommitted changes and `force` is false.
+/// * `Error::InvalidRevision` if the target is neither a valid branch nor a revision naming a commit.
+pub fn checkout_core(repo: &Repository, target: &str, force: bool) -> Result<Head> {
+    match reference::get_head(repo, target) {
         Ok(head_hash) => {
             // target is a head, e.g. target == master
-            checkout_to_commit(&head_hash, force);
-            reference::store_current_branch_ref(&target);
-            if verbose {
-                eprintln!("Now on head {}.", target);
-            }
+            checkout_to_commit(repo, &head_hash, force)?;
+            reference::store_current_branch_ref(repo, target)?;
+            Ok(Head::Branch(target.to_string()))
         }
         Err(_) => {
-            // target is not a head
-            match object::get_object_type(&target) {
-                object::ObjectType::Commit => {
-                    // target is a commit, e.g. target == s65df41d6sf...
-                    checkout_to_commit(&target, force);
-                    reference::store_current_branch_commit(&target);
-                    if verbose {
-                        eprintln!("Now in 'detached HEAD' state on {}.", target);
-                    }
-                }
-                _ => {
-                    eprintln!("Unrecognized checkout target {}.", target);
-                }
-            }
+            // target is not a head, but a revision, e.g. target == 65df41d or HEAD~2
+            let commit_hash = revision::resolve_commit(repo, target)?;
+            checkout_to_commit(repo, &commit_hash, force)?;
+            reference::store_current_branch_commit(repo, &commit_hash)?;
+            Ok(Head::Detached(commit_hash))
         }
     }
 }
@@ -64,29 +84,35 @@ pub fn checkout(target: String, force: bool, branch: bool, verbose: bool) {
 /// Restores the working area and index from the specified commit.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `target_commit_hash` - SHA-1 hash of the commit to restore.
 /// * `force` - If true, ignores uncommitted changes in the working area.
 ///
-/// # Exits
-/// * If there are uncommitted changes and `force` is false.
-fn checkout_to_commit(target_commit_hash: &str, force: bool) {
-    if !force && commit::check_has_uncommitted() {
-        eprintln!("Detected uncommited files. Cannot checkout.");
-        eprintln!("Use `git checkout --force/-f` to force checkout.");
-        process::exit(1);
+/// # Errors
+/// * `Error::Locked` if another process holds the index lock.
+/// * `Error::DirtyWorktree` if there are uncommitted changes and `force` is false.
+fn checkout_to_commit(repo: &Repository, target_commit_hash: &str, force: bool) -> Result<()> {
+    let lock = index::lock_index(repo)?;
+    let mut index = index::read_index(repo)?;
+    if !force && commit::check_has_uncommitted(repo, &mut index)? {
+        index::write_locked_index(repo, lock, &index)?;
+        return Err(Error::DirtyWorktree(
+            "Detected uncommited files. Cannot checkout.\nUse `git checkout --force/-f` to force checkout.".to_string()
+        ));
     }
-    storage::clear_working_area();
-    if target_commit_hash != "" {
-        storage::restore_working_area(target_commit_hash);
+    storage::clear_working_area(repo)?;
+    if !target_commit_hash.is_empty() {
+        storage::restore_working_area(repo, target_commit_hash)?;
     }
 
     let mut commit = Commit {
         hash: Some(target_commit_hash.to_string()),
         data: None
     };
-    commit.read_commit();
+    commit.read_commit(repo)?;
 
     let mut index_entries: HashMap<String, IndexEntry> = Default::default();
-    storage::restore_index_by_tree(&commit.data.unwrap().tree_hash, &utils::pwd(), &mut index_entries);
-    index::write_index(&index_entries);
+    storage::restore_index_by_tree(repo, &commit.data.unwrap().tree_hash, repo.path(), &mut index_entries)?;
+    index::record_stat(repo, &mut index_entries);
+    index::write_locked_index(repo, lock, &index_entries)
 }
@@ -5,17 +5,19 @@
 //! and performing both regular and merge commits.
 
 use std::collections::{HashMap, HashSet};
-use std::process;
 
 use crate::core::index::IndexEntry;
 use crate::{core::*, utils::*};
 use crate::core::tree::{Tree, TreeEntry, TreeEntryType, TreeTrait};
 use crate::core::commit::{Commit, CommitData, CommitTrait};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
 
 
 /// Creates a new commit object with the given entries, message, and parent commits.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `entries` - Set of index entries to include in the commit.
 /// * `message` - Commit message.
 /// * `time` - Timestamp of the commit.
@@ -24,32 +26,30 @@ use crate::core::commit::{Commit, CommitData, CommitTrait};
 ///
 /// # Returns
 /// * `String` - The SHA-1 hash of the newly created commit.
-pub fn commit(  entries: &HashSet<IndexEntry>,
+///
+/// # Errors
+/// * If a tree or the commit cannot be written.
+pub fn commit(  repo: &Repository,
+                entries: &HashSet<IndexEntry>,
                 message: String, time: String,
                 user: String,
-                parent_commits: Vec<String>) -> String {
-
-    let mut trees: HashMap<String, Tree> = Default::default();
-    trees.insert("".to_string(), Tree { hash: None, data: Some(Default::default())});
-
-    for entry in entries {
-        add_entry_to_tree(entry, &mut trees, None);
-    }
+                parent_commits: Vec<String>) -> Result<String> {
 
-    let tree_hash = hash_then_write_tree("", &mut trees);
+    let tree_hash = write_tree(repo, entries, None)?;
 
-    let commit_data = CommitData { message, user, time, tree_hash, parent_commits };
+    let commit_data = CommitData { message: message.into_bytes(), user, time, tree_hash, parent_commits };
     let mut commit = Commit { hash: None, data: Some(commit_data) };
 
-    commit.write_commit();
+    commit.write_commit(repo)?;
 
-    commit.hash.unwrap()
+    Ok(commit.hash.unwrap())
 }
 
 
 /// Creates a new merge commit using a precomputed blob table to preserve file types.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `entries` - Set of index entries to include in the commit.
 /// * `message` - Commit message.
 /// * `time` - Timestamp of the commit.
@@ -59,78 +59,122 @@ pub fn commit(  entries: &HashSet<IndexEntry>,
 ///
 /// # Returns
 /// * `String` - The SHA-1 hash of the newly created merge commit.
-pub fn commit_merge( 
+///
+/// # Errors
+/// * If a tree or the commit cannot be written.
+pub fn commit_merge(
+    repo: &Repository,
     entries: &HashSet<IndexEntry>,
     message: String, time: String,
     user: String,
     parent_commits: Vec<String>,
     new_blob_table: HashMap<String, TreeEntry>
-) -> String {
+) -> Result<String> {
+    let tree_hash = write_tree(repo, entries, Some(&new_blob_table))?;
+
+    let commit_data = CommitData { message: message.into_bytes(), user, time, tree_hash, parent_commits };
+    let mut commit = Commit { hash: None, data: Some(commit_data) };
+
+    commit.write_commit(repo)?;
+
+    Ok(commit.hash.unwrap())
+}
+
+
+/// Builds and writes the tree hierarchy holding the given entries, without creating a commit.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `entries` - Set of index entries to include in the tree.
+/// * `tree_table` - Optional map of file paths to tree entries used to preserve file types;
+///   if omitted, file types are taken from the entries.
+///
+/// # Returns
+/// * `String` - The SHA-1 hash of the root tree.
+///
+/// # Errors
+/// * If a tree cannot be written.
+pub fn write_tree(repo: &Repository, entries: &HashSet<IndexEntry>, tree_table: Option<&HashMap<String, TreeEntry>>) -> Result<String> {
     let mut trees: HashMap<String, Tree> = Default::default();
     trees.insert("".to_string(), Tree { hash: None, data: Some(Default::default())});
 
     for entry in entries {
-        add_entry_to_tree(entry, &mut trees, Some(&new_blob_table));
+        add_entry_to_tree(repo, entry, &mut trees, tree_table)?;
     }
 
-    let tree_hash = hash_then_write_tree("", &mut trees);
+    hash_then_write_tree(repo, "", &mut trees)
+}
 
-    let commit_data = CommitData { message, user, time, tree_hash, parent_commits };
-    let mut commit = Commit { hash: None, data: Some(commit_data) };
 
-    commit.write_commit();
+/// Commits the current index with a provided message.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `message` - Commit message.
+/// * `verbose` - If true, displays beautified output instead of just the commit hash.
+///
+/// # Errors
+/// * See `commit_index_core`.
+pub fn commit_index(repo: &Repository, message: String, verbose: bool) -> Result<()> {
+
+    let (current_branch, new_head_hash) = commit_index_core(repo, message)?;
 
-    commit.hash.unwrap()
+    if !verbose {
+        eprintln!("{}", new_head_hash);
+    } else {
+        eprintln!("Committed changes {} to head {}.", new_head_hash, current_branch);
+    }
+    Ok(())
 }
 
 
-/// Commits the current index with a provided message.
+/// Core logic for committing the current index.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `message` - Commit message.
-/// * `verbose` - If true, displays beautified output instead of just the commit hash.
+///
+/// # Returns
+/// * `(String, String)` - The name of the current branch and the hash of the new commit.
 ///
 /// # Behavior
 /// 1. Reads the current index.
 /// 2. Builds the corresponding tree structure.
 /// 3. Creates a new commit object.
-/// 4. Updates the current branch reference to point to the new commit.
+/// 4. Advances the current branch to the new commit, provided it still points to the parent
+///    read in step 3 (the branch must still be unborn if it was).
 ///
-/// # Exits
-/// * If the repository is in detached HEAD state.
-pub fn commit_index(message: String, verbose: bool) {
+/// # Errors
+/// * `Error::InvalidState` if the repository is in detached HEAD state.
+/// * `Error::StaleRef` if another process moved the branch meanwhile; the new commit is then
+///   left unreferenced.
+pub fn commit_index_core(repo: &Repository, message: String) -> Result<(String, String)> {
     
-    let index = index::read_index();
+    let index = index::read_index(repo)?;
     let mut entries: HashSet<IndexEntry> = Default::default();
     for kv in index {
         entries.insert(kv.1);
     }
 
-    let current_branch: String;
-
-    match &reference::get_current_branch() {
+    let current_branch = match reference::get_current_branch(repo)? {
         None => {
-            eprintln!("You are in 'detached HEAD' state. Cannot commit.");
-            process::exit(1);
+            return Err(Error::InvalidState("You are in 'detached HEAD' state. Cannot commit.".to_string()));
         }
-        Some(branch_name) => {
-            current_branch = branch_name.to_string();
-        }
-    }
+        Some(branch_name) => branch_name,
+    };
 
-    let parent_commits: Vec<String> = [ reference::get_head(&current_branch) ].to_vec();
+    let parent_hash = reference::get_head(repo, &current_branch)?;
+    let parent_commits: Vec<String> = [ parent_hash.clone() ].to_vec();
 
-    let new_head_hash = commit(&entries, message, utils::get_time_string(), utils::get_username(), parent_commits);
+    let new_head_hash = commit(repo, &entries, message, utils::get_time_string(), utils::get_username(), parent_commits)?;
 
-    reference::store_head(&current_branch, &new_head_hash);
+    // compare-and-swap against the parent, so that a concurrent commit is not overwritten
+    let ref_name = format!("refs/heads/{}", current_branch);
+    reference::update_ref(repo, &ref_name, &new_head_hash, Some(&parent_hash))?;
 
     // storage::clear_index();
 
-    if !verbose {
-        eprintln!("{}", new_head_hash);
-    } else {
-        eprintln!("Committed changes {} to head {}.", new_head_hash, current_branch);
-    }
+    Ok((current_branch, new_head_hash))
 }
 
 
@@ -141,7 +185,7 @@ pub fn commit_index(message: String, verbose: bool) {
 /// * `father_path` - Path of the parent tree.
 /// * `trees` - Mutable map of path → tree used to build the hierarchy.
 fn create_tree_for_path(path: &str, father_path: &str, trees: &mut HashMap<String, Tree>) {
-    if path == "" {
+    if path.is_empty() {
         return ;
     }
     let (cur_name, after_path) = utils::split_path_by_first(path);
@@ -186,14 +230,16 @@ fn create_tree_for_path(path: &str, father_path: &str, trees: &mut HashMap<Strin
 /// Adds an index entry (file) into the appropriate tree based on its path.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `entry` - Index entry to add.
 /// * `trees` - Mutable map of path → tree used to build the hierarchy.
 /// * `tree_table` - Optional map of file paths to tree entries used to preserve file types.
 fn add_entry_to_tree(
+    repo: &Repository,
     entry: &IndexEntry,
     trees: &mut HashMap<String, Tree>,
     tree_table: Option<&HashMap<String, TreeEntry>>
-) {
+) -> Result<()> {
     let file_path = entry.path.clone();
     let (dir_path, file_name) = utils::split_path_by_last(&file_path);
     // e.g. A/B/C/D/E/1.txt
@@ -204,13 +250,15 @@ fn add_entry_to_tree(
     create_tree_for_path(&dir_path, "", trees);
 
     if let Some(folder_tree) = trees.get_mut(&dir_path) {
-        let blob_path = format!("{}/{}", utils::pwd(), file_path);
+        let blob_path = format!("{}/{}", repo.path(), file_path);
         let entry_type = match tree_table {
             None => {
-                blob::get_blob_type(&blob_path)
+                entry.entry_type.clone()
             }
             Some(tree_table) => {
-                tree_table.get(&blob_path).unwrap().entry_type.clone()
+                tree_table.get(&blob_path)
+                    .ok_or_else(|| Error::InvalidState(format!("commit::add_entry_to_tree: no type is known for '{}'.", blob_path)))?
+                    .entry_type.clone()
             }
         };
         if let Some(vec) = folder_tree.data.as_mut() {
@@ -227,25 +275,27 @@ fn add_entry_to_tree(
             }]);
         }
     } else {
-        panic!("commit::add_entry_to_tree: Cannot get dir_path.");
+        return Err(Error::InvalidState(format!("commit::add_entry_to_tree: no tree was created for '{}'.", dir_path)));
     }
+    Ok(())
 }
 
 
 /// Recursively hashes and writes all trees starting from the given path.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `path` - Root path of the tree to hash and write.
 /// * `trees` - Mutable map of path → tree containing the full tree structure.
 ///
 /// # Returns
 /// * `String` - The SHA-1 hash of the tree at the specified path.
-fn hash_then_write_tree(path: &str, trees: &mut HashMap<String, Tree>) -> String {
+fn hash_then_write_tree(repo: &Repository, path: &str, trees: &mut HashMap<String, Tree>) -> Result<String> {
 
     // collect all sub_trees to be hashed and written
 
     let tree = trees.get_mut(path)
-        .unwrap_or_else(|| panic!("commit::hash_then_write_tree: Cannot find path {}.", path));
+        .ok_or_else(|| Error::InvalidState(format!("commit::hash_then_write_tree: no tree was created for '{}'.", path)))?;
     let data = tree.data.as_mut().unwrap();
     let mut sub_trees = Vec::new();
 
@@ -264,7 +314,7 @@ fn hash_then_write_tree(path: &str, trees: &mut HashMap<String, Tree>) -> String
     let mut hash_result: HashMap<String, String> = HashMap::new(); // next_path -> hash
 
     for next_path in sub_trees {
-        hash_result.insert(next_path.clone(), hash_then_write_tree(&next_path, trees));
+        hash_result.insert(next_path.clone(), hash_then_write_tree(repo, &next_path, trees)?);
     }
 
     // write back hash result to current_tree.data
@@ -285,7 +335,7 @@ fn hash_then_write_tree(path: &str, trees: &mut HashMap<String, Tree>) -> String
     // hash current_tree
 
     let tree = trees.get_mut(path).unwrap();
-    tree.write_tree();
+    tree.write_tree(repo)?;
 
-    tree.hash.clone().unwrap()
+    Ok(tree.hash.clone().unwrap())
 }
@@ -0,0 +1,123 @@
+//! Module: commit_tree
+//!
+//! Implements creating a commit object from an existing tree, similar to `git commit-tree`.
+//! Neither the index, HEAD nor any branch is touched; the hash of the new commit is printed
+//! to stdout, to be recorded with `update-ref`.
+
+use crate::{core::*, utils::*};
+use crate::core::commit::{Commit, CommitData, CommitTrait};
+use crate::core::object::ObjectType;
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+
+
+/// Creates a commit object.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `tree` - Revision naming the tree of the commit, or a commit whose tree to reuse.
+/// * `parents` - Revisions of the parent commits, in order; none for a root commit.
+/// * `message` - Commit message.
+///
+/// # Returns
+/// * `String` - Hash of the new commit.
+///
+/// # Errors
+/// * `Error::InvalidRevision` if `tree` does not name a tree or a commit, or a parent does not
+///   name a commit.
+pub fn commit_tree_core(repo: &Repository, tree: &str, parents: &[String], message: String) -> Result<String> {
+    let hash = revision::resolve_revision(repo, tree)?;
+    let tree_hash = match object::get_object_type(repo, &hash)? {
+        ObjectType::Tree => hash,
+        ObjectType::Commit => {
+            let mut commit = Commit { hash: Some(hash), data: None };
+            commit.read_commit(repo)?;
+            commit.data.unwrap().tree_hash
+        }
+        ObjectType::Blob => {
+            return Err(Error::InvalidRevision(format!("{} is not a tree.", tree)));
+        }
+    };
+
+    let mut parent_commits: Vec<String> = parents.iter()
+        .map(|parent| revision::resolve_commit(repo, parent))
+        .collect::<Result<_>>()?;
+    if parent_commits.is_empty() {
+        // root commits are recorded with an empty parent, as `commit` does
+        parent_commits.push(String::new());
+    }
+
+    let commit_data = CommitData {
+        message: message.into_bytes(),
+        user: utils::get_username(),
+        time: utils::get_time_string(),
+        tree_hash,
+        parent_commits,
+    };
+    let mut commit = Commit { hash: None, data: Some(commit_data) };
+    commit.write_commit(repo)?;
+    Ok(commit.hash.unwrap())
+}
+
+
+/// Creates a commit object and prints its hash.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `tree` - Revision naming the tree of the commit, or a commit whose tree to reuse.
+/// * `parents` - Revisions of the parent commits, in order; none for a root commit.
+/// * `message` - Commit message.
+///
+/// # Errors
+/// * See `commit_tree_core`.
+pub fn commit_tree(repo: &Repository, tree: String, parents: Vec<String>, message: String) -> Result<()> {
+    println!("{}", commit_tree_core(repo, &tree, &parents, message)?);
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::commands::update_ref::update_ref;
+    use crate::core::config::RepositoryFormat;
+    use crate::utils::hash::HashAlgorithm;
+
+    #[test]
+    fn commits_are_built_and_recorded_by_plumbing() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        fs::write(dir.path().join("file"), b"content\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        let base = repo.commit("base").unwrap();
+        let tree = repo.resolve("HEAD:").unwrap();
+
+        // commit-tree touches no ref
+        let root = commit_tree_core(&repo, &tree, &[], "root".to_string()).unwrap();
+        let child = commit_tree_core(&repo, "HEAD", &[root.clone(), "HEAD".to_string()], "child".to_string()).unwrap();
+        assert_eq!(repo.resolve("HEAD").unwrap(), base);
+        assert_eq!(repo.resolve(&format!("{}:", child)).unwrap(), tree);
+        assert_eq!(repo.resolve(&format!("{}^1", child)).unwrap(), root);
+        assert_eq!(repo.resolve(&format!("{}^2", child)).unwrap(), base);
+        assert!(matches!(repo.resolve(&format!("{}^1", root)), Err(Error::InvalidRevision(_))));
+        assert!(matches!(commit_tree_core(&repo, "HEAD:file", &[], String::new()), Err(Error::InvalidRevision(_))));
+        assert!(matches!(commit_tree_core(&repo, &tree, std::slice::from_ref(&tree), String::new()), Err(Error::InvalidRevision(_))));
+
+        // update-ref moves a ref only from the expected value
+        update_ref(&repo, "refs/heads/plumbing".to_string(), root.clone(), Some("0".repeat(40)), false).unwrap();
+        assert!(matches!(
+            update_ref(&repo, "refs/heads/plumbing".to_string(), child.clone(), Some(base.clone()), false),
+            Err(Error::StaleRef(_))
+        ));
+        update_ref(&repo, "refs/heads/plumbing".to_string(), child.clone(), Some(root.clone()), false).unwrap();
+        assert_eq!(repo.resolve("plumbing").unwrap(), child);
+
+        // branches and HEAD only point to commits, other refs to anything
+        assert!(matches!(update_ref(&repo, "HEAD".to_string(), tree.clone(), None, false), Err(Error::InvalidRevision(_))));
+        update_ref(&repo, "refs/tags/tree".to_string(), tree.clone(), None, false).unwrap();
+        update_ref(&repo, "HEAD".to_string(), "plumbing".to_string(), Some(base), false).unwrap();
+        assert_eq!(repo.resolve("master").unwrap(), child);
+    }
+}
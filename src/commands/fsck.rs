@@ -0,0 +1,441 @@
+//! Module: fsck
+//!
+//! Implements verification of the repository integrity, similar to `git fsck`.
+//! Every loose and packed object is decoded and rehashed, the links between objects are
+//! followed, and every ref, HEAD and the index are checked to point to existing objects
+//! of the right type.
+//!
+//! Problems are reported as:
+//! - `corrupt`: the object cannot be decoded, or does not hash to its name, or is a tree with
+//!   duplicate entries, or a Git tree with non-canonically ordered entries,
+//! - `missing`: an object referenced by another object, a ref or the index does not exist,
+//! - `mistyped`: a referenced object exists but has another type than expected,
+//! - `dangling`: an object referenced by nothing; reported for information only, as Git does.
+//!
+//! Native trees written before entries were sorted are valid, so their ordering is only
+//! reported as a warning.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{core::*, utils::*};
+use crate::core::commit::CommitData;
+use crate::core::config::RepositoryFormat;
+use crate::core::object::ObjectType;
+use crate::core::odb::ObjectDatabase;
+use crate::core::tree::{TreeEntry, TreeEntryType};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+
+
+/// State accumulated while checking a repository.
+#[derive(Default)]
+struct Fsck {
+    /// Every object present in the store, whether it could be decoded or not.
+    present: HashSet<String>,
+    /// Type of every object whose header could be decoded.
+    types: HashMap<String, ObjectType>,
+    /// Entries of every tree that could be parsed.
+    trees: HashMap<String, Vec<TreeEntry>>,
+    /// Metadata of every commit that could be parsed.
+    commits: HashMap<String, CommitData>,
+    /// Objects referenced by another object, a ref or the index.
+    referenced: HashSet<String>,
+    /// Number of problems found (dangling objects excluded).
+    errors: usize,
+    /// Number of warnings, which do not fail the check.
+    warnings: usize,
+    /// Length of the object names of the repository.
+    hash_length: usize,
+}
+
+impl Fsck {
+    /// Reports a problem.
+    fn error(&mut self, message: String) {
+        eprintln!("error: {}", message);
+        self.errors += 1;
+    }
+
+    /// Reports a harmless irregularity.
+    fn warning(&mut self, message: String) {
+        eprintln!("warning: {}", message);
+        self.warnings += 1;
+    }
+
+    /// Decodes an object, checks that it hashes to its name and records its content.
+    ///
+    /// Blobs are hashed as they are inflated, so that large files never need to fit in memory.
+    ///
+    /// # Arguments
+    /// * `repo` - The repository being checked.
+    /// * `name` - Hash of the object.
+    fn check_object(&mut self, repo: &Repository, name: &str) {
+        self.present.insert(name.to_string());
+
+        let (object_type, length) = match object::read_object_info(repo, name) {
+            Ok(info) => info,
+            Err(e) => return self.error(e.to_string()),
+        };
+        self.types.insert(name.to_string(), object_type);
+
+        let actual_hash = match object_type {
+            ObjectType::Blob => match hash_blob(repo, name, length) {
+                Ok(hash) => hash,
+                Err(e) => return self.error(e.to_string()),
+            },
+            _ => match self.parse_object(&repo.odb(), name, object_type) {
+                Some(hash) => hash,
+                None => return,
+            },
+        };
+
+        // native commits written before format version 2 are named without their parents
+        let legacy_hash = match (repo.config().format, self.commits.get(name)) {
+            (RepositoryFormat::Native, Some(data)) => Some(commit::legacy_native_hash(repo.hash_algorithm(), data)),
+            _ => None,
+        };
+        if actual_hash != name && legacy_hash.as_deref() != Some(name) {
+            self.error(format!("corrupt {} {}: content hashes to {}", object_type.git_name(), name, actual_hash));
+        }
+    }
+
+    /// Parses a tree or a commit, records its content and returns the hash of what is stored.
+    ///
+    /// # Arguments
+    /// * `db` - The object store of the repository.
+    /// * `name` - Hash of the object.
+    /// * `object_type` - Type of the object, as read from its header.
+    ///
+    /// # Returns
+    /// * The hash of the stored object, or `None` if it could not be decoded, which is reported.
+    fn parse_object(&mut self, db: &dyn ObjectDatabase, name: &str, object_type: ObjectType) -> Option<String> {
+        let full_content = match db.get(name) {
+            Ok(Some(content)) => content,
+            Ok(None) => { self.error(format!("corrupt object {}: cannot be read", name)); return None; }
+            Err(e) => { self.error(e.to_string()); return None; }
+        };
+        let payload = match object::decode_object_as(db.format(), name, &full_content) {
+            Ok((_, payload)) => payload,
+            Err(e) => { self.error(e.to_string()); return None; }
+        };
+
+        let raw_hash_length = db.hash_algorithm().raw_length();
+        let parsed = match object_type {
+            ObjectType::Blob => Ok(()),
+            ObjectType::Tree => tree::parse_tree_payload(db.format(), raw_hash_length, payload)
+                .map(|entries| {
+                    // the parser rejects duplicates, but accepts native trees in any order
+                    if let Err(e) = tree::check_entry_order(&entries) {
+                        self.warning(format!("tree {}: {}", name, e));
+                    }
+                    self.trees.insert(name.to_string(), entries);
+                }),
+            ObjectType::Commit => commit::parse_commit_payload(db.format(), payload)
+                .map(|data| { self.commits.insert(name.to_string(), data); }),
+        };
+        if let Err(e) = parsed {
+            self.error(format!("corrupt {} {}: {}", object_type.git_name(), name, e));
+            return None;
+        }
+
+        // native objects are named after their payload, Git objects after their full content
+        let algorithm = db.hash_algorithm();
+        Some(match db.format() {
+            RepositoryFormat::Native => algorithm.digest(payload),
+            RepositoryFormat::Git => algorithm.digest(&full_content),
+        })
+    }
+
+    /// Checks that `hash` names an existing object of type `expected`, and marks it referenced.
+    ///
+    /// # Arguments
+    /// * `hash` - The referenced object.
+    /// * `expected` - The type the referrer expects.
+    /// * `referrer` - Description of where the reference comes from, for messages.
+    fn check_link(&mut self, hash: &str, expected: ObjectType, referrer: &str) {
+        if !is_object_name(hash, self.hash_length) {
+            return self.error(format!("{}: invalid object name '{}'", referrer, hash));
+        }
+        self.referenced.insert(hash.to_string());
+
+        if !self.present.contains(hash) {
+            self.error(format!("missing {} {} (referenced by {})", expected.git_name(), hash, referrer));
+        } else if let Some(&actual) = self.types.get(hash) {
+            if actual != expected {
+                self.error(format!(
+                    "mistyped object {}: {} expects a {}, found a {}",
+                    hash, referrer, expected.git_name(), actual.git_name()
+                ));
+            }
+        }
+    }
+
+    /// Follows the links from every parsed tree and commit.
+    fn check_connectivity(&mut self) {
+        let trees = std::mem::take(&mut self.trees);
+        for (tree_hash, entries) in &trees {
+            for entry in entries {
+                let expected = match entry.entry_type {
+                    TreeEntryType::Tree => ObjectType::Tree,
+                    _ => ObjectType::Blob,
+                };
+                self.check_link(&entry.hash, expected, &format!("tree {} entry '{}'", tree_hash, entry.name));
+            }
+        }
+
+        let commits = std::mem::take(&mut self.commits);
+        for (commit_hash, data) in &commits {
+            self.check_link(&data.tree_hash, ObjectType::Tree, &format!("commit {}", commit_hash));
+            for parent in data.parent_commits.iter().filter(|parent| !parent.is_empty()) {
+                self.check_link(parent, ObjectType::Commit, &format!("commit {} parent", commit_hash));
+            }
+        }
+    }
+
+    /// Checks that every ref under `refs/` points to an existing object, branches and HEAD to commits.
+    ///
+    /// # Arguments
+    /// * `repo` - The repository being checked.
+    ///
+    /// # Errors
+    /// * None so far: unreadable refs are reported as problems.
+    fn check_refs(&mut self, repo: &Repository) -> Result<()> {
+        match reference::get_all_refs(repo) {
+            Ok(refs) => {
+                for (ref_name, hash) in refs {
+                    if ref_name.starts_with("refs/heads/") {
+                        self.check_link(&hash, ObjectType::Commit, &ref_name);
+                    } else if let Some(&actual) = self.types.get(&hash) {
+                        // other refs (e.g. tags written by `update-ref`) may point to any object
+                        self.check_link(&hash, actual, &ref_name);
+                    } else if !is_object_name(&hash, self.hash_length) {
+                        self.error(format!("{}: invalid object name '{}'", ref_name, hash));
+                    } else if !self.present.contains(&hash) {
+                        self.error(format!("{}: points to missing object {}", ref_name, hash));
+                    } else {
+                        // present but undecodable, which `check_object` already reported
+                        self.referenced.insert(hash);
+                    }
+                }
+            }
+            Err(e) => self.error(format!("refs: cannot be read: {}", e)),
+        }
+
+        let head_path = format!("{}/HEAD", repo.git_dir());
+        match storage::read_text_file(&head_path) {
+            Ok(content) => {
+                let content = content.trim_end();
+                match content.strip_prefix("ref: refs/heads/") {
+                    // the branch may be unborn, which `get_head` reports as having no commit
+                    Some(branch) => {
+                        if let Err(e) = reference::get_head(repo, branch) {
+                            self.error(format!("HEAD: points to refs/heads/{}, which cannot be read: {}", branch, e));
+                        }
+                    }
+                    None => self.check_link(content, ObjectType::Commit, "HEAD"),
+                }
+            }
+            Err(e) => self.error(format!("HEAD: cannot be read: {}", e)),
+        }
+        Ok(())
+    }
+
+    /// Checks that every entry of the index points to a blob.
+    fn check_index(&mut self, repo: &Repository) {
+        match index::read_index(repo) {
+            Ok(index) => {
+                for entry in index.values() {
+                    self.check_link(&entry.hash, ObjectType::Blob, &format!("index entry '{}'", entry.path));
+                }
+            }
+            Err(e) => self.error(format!("index: {}", e)),
+        }
+    }
+}
+
+
+/// Hashes a blob as it is inflated, the way it is named in the repository format.
+///
+/// # Arguments
+/// * `repo` - The repository holding the blob.
+/// * `name` - Hash of the blob.
+/// * `length` - Payload length of the blob, as read from its header.
+///
+/// # Errors
+/// * If the blob cannot be read or decoded.
+fn hash_blob(repo: &Repository, name: &str, length: u64) -> Result<String> {
+    let mut hasher = hash::StreamHasher::new(repo.hash_algorithm());
+    if repo.config().format == RepositoryFormat::Git {
+        hasher.update(&object::encode_object_header(repo, ObjectType::Blob, length));
+    }
+    object::write_object_payload_to(repo, name, ObjectType::Blob, &mut hasher)?;
+    Ok(hasher.finish())
+}
+
+
+/// Checks whether a string is a well-formed object name of the given length.
+fn is_object_name(hash: &str, hash_length: usize) -> bool {
+    hash.len() == hash_length && hash.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+
+/// Verifies the integrity of the repository.
+///
+/// # Arguments
+/// * `repo` - The repository to check.
+/// * `verbose` - If true, also displays a summary of the check.
+///
+/// # Behavior
+/// 1. Checks the checksum of every pack, then decodes every loose and packed object and
+///    checks that it hashes to its name.
+/// 2. Checks that trees and commits reference existing objects of the right type.
+/// 3. Checks that the branches, HEAD and the index reference existing objects of the right type.
+/// 4. Lists dangling objects, which are not errors.
+///
+/// # Errors
+/// * `Error::IntegrityCheckFailed` if any corrupt, missing or mistyped object or broken ref
+///   was found.
+/// * If the repository cannot be listed at all.
+pub fn fsck(repo: &Repository, verbose: bool) -> Result<()> {
+    let db = repo.odb();
+    let mut checker = Fsck { hash_length: db.hash_algorithm().hex_length(), ..Default::default() };
+
+    for pack_path in pack::list_packs(repo)? {
+        if let Err(e) = pack::verify_pack(repo, &pack_path) {
+            checker.error(e.to_string());
+        }
+    }
+
+    let mut names = db.iterate()?;
+    names.sort();
+    for name in &names {
+        checker.check_object(repo, name);
+    }
+
+    checker.check_connectivity();
+    checker.check_refs(repo)?;
+    checker.check_index(repo);
+
+    let mut dangling = 0;
+    for name in &names {
+        if let Some(object_type) = checker.types.get(name) {
+            if !checker.referenced.contains(name) {
+                eprintln!("dangling {} {}", object_type.git_name(), name);
+                dangling += 1;
+            }
+        }
+    }
+
+    if verbose {
+        eprintln!(
+            "Checked {} object(s): {} error(s), {} warning(s), {} dangling object(s).",
+            names.len(), checker.errors, checker.warnings, dangling
+        );
+    }
+
+    match checker.errors {
+        0 => Ok(()),
+        errors => Err(Error::IntegrityCheckFailed(errors)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::utils::hash::HashAlgorithm;
+
+    /// Stores an object with the given payload as is, and returns its name.
+    fn store(repo: &Repository, object_type: ObjectType, payload: &[u8]) -> String {
+        let db = repo.odb();
+        let full_content = object::encode_object_as(db.format(), object_type, payload);
+        let name = match db.format() {
+            RepositoryFormat::Native => db.hash_algorithm().digest(payload),
+            RepositoryFormat::Git => db.hash_algorithm().digest(&full_content),
+        };
+        db.put(&name, &full_content).unwrap();
+        name
+    }
+
+    /// Serializes tree entries in the given order, without any check.
+    fn tree_payload(format: RepositoryFormat, entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for (name, hash) in entries {
+            match format {
+                RepositoryFormat::Native => payload.extend(format!("BLOB\0{}\0{}\n", name, hash).into_bytes()),
+                RepositoryFormat::Git => {
+                    payload.extend(format!("100644 {}\0", name).into_bytes());
+                    payload.extend(hex::decode(hash).unwrap());
+                }
+            }
+        }
+        payload
+    }
+
+    fn repository(dir: &tempfile::TempDir, format: RepositoryFormat) -> Repository {
+        Repository::init(&dir.path().to_string_lossy(), None, format, HashAlgorithm::Sha1).unwrap()
+    }
+
+    #[test]
+    fn unsorted_native_trees_are_only_a_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repository(&dir, RepositoryFormat::Native);
+        let blob = store(&repo, ObjectType::Blob, b"content\n");
+
+        store(&repo, ObjectType::Tree, &tree_payload(RepositoryFormat::Native, &[("b", &blob), ("a", &blob)]));
+        assert!(fsck(&repo, false).is_ok());
+
+        store(&repo, ObjectType::Tree, &tree_payload(RepositoryFormat::Native, &[("a", &blob), ("a", &blob)]));
+        assert!(matches!(fsck(&repo, false), Err(Error::IntegrityCheckFailed(1))));
+    }
+
+    #[test]
+    fn unsorted_git_trees_are_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repository(&dir, RepositoryFormat::Git);
+        let blob = store(&repo, ObjectType::Blob, b"content\n");
+
+        store(&repo, ObjectType::Tree, &tree_payload(RepositoryFormat::Git, &[("a", &blob), ("b", &blob)]));
+        assert!(fsck(&repo, false).is_ok());
+
+        store(&repo, ObjectType::Tree, &tree_payload(RepositoryFormat::Git, &[("b", &blob), ("a", &blob)]));
+        assert!(matches!(fsck(&repo, false), Err(Error::IntegrityCheckFailed(1))));
+    }
+
+    #[test]
+    fn entries_split_by_a_newline_are_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repository(&dir, RepositoryFormat::Native);
+        let blob = store(&repo, ObjectType::Blob, b"content\n");
+
+        // a name holding a newline cuts its native entry line in two
+        store(&repo, ObjectType::Tree, &tree_payload(RepositoryFormat::Native, &[("new\nline", &blob)]));
+        assert!(matches!(fsck(&repo, false), Err(Error::IntegrityCheckFailed(1))));
+    }
+
+    #[test]
+    fn missing_mistyped_and_corrupt_objects_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repository(&dir, RepositoryFormat::Git);
+        fs::write(dir.path().join("file"), b"content\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        let commit = repo.commit("first").unwrap();
+        assert!(fsck(&repo, false).is_ok());
+
+        // a tree entry pointing to nothing
+        let missing = "1".repeat(40);
+        let tree = store(&repo, ObjectType::Tree, &tree_payload(RepositoryFormat::Git, &[("gone", &missing)]));
+        assert!(matches!(fsck(&repo, false), Err(Error::IntegrityCheckFailed(1))));
+
+        // a branch pointing to a tree is mistyped, on top of the missing entry
+        reference::update_ref(&repo, "refs/heads/tree", &tree, None).unwrap();
+        assert!(matches!(fsck(&repo, false), Err(Error::IntegrityCheckFailed(2))));
+        reference::update_ref(&repo, "refs/heads/tree", &commit, None).unwrap();
+
+        // the missing entry now exists, but its content does not hash to its name
+        let blob = object::encode_object(&repo, ObjectType::Blob, b"tampered\n");
+        repo.odb().put(&missing, &blob).unwrap();
+        assert!(matches!(fsck(&repo, false), Err(Error::IntegrityCheckFailed(1))));
+    }
+}
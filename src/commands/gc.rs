@@ -0,0 +1,289 @@
+//! Module: gc
+//!
+//! Implements garbage collection of unreachable objects, similar to `git gc --prune`.
+//! Every object reachable from the refs, HEAD or the index is kept. Unreachable objects
+//! are deleted once they are older than a grace period, so that objects being written by a
+//! concurrent command (e.g. blobs of an `add` not yet recorded in the index) survive.
+//!
+//! Loose objects are aged by their file's modification time. Packed objects are aged by
+//! their pack's; packs holding expired objects are rewritten without them.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::{core::*, utils::*};
+use crate::commands::repack;
+use crate::core::commit::{Commit, CommitTrait};
+use crate::core::object::ObjectType;
+use crate::core::tree::{Tree, TreeEntryType, TreeTrait};
+use crate::error::Result;
+use crate::repository::Repository;
+
+
+/// Deletes unreachable objects older than the grace period.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `grace_period` - Minimum age, in seconds, of an unreachable object for it to be deleted.
+/// * `dry_run` - If true, only lists the objects that would be deleted.
+/// * `verbose` - If true, displays beautified output instead of just the object hashes.
+///
+/// # Behavior
+/// 1. Marks every commit, tree and blob reachable from the refs, HEAD and the index.
+/// 2. Deletes the unreachable loose objects older than the grace period.
+/// 3. Rewrites the packs holding unreachable objects older than the grace period without them.
+///
+/// # Errors
+/// * If a reachable object cannot be read; nothing is deleted then.
+pub fn gc(repo: &Repository, grace_period: u64, dry_run: bool, verbose: bool) -> Result<()> {
+    let reachable = mark_reachable(repo)?;
+    let expire_time = SystemTime::now()
+        .checked_sub(Duration::from_secs(grace_period))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let is_expired = |path: &str| {
+        fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .is_ok_and(|modified| modified <= expire_time)
+    };
+
+    let mut expired_loose: Vec<String> = Default::default();
+    let mut expired_packed: HashSet<String> = Default::default();
+    let mut recent = 0;
+
+    for name in object::list_loose_objects(repo)? {
+        if reachable.contains(&name) {
+            continue;
+        }
+        if is_expired(&object::get_object_path(repo, &name)?) {
+            expired_loose.push(name);
+        } else {
+            recent += 1;
+        }
+    }
+    let mut pack_expired: HashMap<String, bool> = Default::default();
+    for name in pack::list_packed_objects(repo)? {
+        if reachable.contains(&name) || expired_packed.contains(&name) {
+            continue;
+        }
+        let pack_path = pack::find_packed_object(repo, &name)?.unwrap();
+        let expired = *pack_expired.entry(pack_path.clone()).or_insert_with(|| is_expired(&pack_path));
+        if expired {
+            expired_packed.insert(name);
+        } else {
+            recent += 1;
+        }
+    }
+
+    let mut pruned: Vec<&String> = expired_loose.iter().chain(expired_packed.iter()).collect();
+    pruned.sort();
+    pruned.dedup();
+
+    for name in &pruned {
+        match (dry_run, verbose) {
+            (true, _) => eprintln!("Would prune {}", name),
+            (false, false) => eprintln!("{}", name),
+            (false, true) => eprintln!("Pruned {}", name),
+        }
+    }
+
+    if !dry_run {
+        for name in &expired_loose {
+            let object_path = object::get_object_path(repo, name)?;
+            storage::remove_file(&object_path)?;
+            // the shard folder is only removed once it is empty
+            if let Some(folder) = Path::new(&object_path).parent() {
+                let _ = fs::remove_dir(folder);
+            }
+        }
+        if !expired_packed.is_empty() {
+            rewrite_packs(repo, &expired_packed)?;
+        }
+    }
+
+    if verbose {
+        eprintln!(
+            "{} {} unreachable object(s), kept {} unreachable object(s) younger than {} second(s).",
+            if dry_run { "Would prune" } else { "Pruned" }, pruned.len(), recent, grace_period
+        );
+    }
+    Ok(())
+}
+
+
+/// Replaces all packs by a single pack holding every packed object except `excluded`.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `excluded` - Hashes of the objects to drop.
+fn rewrite_packs(repo: &Repository, excluded: &HashSet<String>) -> Result<()> {
+    let old_packs = pack::list_packs(repo)?;
+
+    let names: HashSet<String> = pack::list_packed_objects(repo)?.into_iter().collect();
+    let mut objects = Vec::with_capacity(names.len());
+    for name in names {
+        if excluded.contains(&name) {
+            continue;
+        }
+        let (object_type, size) = object::read_object_info(repo, &name)?;
+        objects.push((name, object_type, size));
+    }
+
+    let new_pack = match objects.is_empty() {
+        true => None,
+        false => Some(pack::write_pack(repo, &objects, &repack::collect_path_hints(repo)?, repo.config().pack_depth)?),
+    };
+    for old_pack in &old_packs {
+        if Some(old_pack) != new_pack.as_ref() {
+            pack::remove_pack(repo, old_pack)?;
+        }
+    }
+    Ok(())
+}
+
+
+/// Collects every object reachable from the refs under `refs/`, HEAD and the index.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+///
+/// # Returns
+/// * `HashSet<String>` - Hashes of the reachable commits, trees and blobs.
+///
+/// # Errors
+/// * If a reachable object cannot be read.
+fn mark_reachable(repo: &Repository) -> Result<HashSet<String>> {
+    let mut reachable: HashSet<String> = Default::default();
+    let mut commit_stack: Vec<String> = vec![reference::get_current_commit(repo)?];
+    let mut tree_stack: Vec<String> = Default::default();
+
+    // refs other than branches (e.g. tags written by `update-ref`) may point to any object
+    for (_, hash) in reference::get_all_refs(repo)? {
+        match object::get_object_type(repo, &hash)? {
+            ObjectType::Commit => commit_stack.push(hash),
+            ObjectType::Tree => tree_stack.push(hash),
+            ObjectType::Blob => {
+                reachable.insert(hash);
+            }
+        }
+    }
+    for entry in index::read_index(repo)?.into_values() {
+        reachable.insert(entry.hash);
+    }
+
+    while let Some(commit_hash) = commit_stack.pop() {
+        if commit_hash.is_empty() || !reachable.insert(commit_hash.clone()) {
+            continue;
+        }
+        let mut commit = Commit { hash: Some(commit_hash), data: None };
+        commit.read_commit(repo)?;
+        let commit_data = commit.data.unwrap();
+
+        tree_stack.push(commit_data.tree_hash);
+        commit_stack.extend(commit_data.parent_commits);
+    }
+
+    while let Some(tree_hash) = tree_stack.pop() {
+        if tree_hash.is_empty() || !reachable.insert(tree_hash.clone()) {
+            continue;
+        }
+        let mut tree = Tree { hash: Some(tree_hash), data: None };
+        tree.read_tree(repo)?;
+
+        for entry in tree.data.unwrap() {
+            match entry.entry_type {
+                TreeEntryType::Tree => tree_stack.push(entry.hash),
+                _ => {
+                    reachable.insert(entry.hash);
+                }
+            }
+        }
+    }
+
+    Ok(reachable)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::RepositoryFormat;
+    use crate::utils::hash::HashAlgorithm;
+
+    /// Dates a file two hours back.
+    fn age(path: &str) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(7200)).unwrap();
+    }
+
+    /// Stores a blob that nothing references, and returns its name.
+    fn unreachable_blob(repo: &Repository, content: &[u8]) -> String {
+        let name = repo.hash_algorithm().digest(content);
+        object::write_object_file(repo, &name, &object::encode_object(repo, ObjectType::Blob, content)).unwrap();
+        name
+    }
+
+    fn repository(dir: &tempfile::TempDir) -> Repository {
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Native, HashAlgorithm::Sha1).unwrap();
+        fs::write(dir.path().join("file"), b"committed\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        repo.commit("file").unwrap();
+        fs::write(dir.path().join("staged"), b"staged\n").unwrap();
+        repo.add(&["staged"]).unwrap();
+        repo
+    }
+
+    #[test]
+    fn old_unreachable_loose_objects_are_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repository(&dir);
+        let recent = unreachable_blob(&repo, b"recent\n");
+        let old = unreachable_blob(&repo, b"old\n");
+        for name in object::list_loose_objects(&repo).unwrap() {
+            if name != recent {
+                age(&object::get_object_path(&repo, &name).unwrap());
+            }
+        }
+        let objects = object::list_loose_objects(&repo).unwrap().len();
+
+        gc(&repo, 3600, true, false).unwrap();
+        assert_eq!(object::list_loose_objects(&repo).unwrap().len(), objects);
+
+        gc(&repo, 3600, false, false).unwrap();
+        assert!(!object::object_exists(&repo, &old).unwrap());
+        assert!(object::object_exists(&repo, &recent).unwrap());
+        assert_eq!(object::list_loose_objects(&repo).unwrap().len(), objects - 1);
+
+        // the committed and the staged files survive
+        assert!(repo.status().unwrap().added.iter().any(|entry| entry.path == "staged"));
+        assert!(crate::commands::fsck::fsck(&repo, false).is_ok());
+
+        gc(&repo, 0, false, false).unwrap();
+        assert!(!object::object_exists(&repo, &recent).unwrap());
+    }
+
+    #[test]
+    fn old_unreachable_packed_objects_are_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = repository(&dir);
+        let unreachable = unreachable_blob(&repo, b"unreachable\n");
+        repack::repack(&repo, None, false).unwrap();
+        assert!(pack::contains_packed_object(&repo, &unreachable).unwrap());
+
+        gc(&repo, 3600, false, false).unwrap();
+        assert!(pack::contains_packed_object(&repo, &unreachable).unwrap());
+
+        for pack_path in pack::list_packs(&repo).unwrap() {
+            age(&pack_path);
+        }
+        let packed = pack::list_packed_objects(&repo).unwrap().len();
+        gc(&repo, 3600, true, false).unwrap();
+        assert_eq!(pack::list_packed_objects(&repo).unwrap().len(), packed);
+
+        gc(&repo, 3600, false, false).unwrap();
+        assert!(!object::object_exists(&repo, &unreachable).unwrap());
+        assert_eq!(pack::list_packed_objects(&repo).unwrap().len(), packed - 1);
+        assert!(crate::commands::fsck::fsck(&repo, false).is_ok());
+    }
+}
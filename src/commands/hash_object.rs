@@ -0,0 +1,103 @@
+//! Module: hash_object
+//!
+//! Implements computing the object name of file contents, similar to `git hash-object`,
+//! optionally storing them as blobs. The hashes are printed to stdout, one per line.
+
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::core::blob::{self, Blob, BlobTrait};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+
+
+/// Computes the blob hash of contents and files, optionally writing the blobs.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `data` - Content to hash before the files, e.g. read from stdin.
+/// * `files` - Paths of the files to hash, relative to the working directory.
+/// * `write` - If true, also stores the blobs in the object store.
+///
+/// # Returns
+/// * `Vec<String>` - The hash of `data`, if given, then of every file, in order.
+///
+/// # Errors
+/// * If a file cannot be read, or a blob cannot be written.
+pub fn hash_object_core(repo: &Repository, data: Option<Vec<u8>>, files: &[String], write: bool) -> Result<Vec<String>> {
+    let mut blobs: Vec<Blob> = Vec::new();
+    if let Some(data) = data {
+        let mut blob = Blob { hash: None, data: Some(data) };
+        blob.calculate_hash(repo)?;
+        blobs.push(blob);
+    }
+    for file in files {
+        let file_path = Path::new(repo.path()).join(file);
+        blobs.push(blob::get_blob_from_file(repo, &file_path.to_string_lossy())?);
+    }
+
+    let mut hashes = Vec::with_capacity(blobs.len());
+    for mut blob in blobs {
+        if write {
+            blob.write_blob(repo)?;
+        }
+        hashes.push(blob.hash.unwrap());
+    }
+    Ok(hashes)
+}
+
+
+/// Computes the blob hash of files or of stdin, optionally writing the blobs.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `files` - Paths of the files to hash, relative to the working directory.
+/// * `stdin` - If true, hashes the content read from stdin instead of files.
+/// * `write` - If true, also stores the blobs in the object store.
+///
+/// # Errors
+/// * If a file or stdin cannot be read.
+/// * `Error::InvalidArgument` if both or neither of `files` and `stdin` are given.
+pub fn hash_object(repo: &Repository, files: Vec<String>, stdin: bool, write: bool) -> Result<()> {
+    if stdin != files.is_empty() {
+        return Err(Error::InvalidArgument("Either --stdin or file(s) must be given.".to_string()));
+    }
+
+    let mut data: Option<Vec<u8>> = None;
+    if stdin {
+        let mut content: Vec<u8> = Vec::new();
+        io::stdin().read_to_end(&mut content).map_err(Error::io("Error when reading stdin"))?;
+        data = Some(content);
+    }
+
+    for hash in hash_object_core(repo, data, &files, write)? {
+        println!("{}", hash);
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::core::config::RepositoryFormat;
+    use crate::core::object;
+    use crate::utils::hash::HashAlgorithm;
+
+    #[test]
+    fn blobs_are_hashed_as_git_does() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        fs::write(dir.path().join("hello"), b"hello\n").unwrap();
+        let hello = "ce013625030ba8dba906f756967f9e9ca394464a";
+
+        let hashes = hash_object_core(&repo, Some(b"hello\n".to_vec()), &["hello".to_string()], false).unwrap();
+        assert_eq!(hashes, [hello, hello]);
+        assert!(!object::object_exists(&repo, hello).unwrap());
+
+        hash_object_core(&repo, None, &["hello".to_string()], true).unwrap();
+        assert_eq!(object::read_object_payload(&repo, hello, object::ObjectType::Blob).unwrap(), b"hello\n");
+    }
+}
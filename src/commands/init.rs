@@ -4,58 +4,121 @@
 //! Creates the necessary directory structure and initial files inside `.git`,
 //! and sets up the default branch reference.
 
-use crate::utils::*;
+use crate::core::{config::{self, Config, RepositoryFormat}, index};
+use crate::utils::{hash::HashAlgorithm, *};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
 
-use std::{fs, path::Path, process};
+use std::{fs, path::Path};
 
 
-/// Initializes a new Git repository in the current working directory.
+/// Initializes a new Git repository in a directory.
 ///
 /// # Arguments
+/// * `path` - The directory to create the repository in.
 /// * `initial_branch` - Optional name of the initial branch (defaults to "master").
+/// * `format` - Repository format, either `native` or `git`.
+/// * `object_format` - Hash algorithm of the object IDs, either `sha1` or `sha256`.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if the format or hash algorithm is unknown.
+/// * See `init_core`.
+pub fn init(path: &str, initial_branch: Option<String>, format: String, object_format: String) -> Result<()> {
+    let Some(format) = RepositoryFormat::from_name(&format) else {
+        return Err(Error::InvalidArgument(format!("Unknown repository format {}.", format)));
+    };
+    let Some(hash_algorithm) = HashAlgorithm::from_name(&object_format) else {
+        return Err(Error::InvalidArgument(format!("Unknown object format {}.", object_format)));
+    };
+
+    let (repo, reinitialized) = init_core(path, initial_branch, format, hash_algorithm, true)?;
+    if reinitialized {
+        eprintln!("Reinitialized existing Git repository at: {}", repo.git_dir());
+    }
+    Ok(())
+}
+
+
+/// Core logic for initializing a new repository in a directory.
+///
+/// # Arguments
+/// * `path` - The directory to create the repository in.
+/// * `initial_branch` - Optional name of the initial branch (defaults to "master").
+/// * `format` - Repository format.
+/// * `hash_algorithm` - Hash algorithm of the object IDs.
+/// * `reinitialize` - If true, an existing repository is deleted and replaced; otherwise it
+///   is an error.
+///
+/// # Returns
+/// * `(Repository, bool)` - The new repository, and true if an existing repository was replaced.
 ///
 /// # Behavior
-/// 1. Deletes any existing `.git` directory if present.
+/// 1. Deletes any existing `.git` directory if present and `reinitialize` is true.
 /// 2. Recreates the `.git` directory and all required subdirectories.
 /// 3. Sets up the initial `HEAD` reference pointing to the specified branch.
-/// 4. Creates an empty index file and branch reference.
+/// 4. Records the repository format and hash algorithm in `.git/config`.
+/// 5. Creates an empty index file and branch reference.
+///    In `git` format the branch reference is left unborn, as Git does.
 ///
-/// # Exits
-/// * If writing the HEAD reference fails.
-pub fn init(initial_branch: Option<String>) {
-    let git_directory: String = utils::pwd() + "/.git";//"/.mygit";
+/// # Errors
+/// * `Error::Io` if the directory does not exist.
+/// * `Error::InvalidState` if a repository already exists and `reinitialize` is false.
+/// * If deleting the existing repository, creating the directories or writing the HEAD
+///   reference fails.
+pub fn init_core(
+    path: &str,
+    initial_branch: Option<String>,
+    format: RepositoryFormat,
+    hash_algorithm: HashAlgorithm,
+    reinitialize: bool
+) -> Result<(Repository, bool)> {
+    let abs_path = Path::new(path)
+        .canonicalize()
+        .map_err(Error::io(format!("Error when canonicalizing path {}", path)))?
+        .to_string_lossy()
+        .into_owned();
+    let git_directory: String = abs_path.clone() + "/.git";//"/.mygit";
 
-    delete_original_directory(&git_directory);
+    if !reinitialize && Path::new(&git_directory).exists() {
+        return Err(Error::InvalidState(format!("A repository already exists at {}.", git_directory)));
+    }
+    let reinitialized = delete_original_directory(&git_directory)?;
 
     use storage::create_nonexist_directory;
     use storage::create_nonexist_file;
 
-    create_nonexist_directory(&git_directory);
+    create_nonexist_directory(&git_directory)?;
     // create_nonexist_directory(&format!("{}/branches", git_directory));
     // create_nonexist_directory(&format!("{}/logs", git_directory));
-    create_nonexist_directory(&format!("{}/objects", git_directory));
+    create_nonexist_directory(&format!("{}/objects", git_directory))?;
     // create_nonexist_directory(&format!("{}/objects/info", git_directory));
-    // create_nonexist_directory(&format!("{}/objects/pack", git_directory));
-    create_nonexist_directory(&format!("{}/refs", git_directory));
-    create_nonexist_directory(&format!("{}/refs/heads", git_directory));
+    create_nonexist_directory(&format!("{}/objects/pack", git_directory))?;
+    create_nonexist_directory(&format!("{}/refs", git_directory))?;
+    create_nonexist_directory(&format!("{}/refs/heads", git_directory))?;
     // create_nonexist_directory(&format!("{}/refs/tags", git_directory));
     // create_nonexist_file(&format!("{}/COMMIT_EDITMSG", git_directory));
-    create_nonexist_file(&format!("{}/HEAD", git_directory));
-    create_nonexist_file(&format!("{}/index", git_directory));
+    create_nonexist_file(&format!("{}/HEAD", git_directory))?;
+    create_nonexist_file(&format!("{}/index", git_directory))?;
+
+    config::write_config(&git_directory, &Config { format, hash_algorithm, format_version: config::FORMAT_VERSION, ..Default::default() })?;
+    let repo = Repository::open(&abs_path)?;
+    if format == RepositoryFormat::Git {
+        index::write_index(&repo, &Default::default())?;
+    }
 
     let branch_name: String = match &initial_branch {
         Some(name) => name.clone(),
         None => "master".to_string()
     };
 
-    if let Err(e) = storage::write_file( 
+    storage::write_file( 
         &format!("{}/HEAD", git_directory), 
-        &format!("ref: refs/heads/{}", branch_name).as_bytes()
-    ) {
-        eprintln!("Cannot write to {}/HEAD: {}", git_directory, e);
-        process::exit(1);
+        format!("ref: refs/heads/{}", branch_name).as_bytes()
+    ).map_err(Error::io(format!("Cannot write to {}/HEAD", git_directory)))?;
+    if format == RepositoryFormat::Native {
+        create_nonexist_file(&format!("{}/refs/heads/{}", git_directory, branch_name))?;
     }
-    create_nonexist_file(&format!("{}/refs/heads/{}", git_directory, branch_name));
+    Ok((repo, reinitialized))
 }
 
 
@@ -64,15 +127,16 @@ pub fn init(initial_branch: Option<String>) {
 /// # Arguments
 /// * `git_directory` - Path to the `.git` directory to delete.
 ///
-/// # Notes
-/// * If deletion fails, only prints an error but does not exit.
-fn delete_original_directory(git_directory: &str) {
+/// # Returns
+/// * `bool` - True if the directory existed.
+///
+/// # Errors
+/// * `Error::Io` if the directory cannot be deleted.
+fn delete_original_directory(git_directory: &str) -> Result<bool> {
     let path = Path::new(&git_directory);
-    if path.exists() {
-        if let Err(e) = fs::remove_dir_all(path) {
-            eprintln!("Error deleting original repository: {}", e);
-        } else {
-            eprintln!("Reinitialized existing Git repository at: {}", git_directory);
-        }
+    if !path.exists() {
+        return Ok(false);
     }
+    fs::remove_dir_all(path).map_err(Error::io("Error deleting original repository"))?;
+    Ok(true)
 }
\ No newline at end of file
@@ -0,0 +1,132 @@
+//! Module: ls_tree
+//!
+//! Implements listing the entries of a tree, similar to `git ls-tree`.
+//! The output goes to stdout, in Git's `<mode> <type> <hash>\t<path>` format.
+
+use crate::core::*;
+use crate::core::commit::{Commit, CommitTrait};
+use crate::core::object::ObjectType;
+use crate::core::tree::{Tree, TreeEntry, TreeEntryType, TreeTrait};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+
+
+/// Collects the entries of a tree (see `ls_tree`).
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `tree_ish` - Revision naming a tree, or a commit whose tree is listed.
+/// * `recursive` - If true, collects the blobs of every subtree instead of the subtrees themselves.
+///
+/// # Returns
+/// * `Vec<(String, TreeEntry)>` - The path of every entry, relative to the tree, and the entry,
+///   in tree order.
+///
+/// # Errors
+/// * `Error::InvalidRevision` if the revision does not name a tree or a commit.
+pub fn ls_tree_core(repo: &Repository, tree_ish: &str, recursive: bool) -> Result<Vec<(String, TreeEntry)>> {
+    let hash = revision::resolve_revision(repo, tree_ish)?;
+
+    let tree_hash = match object::get_object_type(repo, &hash)? {
+        ObjectType::Tree => hash,
+        ObjectType::Commit => {
+            let mut commit = Commit { hash: Some(hash), data: None };
+            commit.read_commit(repo)?;
+            commit.data.unwrap().tree_hash
+        }
+        ObjectType::Blob => {
+            return Err(Error::InvalidRevision(format!("{} is not a tree.", tree_ish)));
+        }
+    };
+
+    let mut entries = Vec::new();
+    list_tree(repo, &tree_hash, "", recursive, &mut entries)?;
+    Ok(entries)
+}
+
+
+/// Lists the entries of a tree.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `tree_ish` - Revision naming a tree, or a commit whose tree is listed (e.g. `HEAD`, `HEAD:src`).
+/// * `recursive` - If true, lists the blobs of every subtree with their full path, instead of
+///   the subtrees themselves.
+///
+/// # Errors
+/// * `Error::InvalidRevision` if the revision does not name a tree or a commit.
+pub fn ls_tree(repo: &Repository, tree_ish: String, recursive: bool) -> Result<()> {
+    for (path, entry) in ls_tree_core(repo, &tree_ish, recursive)? {
+        println!("{}", format_tree_entry(&entry, &path));
+    }
+    Ok(())
+}
+
+
+/// Collects the entries of a tree, prefixing their names with `prefix`.
+fn list_tree(repo: &Repository, tree_hash: &str, prefix: &str, recursive: bool, entries: &mut Vec<(String, TreeEntry)>) -> Result<()> {
+    let mut tree = Tree { hash: Some(tree_hash.to_string()), data: None };
+    tree.read_tree(repo)?;
+
+    for entry in tree.data.unwrap() {
+        let path = format!("{}{}", prefix, entry.name);
+        if recursive && entry.entry_type == TreeEntryType::Tree {
+            list_tree(repo, &entry.hash, &format!("{}/", path), recursive, entries)?;
+        } else {
+            entries.push((path, entry));
+        }
+    }
+    Ok(())
+}
+
+
+/// Formats a tree entry as Git prints it: `<mode> <type> <hash>\t<path>`.
+///
+/// # Arguments
+/// * `entry` - The tree entry.
+/// * `path` - Name to print for the entry.
+pub fn format_tree_entry(entry: &TreeEntry, path: &str) -> String {
+    let object_type = match entry.entry_type {
+        TreeEntryType::Tree => ObjectType::Tree,
+        _ => ObjectType::Blob,
+    };
+    format!("{:0>6} {} {}\t{}", entry.entry_type.git_mode(), object_type.git_name(), entry.hash, path)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+    use crate::core::config::RepositoryFormat;
+    use crate::utils::hash::HashAlgorithm;
+
+    fn listing(repo: &Repository, tree_ish: &str, recursive: bool) -> Vec<String> {
+        ls_tree_core(repo, tree_ish, recursive).unwrap()
+            .iter()
+            .map(|(path, entry)| format_tree_entry(entry, path))
+            .collect()
+    }
+
+    #[test]
+    fn entries_are_listed_as_git_does() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        fs::write(dir.path().join("hello"), b"hello\n").unwrap();
+        fs::create_dir(dir.path().join("dir")).unwrap();
+        fs::write(dir.path().join("dir/nested"), b"nested\n").unwrap();
+        fs::set_permissions(dir.path().join("dir/nested"), fs::Permissions::from_mode(0o755)).unwrap();
+        repo.add(&["hello", "dir"]).unwrap();
+        repo.commit("tree").unwrap();
+
+        let nested = "100755 blob 79c53955ef856f16f2107446bc721c8879a1bd2e\tnested";
+        let hello = "100644 blob ce013625030ba8dba906f756967f9e9ca394464a\thello";
+        assert_eq!(listing(&repo, "HEAD", false), ["040000 tree f8d6bbbe85d7b819079bd49bf452c17d6b4b0449\tdir", hello]);
+        assert_eq!(listing(&repo, "HEAD", true), ["100755 blob 79c53955ef856f16f2107446bc721c8879a1bd2e\tdir/nested", hello]);
+        assert_eq!(listing(&repo, "c08c3e075e99c9cfba0c9ae0e4f43a911d5c58d3", false).len(), 2);
+        assert_eq!(listing(&repo, "HEAD:dir", false), [nested]);
+        assert!(matches!(ls_tree_core(&repo, "HEAD:hello", false), Err(Error::InvalidRevision(_))));
+    }
+}
@@ -9,17 +9,57 @@
 //! - Reporting file conflicts when both sides modified the same content.
 //! - Updating the working directory and index after a successful merge.
 
-use std::{collections::{HashMap, HashSet}, path::Path, process, str};
+use std::{collections::{HashMap, HashSet}, str};
 
 use crate::{commands::commit::commit_merge, core::{blob::{Blob, BlobTrait}, commit::{Commit, CommitTrait}, index::IndexEntry, tree::*, *}, utils::*};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+
+
+/// The result of a successful merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// The merged commit is already an ancestor of the current branch; nothing changed.
+    UpToDate,
+    /// The current branch was moved forward to the merged commit, given by hash.
+    FastForward(String),
+    /// A merge commit was created, given by hash.
+    Merged(String),
+}
 
 
 /// Merges the specified branch into the current branch.
 ///
 /// # Arguments
-/// * `merge_branch` - Name of the branch to merge into the current branch.
+/// * `repo` - The repository to operate on.
+/// * `merge_branch` - Name of the branch, or revision (e.g. `feature~1`), to merge into the current branch.
+/// * `force` - If true, allows merging even if there are uncommitted changes.
+///
+/// # Errors
+/// * See `merge_core`.
+pub fn merge(repo: &Repository, merge_branch: String, force: bool) -> Result<()> {
+    let current_branch = reference::get_current_branch(repo)?;
+    match merge_core(repo, &merge_branch, force)? {
+        MergeOutcome::UpToDate => println!("Already up to date."),
+        MergeOutcome::FastForward(_) => println!(
+            "Merged branch {} to {} by Fast-Forward Policy.", current_branch.unwrap_or_default(), merge_branch
+        ),
+        MergeOutcome::Merged(_) => println!("Merged branches {} and {}.", current_branch.unwrap_or_default(), merge_branch),
+    }
+    Ok(())
+}
+
+
+/// Core logic for merging a branch into the current branch.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `merge_branch` - Name of the branch, or revision (e.g. `feature~1`), to merge into the current branch.
 /// * `force` - If true, allows merging even if there are uncommitted changes.
 ///
+/// # Returns
+/// * `MergeOutcome` - Whether the merge was a no-op, a fast-forward, or created a merge commit.
+///
 /// # Behavior
 /// 1. Checks if the repository is in a valid state (not detached HEAD).
 /// 2. Determines if a fast-forward merge is possible.
@@ -27,66 +67,70 @@ use crate::{commands::commit::commit_merge, core::{blob::{Blob, BlobTrait}, comm
 /// 4. Detects and reports conflicts if files were changed differently on both branches.
 /// 5. Updates the working area and index with the merged content.
 ///
-/// # Exits
-/// - If there are uncommitted changes and `force` is false.
-/// - If the repository is in detached HEAD state.
-/// - If any merge conflicts are detected.
+/// # Errors
+/// - `Error::Locked` if another process holds the index or branch lock.
+/// - `Error::StaleRef` if the branch was moved by another process during the merge.
+/// - `Error::DirtyWorktree` if there are uncommitted changes and `force` is false.
+/// - `Error::InvalidState` if the repository is in detached HEAD state.
+/// - `Error::Conflict` if any merge conflicts are detected; nothing is changed then.
 /// - If an I/O or internal error occurs during processing.
-pub fn merge(merge_branch: String, force: bool) {
+pub fn merge_core(repo: &Repository, merge_branch: &str, force: bool) -> Result<MergeOutcome> {
 
-    if !force && commit::check_has_uncommitted() {
-        eprintln!("Detected uncommited files. Cannot merge.");
-        process::exit(1);
-    }
+    // held until the merged index is written, so that no other command updates it in between
+    let lock = index::lock_index(repo)?;
+    let mut index = index::read_index(repo)?;
 
-    let current_branch: String;
+    if !force && commit::check_has_uncommitted(repo, &mut index)? {
+        index::write_locked_index(repo, lock, &index)?;
+        return Err(Error::DirtyWorktree("Detected uncommited files. Cannot merge.".to_string()));
+    }
 
-    match &reference::get_current_branch() {
+    let current_branch = match reference::get_current_branch(repo)? {
         None => {
-            eprintln!("You are in 'detached HEAD' state. Cannot merge.");
-            process::exit(1);
-        }
-        Some(branch_name) => {
-            current_branch = branch_name.to_string();
+            return Err(Error::InvalidState("You are in 'detached HEAD' state. Cannot merge.".to_string()));
         }
-    }
+        Some(branch_name) => branch_name,
+    };
+
+    let current_commit = reference::get_head(repo, &current_branch)?;
+    let merge_commit = revision::resolve_commit(repo, merge_branch)?;
 
-    if reference::is_prev_branch(&merge_branch, &current_branch) {
-        println!("Already up to date.");
-        process::exit(0);
-    } else if reference::is_prev_branch(&current_branch, &merge_branch) {
-        reference::store_head(&current_branch, &reference::get_head(&merge_branch));
-        println!("Merged branch {} to {} by Fast-Forward Policy.", current_branch, merge_branch);
+    if commit::is_prev_commit(repo, &merge_commit, &current_commit)? {
+        if !force {
+            index::write_locked_index(repo, lock, &index)?;
+        }
+        return Ok(MergeOutcome::UpToDate);
+    } else if commit::is_prev_commit(repo, &current_commit, &merge_commit)? {
+        let ref_name = format!("refs/heads/{}", current_branch);
+        reference::update_ref(repo, &ref_name, &merge_commit, Some(&current_commit))?;
 
-        storage::clear_working_area();
-        let commit_hash = reference::get_head(&current_branch);
-        storage::restore_working_area(&commit_hash);
+        storage::clear_working_area(repo)?;
+        let commit_hash = merge_commit;
+        storage::restore_working_area(repo, &commit_hash)?;
 
         let mut commit = Commit {
-            hash: Some(commit_hash),
+            hash: Some(commit_hash.clone()),
             data: None
         };
-        commit.read_commit();
+        commit.read_commit(repo)?;
     
         let mut index_entries: HashMap<String, IndexEntry> = Default::default();
-        storage::restore_index_by_tree(&commit.data.unwrap().tree_hash, &utils::pwd(), &mut index_entries);
-        index::write_index(&index_entries);
-
-        process::exit(0);
+        storage::restore_index_by_tree(repo, &commit.data.unwrap().tree_hash, repo.path(), &mut index_entries)?;
+        index::record_stat(repo, &mut index_entries);
+        index::write_locked_index(repo, lock, &index_entries)?;
+        return Ok(MergeOutcome::FastForward(commit_hash));
     }
 
     // Need to produce a new commit
 
-    let current_commit = reference::get_head(&current_branch);
-    let merge_commit = reference::get_head(&merge_branch);
-    let base_commit = commit::get_merge_base(&current_commit, &merge_commit);
+    let base_commit = commit::get_merge_base(repo, &current_commit, &merge_commit)?;
 
     let mut current_blob_table: HashMap<String, TreeEntry> = Default::default();
     let mut merge_blob_table: HashMap<String, TreeEntry> = Default::default();
     let mut base_blob_table: HashMap<String, TreeEntry> = Default::default();
-    register_blob_by_commit(&current_commit, &mut current_blob_table);
-    register_blob_by_commit(&merge_commit, &mut merge_blob_table);
-    register_blob_by_commit(&base_commit, &mut base_blob_table);
+    register_blob_by_commit(repo, &current_commit, &mut current_blob_table)?;
+    register_blob_by_commit(repo, &merge_commit, &mut merge_blob_table)?;
+    register_blob_by_commit(repo, &base_commit, &mut base_blob_table)?;
 
     // println!("[base]");
     // for blob in &base_blob_table {
@@ -137,7 +181,7 @@ pub fn merge(merge_branch: String, force: bool) {
     let mut new_blob_table: HashMap<String, TreeEntry> = base_blob_table.clone();
     let mut conflicts: HashSet<(String, TreeEntry, TreeEntry)> = Default::default();
         // (String, TreeEntry, TreeEntry): (path, entry1, entry2)
-    let mut has_operation_conflict: bool = false;
+    let mut operation_conflicts: Vec<String> = Vec::new();
     
     // add
     for (current_path, current_entry) in &current_add_log {
@@ -165,24 +209,22 @@ pub fn merge(merge_branch: String, force: bool) {
         new_blob_table.insert(merge_path.to_owned(), merge_entry.to_owned());
     }
     // remove
-    for (current_path, _) in &current_remove_log {
+    for current_path in current_remove_log.keys() {
         if merge_modify_log.contains_key(current_path) {
-            eprintln!("Detected operation conflict: {}", current_path);
-            eprintln!("    Removed in: {}", current_commit);
-            eprintln!("    Modified in: {}", merge_commit);
-            has_operation_conflict = true;
+            operation_conflicts.push(format!("Detected operation conflict: {}", current_path));
+            operation_conflicts.push(format!("    Removed in: {}", current_commit));
+            operation_conflicts.push(format!("    Modified in: {}", merge_commit));
             continue;
         }
         if new_blob_table.contains_key(current_path) {
             new_blob_table.remove(current_path);
         }
     }
-    for (merge_path, _) in &merge_remove_log {
+    for merge_path in merge_remove_log.keys() {
         if current_modify_log.contains_key(merge_path) {
-            eprintln!("Detected operation conflict: {}", merge_path);
-            eprintln!("    Removed in: {}", merge_commit);
-            eprintln!("    Modified in: {}", current_commit);
-            has_operation_conflict = true;
+            operation_conflicts.push(format!("Detected operation conflict: {}", merge_path));
+            operation_conflicts.push(format!("    Removed in: {}", merge_commit));
+            operation_conflicts.push(format!("    Modified in: {}", current_commit));
             continue;
         }
         if new_blob_table.contains_key(merge_path) {
@@ -230,14 +272,15 @@ pub fn merge(merge_branch: String, force: bool) {
     //     println!("path: {}", conflict.0);
     // }
 
-    if conflicts.len() != 0 {
+    if !conflicts.is_empty() {
+        let mut conflict_lines: Vec<String> = Vec::new();
         for (path, entry1, entry2) in &conflicts {
-            analyse_merge_conflict(path, entry1, entry2);
+            conflict_lines.extend(analyse_merge_conflict(repo, path, entry1, entry2)?);
         }
-        process::exit(1);
+        return Err(Error::Conflict(conflict_lines));
     }
-    if has_operation_conflict {
-        process::exit(1);
+    if !operation_conflicts.is_empty() {
+        return Err(Error::Conflict(operation_conflicts));
     }
 
     // Can merge now
@@ -248,52 +291,50 @@ pub fn merge(merge_branch: String, force: bool) {
     let mut index_entries: HashSet<IndexEntry> = Default::default();
     
     for (blob_path, blob_entry) in &new_blob_table {
-        let repo_path = utils::pwd();
-        assert!(blob_path.starts_with(&repo_path));
-        let rel_path = Path::new(&blob_path)
-            .strip_prefix(&repo_path)
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
-
-        index_entries.insert(IndexEntry { path: rel_path, hash: blob_entry.hash.to_owned() });
+        let rel_path = utils::get_relative_path(repo.path(), blob_path)?;
+
+        index_entries.insert(IndexEntry {
+            path: rel_path,
+            hash: blob_entry.hash.to_owned(),
+            entry_type: blob_entry.entry_type.clone(),
+            stat: Default::default(),
+        });
     }
 
-    let parent_commits = [
-        reference::get_head(&current_branch),
-        reference::get_head(&merge_branch)
-    ];
+    let parent_commits = [current_commit.clone(), merge_commit];
 
     let new_head_hash = commit_merge(
+        repo,
         &index_entries,
         format!("Merge branch {} and {}.", current_branch, merge_branch),
         utils::get_time_string(),
         utils::get_username(),
         parent_commits.to_vec(),
         new_blob_table
-    );
+    )?;
 
-    reference::store_head(&current_branch, &new_head_hash);
+    let ref_name = format!("refs/heads/{}", current_branch);
+    reference::update_ref(repo, &ref_name, &new_head_hash, Some(&current_commit))?;
 
     // storage::clear_index();
 
     // restore working area
-    storage::clear_working_area();
-    storage::restore_working_area(&new_head_hash);
+    storage::clear_working_area(repo)?;
+    storage::restore_working_area(repo, &new_head_hash)?;
 
     // restore index
     let mut commit = Commit {
         hash: Some(new_head_hash.to_string()),
         data: None
     };
-    commit.read_commit();
+    commit.read_commit(repo)?;
 
     let mut index_entries: HashMap<String, IndexEntry> = Default::default();
-    storage::restore_index_by_tree(&commit.data.unwrap().tree_hash, &utils::pwd(), &mut index_entries);
-    index::write_index(&index_entries);
+    storage::restore_index_by_tree(repo, &commit.data.unwrap().tree_hash, repo.path(), &mut index_entries)?;
+    index::record_stat(repo, &mut index_entries);
+    index::write_locked_index(repo, lock, &index_entries)?;
 
-    println!("Merged branches {} and {}.", current_branch, merge_branch);
+    Ok(MergeOutcome::Merged(new_head_hash))
 }
 
 
@@ -302,32 +343,33 @@ pub fn merge(merge_branch: String, force: bool) {
 /// Used during merge to track file paths and their corresponding tree entries.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `tree_hash` - SHA-1 hash of the tree to traverse.
 /// * `tree_path` - Filesystem path where this tree is restored.
 /// * `blob_table` - Mutable reference to the map storing blob entries by path.
-/// * `success` - Boolean flag indicating whether all operations succeeded.
+/// * `conflicts` - Receives a description of every path registered twice with different entries.
 fn register_blob(
+    repo: &Repository,
     tree_hash: &str,
     tree_path: &str,
     blob_table: &mut HashMap<String, tree::TreeEntry>,
-    success: &mut bool
-) {
+    conflicts: &mut Vec<String>
+) -> Result<()> {
     let mut tree = Tree { hash: Some(tree_hash.to_owned()), data: None};
-    tree.read_tree();
+    tree.read_tree(repo)?;
 
     let entries = tree.data.as_ref().unwrap();
     for entry in entries {
         let entry_path = format!("{}/{}", tree_path, entry.name);
         match entry.entry_type {
             TreeEntryType::Tree => {
-                register_blob(&entry.hash, &entry_path, blob_table, success);
+                register_blob(repo, &entry.hash, &entry_path, blob_table, conflicts)?;
             }
             _ => {
                 // This is a blob
                 if let Some(stored_entry) = blob_table.get(&entry_path) {
                     if stored_entry != entry {
-                        eprintln!("Conflict deteced on {}.", entry_path);
-                        *success = false;
+                        conflicts.push(format!("Conflict deteced on {}.", entry_path));
                     }
                 } else {
                     blob_table.insert(entry_path, entry.clone());
@@ -335,51 +377,60 @@ fn register_blob(
             }
         }
     }
+    Ok(())
 }
 
 
 /// Registers all blobs from a commit's tree into the blob table.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `commit_hash` - SHA-1 hash of the commit to extract blob data from.
 /// * `blob_table` - Mutable reference to the map storing blob entries by path.
-fn register_blob_by_commit(commit_hash: &str, blob_table: &mut HashMap<String, TreeEntry>) {
+///
+/// # Errors
+/// * `Error::Conflict` if a path is registered twice with different entries.
+fn register_blob_by_commit(repo: &Repository, commit_hash: &str, blob_table: &mut HashMap<String, TreeEntry>) -> Result<()> {
     let mut commit = Commit { hash: Some(commit_hash.to_owned()), data: None };
-    commit.read_commit();
+    commit.read_commit(repo)?;
     let root_hash = commit.data.unwrap().tree_hash;
 
-    let mut success: bool = true;
-    register_blob(&root_hash, &utils::pwd(), blob_table, &mut success);
-    if !success {
-        eprintln!("Failed to merge. Nothing changed.");
-        process::exit(1);
+    let mut conflicts: Vec<String> = Vec::new();
+    register_blob(repo, &root_hash, repo.path(), blob_table, &mut conflicts)?;
+    if !conflicts.is_empty() {
+        conflicts.push("Failed to merge. Nothing changed.".to_string());
+        return Err(Error::Conflict(conflicts));
     }
+    Ok(())
 }
 
 
-/// Analyzes and prints detailed conflict information between two versions of a file.
+/// Analyzes conflict information between two versions of a file.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `path` - Path of the conflicting file.
 /// * `entry1` - First version's tree entry.
 /// * `entry2` - Second version's tree entry.
-fn analyse_merge_conflict(path: &str, entry1: &TreeEntry, entry2: &TreeEntry) {
+///
+/// # Returns
+/// * `Vec<String>` - One description per range of conflicting lines, or a single one for
+///   binary files.
+fn analyse_merge_conflict(repo: &Repository, path: &str, entry1: &TreeEntry, entry2: &TreeEntry) -> Result<Vec<String>> {
     let mut blob1 = Blob { hash: Some(entry1.hash.clone()), data: None };
     let mut blob2 = Blob { hash: Some(entry2.hash.clone()), data: None };
-    blob1.read_blob();
-    blob2.read_blob();
+    blob1.read_blob(repo)?;
+    blob2.read_blob(repo)?;
     let data1: Vec<u8> = blob1.data.unwrap();
     let data2: Vec<u8> = blob2.data.unwrap();
 
-    let repo_path = utils::pwd();
+    let rel_path = utils::get_relative_path(repo.path(), path)?;
 
     let Ok(text1) = str::from_utf8(&data1) else {
-        eprintln!("Merge conflict in {}", utils::relative_path(&repo_path, path));
-        return;
+        return Ok(vec![format!("Merge conflict in {}", rel_path)]);
     };
     let Ok(text2) = str::from_utf8(&data2) else {
-        eprintln!("Merge conflict in {}", utils::relative_path(&repo_path, path));
-        return;
+        return Ok(vec![format!("Merge conflict in {}", rel_path)]);
     };
 
     let lines1: Vec<_> = text1.lines().collect();
@@ -409,13 +460,13 @@ fn analyse_merge_conflict(path: &str, entry1: &TreeEntry, entry2: &TreeEntry) {
         conflict_ranges.push((start, max_len));
     }
 
-    for (start, end) in conflict_ranges {
+    Ok(conflict_ranges.into_iter().map(|(start, end)| {
         if start == end {
-            eprintln!("Merge conflict in {}: {}", utils::relative_path(&repo_path, path), start);
+            format!("Merge conflict in {}: {}", rel_path, start)
         } else {
-            eprintln!("Merge conflict in {}: [{}, {}]", utils::relative_path(&repo_path, path), start, end);
+            format!("Merge conflict in {}: [{}, {}]", rel_path, start, end)
         }
-    }
+    }).collect())
 }
 
 
@@ -447,4 +498,82 @@ echo -e "1\nB\nB\n2\nB" > 1.txt
 ./git merge A
 
 
-*/
\ No newline at end of file
+*/
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::core::config::RepositoryFormat;
+    use crate::utils::hash::HashAlgorithm;
+
+    /// Creates a repository where `side` is one commit ahead of `master`, and returns
+    /// the commits of both branches.
+    fn diverging_repository(dir: &tempfile::TempDir) -> (Repository, String, String) {
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Native, HashAlgorithm::Sha1).unwrap();
+        fs::write(dir.path().join("file"), b"base\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        let master = repo.commit("base").unwrap();
+
+        repo.create_branch("side", None).unwrap();
+        repo.checkout("side", false).unwrap();
+        fs::write(dir.path().join("side"), b"side\n").unwrap();
+        repo.add(&["side"]).unwrap();
+        let side = repo.commit("side").unwrap();
+        repo.checkout("master", false).unwrap();
+        (repo, master, side)
+    }
+
+    #[test]
+    fn fast_forward_and_merge_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, _, side) = diverging_repository(&dir);
+        assert_eq!(repo.merge("side", false).unwrap(), MergeOutcome::FastForward(side.clone()));
+        assert_eq!(repo.resolve("master").unwrap(), side);
+        assert!(dir.path().join("side").exists());
+        assert!(repo.status().unwrap().is_clean());
+        assert_eq!(repo.merge("side", false).unwrap(), MergeOutcome::UpToDate);
+
+        fs::write(dir.path().join("other"), b"other\n").unwrap();
+        repo.add(&["other"]).unwrap();
+        let master = repo.commit("other").unwrap();
+        repo.checkout("side", false).unwrap();
+        fs::write(dir.path().join("third"), b"third\n").unwrap();
+        repo.add(&["third"]).unwrap();
+        let side = repo.commit("third").unwrap();
+        repo.checkout("master", false).unwrap();
+
+        let MergeOutcome::Merged(merged) = repo.merge("side", false).unwrap() else {
+            panic!("expected a merge commit");
+        };
+        assert_eq!(repo.resolve("master").unwrap(), merged);
+        assert_eq!(repo.resolve("master^1").unwrap(), master);
+        assert_eq!(repo.resolve("master^2").unwrap(), side);
+        assert!(dir.path().join("other").exists() && dir.path().join("third").exists());
+        assert!(repo.status().unwrap().is_clean());
+    }
+
+    #[test]
+    fn held_locks_fail_cleanly() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, master, _) = diverging_repository(&dir);
+
+        let index_lock = index::lock_index(&repo).unwrap();
+        assert!(matches!(repo.merge("side", false), Err(Error::Locked(_))));
+        drop(index_lock);
+
+        let ref_path = format!("{}/refs/heads/master", repo.git_dir());
+        let ref_lock = storage::LockFile::acquire(&ref_path).unwrap();
+        assert!(matches!(repo.merge("side", false), Err(Error::Locked(_))));
+        drop(ref_lock);
+
+        // neither attempt changed anything, and no lock file was left behind
+        assert_eq!(repo.resolve("master").unwrap(), master);
+        assert!(!dir.path().join("side").exists());
+        assert!(repo.status().unwrap().is_clean());
+        assert!(!Path::new(&format!("{}/index.lock", repo.git_dir())).exists());
+        assert_eq!(repo.merge("side", false).unwrap(), MergeOutcome::FastForward(repo.resolve("side").unwrap()));
+    }
+}
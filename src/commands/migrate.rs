@@ -0,0 +1,269 @@
+//! Module: migrate
+//!
+//! Implements upgrading a repository to the object format version written by this build
+//! (`rustgit.version` in `.git/config`).
+//!
+//! Native repositories of version 1 hash commits without their parents. Migrating rewrites
+//! every commit reachable from the refs under `refs/` and HEAD under its new hash, in
+//! parents-first order, then points the refs at the rewritten commits. Trees and blobs are
+//! left untouched. The old commit objects stay in the store until they are garbage collected.
+//!
+//! Later versions only change how new commits are encoded; existing commits keep parsing
+//! under their current hashes, so upgrading from version 2 just records the new version.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::core::*;
+use crate::utils::hash::HashAlgorithm;
+use crate::core::commit::{Commit, CommitTrait};
+use crate::core::object::ObjectType;
+use crate::core::config::{RepositoryFormat, FORMAT_VERSION};
+use crate::core::odb::{LooseObjectDatabase, ObjectDatabase};
+use crate::error::Result;
+use crate::repository::Repository;
+
+
+/// The repository's object store, writing objects at the current format version.
+struct UpgradedObjectDatabase<'r>(LooseObjectDatabase<'r>);
+
+impl ObjectDatabase for UpgradedObjectDatabase<'_> {
+    fn format(&self) -> RepositoryFormat {
+        self.0.format()
+    }
+
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.0.hash_algorithm()
+    }
+
+    fn format_version(&self) -> u32 {
+        FORMAT_VERSION
+    }
+
+    fn get(&self, object_name: &str) -> Result<Option<Vec<u8>>> {
+        self.0.get(object_name)
+    }
+
+    fn put(&self, object_name: &str, content: &[u8]) -> Result<()> {
+        self.0.put(object_name, content)
+    }
+
+    fn contains(&self, object_name: &str) -> Result<bool> {
+        self.0.contains(object_name)
+    }
+
+    fn iterate(&self) -> Result<Vec<String>> {
+        self.0.iterate()
+    }
+}
+
+
+/// Upgrades the repository to the current format version.
+///
+/// # Arguments
+/// * `repo` - The repository to upgrade.
+/// * `verbose` - If true, displays beautified output instead of just the rewritten refs.
+///
+/// # Behavior
+/// 1. Does nothing if the repository is already at the current version.
+/// 2. In native repositories before version 2, rewrites every commit reachable from the
+///    refs under `refs/` (branches, tags and others) and a detached HEAD, and updates those
+///    refs to the new hashes.
+///    Other repositories already hash parents and only get their version bumped.
+/// 3. Records the new version in `.git/config`.
+///
+/// The rewrite is idempotent: rerunning an interrupted migration maps already rewritten
+/// commits onto themselves.
+///
+/// # Errors
+/// * `Error::StaleRef` if a ref is moved by another process during the migration.
+/// * If a commit cannot be read or written, or a ref or the config cannot be updated.
+pub fn migrate(repo: &Repository, verbose: bool) -> Result<()> {
+    let mut repo_config = repo.config().clone();
+
+    if repo_config.format_version >= FORMAT_VERSION {
+        if verbose {
+            eprintln!("Repository is already at version {}.", repo_config.format_version);
+        }
+        return Ok(());
+    }
+
+    let mut rewritten: HashMap<String, String> = Default::default();
+    let mut updated_refs = 0;
+
+    if repo_config.format == RepositoryFormat::Native && repo_config.format_version < 2 {
+        // refs may also name trees or blobs (see `update-ref`), which keep their hashes
+        let mut refs: Vec<(String, String)> = Vec::new();
+        for (ref_name, hash) in reference::get_all_refs(repo)? {
+            if object::get_object_type(repo, &hash)? == ObjectType::Commit {
+                refs.push((ref_name, hash));
+            }
+        }
+        let detached_head = match reference::get_current_branch(repo)? {
+            None => Some(reference::get_current_commit(repo)?),
+            Some(_) => None,
+        };
+
+        let db = UpgradedObjectDatabase(repo.odb());
+        for hash in refs.iter().map(|(_, hash)| hash).chain(detached_head.iter()) {
+            rewrite_history(&db, hash, &mut rewritten)?;
+        }
+
+        for (ref_name, hash) in &refs {
+            if let Some(new_hash) = rewritten.get(hash).filter(|new_hash| *new_hash != hash) {
+                reference::update_ref(repo, ref_name, new_hash, Some(hash))?;
+                report_ref(ref_name, hash, new_hash, verbose);
+                updated_refs += 1;
+            }
+        }
+        if let Some(hash) = &detached_head {
+            if let Some(new_hash) = rewritten.get(hash).filter(|new_hash| *new_hash != hash) {
+                reference::store_current_branch_commit(repo, new_hash)?;
+                report_ref("HEAD", hash, new_hash, verbose);
+                updated_refs += 1;
+            }
+        }
+    }
+
+    repo_config.format_version = FORMAT_VERSION;
+    config::write_config(repo.git_dir(), &repo_config)?;
+
+    if verbose {
+        eprintln!(
+            "Migrated repository to version {}: rewrote {} commit(s), updated {} ref(s).",
+            FORMAT_VERSION, rewritten.len(), updated_refs
+        );
+    }
+    Ok(())
+}
+
+
+/// Prints the old and new hash of an updated ref.
+fn report_ref(ref_name: &str, old_hash: &str, new_hash: &str, verbose: bool) {
+    if !verbose {
+        eprintln!("{} {} {}", old_hash, new_hash, ref_name);
+    } else {
+        eprintln!("Updated {}: {} -> {}", ref_name, &old_hash[..7], &new_hash[..7]);
+    }
+}
+
+
+/// Rewrites a commit and all its ancestors at the current format version.
+///
+/// Commits are visited with an explicit stack, so that long histories do not overflow
+/// the call stack. A commit is rewritten once all of its parents have been.
+///
+/// # Arguments
+/// * `db` - The object store to read the commits from and write the rewritten commits to.
+/// * `tip` - Hash of the commit to start from; empty for an unborn branch.
+/// * `rewritten` - Map of old hash → new hash, filled with every rewritten commit.
+fn rewrite_history(db: &UpgradedObjectDatabase, tip: &str, rewritten: &mut HashMap<String, String>) -> Result<()> {
+    let mut stack: Vec<String> = vec![tip.to_string()];
+    let mut expanded: HashSet<String> = Default::default();
+
+    while let Some(hash) = stack.last().cloned() {
+        if hash.is_empty() || rewritten.contains_key(&hash) {
+            stack.pop();
+            continue;
+        }
+
+        let mut commit = Commit { hash: Some(hash.clone()), data: None };
+        commit.read_commit_from(db)?;
+        let mut commit_data = commit.data.unwrap();
+
+        if expanded.insert(hash.clone()) {
+            // visit the parents first, then come back to this commit
+            stack.extend(commit_data.parent_commits.iter().filter(|parent| !parent.is_empty()).cloned());
+            continue;
+        }
+
+        stack.pop();
+        commit_data.parent_commits = commit_data.parent_commits
+            .iter()
+            .map(|parent| rewritten.get(parent).cloned().unwrap_or_else(|| parent.clone()))
+            .collect();
+
+        let mut new_commit = Commit { hash: None, data: Some(commit_data) };
+        new_commit.write_commit_to(db)?;
+        rewritten.insert(hash, new_commit.hash.unwrap());
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::core::commit::CommitData;
+
+    /// Returns a repository whose `master` and `v1` tag point to a two-commit history written
+    /// at version 1, and both commits.
+    fn version_1_repository(dir: &tempfile::TempDir) -> (Repository, String, String) {
+        let path = dir.path().to_string_lossy();
+        let repo = Repository::init(&path, None, RepositoryFormat::Native, HashAlgorithm::Sha1).unwrap();
+        let mut repo_config = repo.config().clone();
+        repo_config.format_version = 1;
+        config::write_config(repo.git_dir(), &repo_config).unwrap();
+
+        let repo = Repository::open(&path).unwrap();
+        fs::write(dir.path().join("file"), b"first\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        let first = repo.commit("first").unwrap();
+        fs::write(dir.path().join("file"), b"second\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        let second = repo.commit("second").unwrap();
+        reference::update_ref(&repo, "refs/tags/v1", &second, Some("")).unwrap();
+        (repo, first, second)
+    }
+
+    fn read(repo: &Repository, hash: &str) -> CommitData {
+        let mut commit = Commit { hash: Some(hash.to_string()), data: None };
+        commit.read_commit(repo).unwrap();
+        commit.data.unwrap()
+    }
+
+    #[test]
+    fn refs_are_rewritten_to_hashes_covering_parents() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, first, second) = version_1_repository(&dir);
+        migrate(&repo, false).unwrap();
+
+        let repo = Repository::open(&dir.path().to_string_lossy()).unwrap();
+        assert_eq!(repo.config().format_version, FORMAT_VERSION);
+        let new_second = repo.resolve("master").unwrap();
+        assert_ne!(new_second, second);
+        assert_eq!(repo.resolve("v1").unwrap(), new_second);
+
+        let new_first = read(&repo, &new_second).parent_commits[0].clone();
+        assert_ne!(new_first, first);
+        assert_eq!(read(&repo, &new_first).message, read(&repo, &first).message);
+        assert_eq!(read(&repo, &new_second).tree_hash, read(&repo, &second).tree_hash);
+
+        // the new hashes are those a current repository gives the same commits
+        let mut rewritten = Commit { hash: None, data: Some(read(&repo, &new_second)) };
+        rewritten.write_commit(&repo).unwrap();
+        assert_eq!(rewritten.hash.unwrap(), new_second);
+    }
+
+    #[test]
+    fn migrating_again_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, _, _) = version_1_repository(&dir);
+        migrate(&repo, false).unwrap();
+        let repo = Repository::open(&dir.path().to_string_lossy()).unwrap();
+        let refs = reference::get_all_refs(&repo).unwrap();
+
+        migrate(&repo, false).unwrap();
+        assert_eq!(reference::get_all_refs(&repo).unwrap(), refs);
+
+        // an interrupted migration, whose refs were updated but not the version, is resumed
+        let mut repo_config = repo.config().clone();
+        repo_config.format_version = 1;
+        config::write_config(repo.git_dir(), &repo_config).unwrap();
+        let repo = Repository::open(&dir.path().to_string_lossy()).unwrap();
+        migrate(&repo, false).unwrap();
+        assert_eq!(reference::get_all_refs(&repo).unwrap(), refs);
+        assert_eq!(Repository::open(&dir.path().to_string_lossy()).unwrap().config().format_version, FORMAT_VERSION);
+    }
+}
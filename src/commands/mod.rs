@@ -1,9 +1,20 @@
-pub mod add;
-pub mod branch;
-pub mod checkout;
-pub mod commit;
-pub mod init;
-pub mod merge;
-pub mod rm;
-pub mod status;
+pub mod add;
+pub mod branch;
+pub mod cat_file;
+pub mod checkout;
+pub mod commit;
+pub mod commit_tree;
+pub mod fsck;
+pub mod gc;
+pub mod hash_object;
+pub mod init;
+pub mod ls_tree;
+pub mod merge;
+pub mod migrate;
+pub mod repack;
+pub mod rm;
+pub mod show;
+pub mod status;
+pub mod update_ref;
+pub mod write_tree;
 pub mod log;
\ No newline at end of file
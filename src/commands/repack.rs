@@ -0,0 +1,178 @@
+//! Module: repack
+//!
+//! Implements packing of the object store, similar to `git repack -a -d`.
+//! All loose objects and the objects of existing packs are written into a single new pack,
+//! after which the loose files and the old packs are removed.
+//!
+//! Versions of the same file are stored as deltas against each other, up to a maximum
+//! delta chain depth (`pack.depth` in `.git/config`, or `--depth`). Objects are streamed
+//! into the pack one at a time, and large blobs are copied without looking for deltas.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+
+use crate::{core::*, utils::*};
+use crate::core::commit::{Commit, CommitTrait};
+use crate::core::object::ObjectType;
+use crate::core::odb::ObjectDatabase;
+use crate::core::tree::{Tree, TreeEntryType, TreeTrait};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+
+
+/// Moves every object of the repository into a single new pack.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `depth` - Maximum delta chain depth, overriding `pack.depth`; 0 disables deltas.
+/// * `verbose` - If true, displays beautified output instead of just the pack name.
+///
+/// # Behavior
+/// 1. Collects loose and packed objects.
+/// 2. Finds the path of every reachable blob, to pick delta bases among versions of the same file.
+/// 3. Writes them into a new pack and its index.
+/// 4. Removes the loose object files and the previous packs.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if `depth` is longer than the delta chains read from packs
+///   (see `pack::max_read_depth`).
+/// * If an object cannot be read, or the pack cannot be written; nothing is removed then.
+pub fn repack(repo: &Repository, depth: Option<u32>, verbose: bool) -> Result<()> {
+
+    let db = repo.odb();
+    let loose_objects = object::list_loose_objects(repo)?;
+    let old_packs = pack::list_packs(repo)?;
+
+    let names = db.iterate()?;
+
+    if names.is_empty() || (loose_objects.is_empty() && old_packs.len() == 1 && depth.is_none()) {
+        if verbose {
+            eprintln!("Nothing new to pack.");
+        }
+        return Ok(());
+    }
+
+    // only the type and length of every object are kept, payloads are streamed by `write_pack`
+    let mut objects = Vec::with_capacity(names.len());
+    for name in names {
+        let (object_type, size) = object::read_object_info(repo, &name)?;
+        objects.push((name, object_type, size));
+    }
+
+    let max_depth = match depth {
+        Some(depth) if depth > pack::max_read_depth(repo) => {
+            return Err(Error::InvalidArgument(format!(
+                "--depth {} is longer than the delta chains read back ({}); raise pack.depth first.",
+                depth, pack::max_read_depth(repo)
+            )));
+        }
+        Some(depth) => depth,
+        None => repo.config().pack_depth,
+    };
+    let pack_path = pack::write_pack(repo, &objects, &collect_path_hints(repo)?, max_depth)?;
+
+    for name in &loose_objects {
+        let object_path = object::get_object_path(repo, name)?;
+        storage::remove_file(&object_path)?;
+        // the shard folder is only removed once it is empty
+        if let Some(folder) = Path::new(&object_path).parent() {
+            let _ = fs::remove_dir(folder);
+        }
+    }
+    for old_pack in &old_packs {
+        if old_pack != &pack_path {
+            pack::remove_pack(repo, old_pack)?;
+        }
+    }
+
+    if !verbose {
+        eprintln!("{}", pack_path);
+    } else {
+        eprintln!(
+            "Packed {} object(s) into {} ({} loose object(s) removed, {} pack(s) replaced).",
+            objects.len(), pack_path, loose_objects.len(), old_packs.len()
+        );
+    }
+    Ok(())
+}
+
+
+/// Maps every blob reachable from the refs under `refs/` and HEAD to a path it appears at.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+///
+/// # Returns
+/// * `HashMap<String, String>` - Blob hash → relative path (the first one found).
+///
+/// # Errors
+/// * If a reachable commit or tree cannot be read.
+pub fn collect_path_hints(repo: &Repository) -> Result<HashMap<String, String>> {
+    let mut path_hints: HashMap<String, String> = Default::default();
+    let mut visited_commits: HashSet<String> = Default::default();
+    let mut visited_trees: HashSet<String> = Default::default();
+    let mut queue: VecDeque<String> = Default::default();
+
+    queue.push_back(reference::get_current_commit(repo)?);
+    // refs other than branches (e.g. tags written by `update-ref`) may point to any object
+    for (_, hash) in reference::get_all_refs(repo)? {
+        match object::get_object_type(repo, &hash)? {
+            ObjectType::Commit => queue.push_back(hash),
+            ObjectType::Tree => collect_tree_paths(repo, &hash, "", &mut visited_trees, &mut path_hints)?,
+            ObjectType::Blob => {}
+        }
+    }
+
+    while let Some(commit_hash) = queue.pop_front() {
+        if commit_hash.is_empty() || !visited_commits.insert(commit_hash.clone()) {
+            continue;
+        }
+        let mut commit = Commit { hash: Some(commit_hash), data: None };
+        commit.read_commit(repo)?;
+        let commit_data = commit.data.unwrap();
+
+        collect_tree_paths(repo, &commit_data.tree_hash, "", &mut visited_trees, &mut path_hints)?;
+        queue.extend(commit_data.parent_commits);
+    }
+
+    Ok(path_hints)
+}
+
+
+/// Recursively records the path of every blob of a tree.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `tree_hash` - Hash of the tree to walk.
+/// * `tree_path` - Relative path of the tree in the working area.
+/// * `visited_trees` - Trees already walked, which are skipped.
+/// * `path_hints` - Map of blob hash → path being filled.
+fn collect_tree_paths(
+    repo: &Repository,
+    tree_hash: &str,
+    tree_path: &str,
+    visited_trees: &mut HashSet<String>,
+    path_hints: &mut HashMap<String, String>
+) -> Result<()> {
+    if tree_hash.is_empty() || !visited_trees.insert(tree_hash.to_string()) {
+        return Ok(());
+    }
+
+    let mut tree = Tree { hash: Some(tree_hash.to_owned()), data: None };
+    tree.read_tree(repo)?;
+
+    for entry in tree.data.unwrap() {
+        let entry_path = match tree_path {
+            "" => entry.name.clone(),
+            _ => format!("{}/{}", tree_path, entry.name),
+        };
+        match entry.entry_type {
+            TreeEntryType::Tree => collect_tree_paths(repo, &entry.hash, &entry_path, visited_trees, path_hints)?,
+            _ => {
+                path_hints.entry(entry.hash).or_insert(entry_path);
+            }
+        }
+    }
+    Ok(())
+}
@@ -7,50 +7,53 @@
 
 use std::collections::HashSet;
 use std::path::Path;
-use std::process;
 
 use crate::{core::*, utils::*};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
 use crate::core::index::IndexEntry;
 
 
 /// Removes files from the working directory and/or index based on user-specified options.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `files` - List of file/directory paths to remove.
 /// * `recursive` - If true, recursively removes directories and their contents.
 /// * `cached` - If true, only removes files from the index, not from the filesystem.
 /// * `verbose` - If true, displays detailed information about removed files.
 ///
 /// # Behavior
-/// 1. Reads the current index.
+/// 1. Locks and reads the current index.
 /// 2. Registers the files/directories to be removed.
 /// 3. Removes matching entries from the index.
 /// 4. Optionally removes files from the working area.
-/// 5. Writes the updated index back to disk.
+/// 5. Writes the updated index back to disk, releasing the lock.
 ///
-/// # Exits
+/// # Errors
 /// * If any path cannot be canonicalized.
-/// * If any path is outside the repository root.
-pub fn remove(files: Vec<String>, recursive: bool, cached: bool, verbose: bool) {
-    let mut index = index::read_index();
-    let repo_path = utils::pwd();
+/// * `Error::InvalidArgument` if any path is outside the repository root, or is a directory
+///   and `recursive` is false.
+/// * `Error::Locked` if another process holds the index lock.
+/// * If the index cannot be read or written, or a file cannot be removed.
+pub fn remove(repo: &Repository, files: Vec<String>, recursive: bool, cached: bool, verbose: bool) -> Result<()> {
+    let lock = index::lock_index(repo)?;
+    let mut index = index::read_index(repo)?;
+    let repo_path = repo.path();
 
     let mut rm_entries: HashSet<IndexEntry> = Default::default();
 
     for file in &files {
-        let file_path = match Path::new(file).canonicalize() {
-            Ok(p) => p.to_string_lossy().into_owned(),
-            Err(e) => {
-                eprintln!("Error when canonicalizing path {}: {}", file, e);
-                process::exit(1)
-            }
-        };
-        if !utils::is_subpath(&repo_path, &file_path) {
-            eprintln!("File {} does not belong to repository {}.", file_path, repo_path);
-            process::exit(1);
+        let file_path = Path::new(file)
+            .canonicalize()
+            .map_err(Error::io(format!("Error when canonicalizing path {}", file)))?
+            .to_string_lossy()
+            .into_owned();
+        if !utils::is_subpath(repo_path, &file_path) {
+            return Err(Error::InvalidArgument(format!("File {} does not belong to repository {}.", file_path, repo_path)));
         }
 
-        index::register_files(&file_path, &utils::relative_path(&repo_path, &file_path), &mut rm_entries, &recursive);
+        index::register_files(repo, &file_path, &utils::get_relative_path(repo_path, &file_path)?, &mut rm_entries, &recursive)?;
     }
 
     let mut remove_log: HashSet<IndexEntry> = Default::default();
@@ -68,16 +71,17 @@ pub fn remove(files: Vec<String>, recursive: bool, cached: bool, verbose: bool)
     }
 
     if !cached {
+        let git_dir = repo.git_dir();
         for file in &files {
-            if file == &utils::get_git_directory() {
+            if file == git_dir {
                 continue;
             }
-            storage::remove_path(file, recursive);
+            storage::remove_path(repo, file, recursive)?;
             remove_working_log.insert(file.clone());
         }
     }
 
-    index::write_index(&index);
+    index::write_locked_index(repo, lock, &index)?;
 
     if !verbose {
         for entry in &remove_log {
@@ -85,7 +89,7 @@ pub fn remove(files: Vec<String>, recursive: bool, cached: bool, verbose: bool)
         }
     } else {
         eprintln!("Removed {} file(s) from staging area.", remove_log.len());
-        if remove_log.len() != 0 {
+        if !remove_log.is_empty() {
             eprintln!();
             for entry in &remove_log {
                 eprintln!("    \x1b[31mRemove:\x1b[0m {} ({})", entry.path, entry.hash);
@@ -94,7 +98,7 @@ pub fn remove(files: Vec<String>, recursive: bool, cached: bool, verbose: bool)
         }
         if !cached {
             eprintln!("Removed {} file(s) from working area.", remove_working_log.len());
-            if remove_working_log.len() != 0 {
+            if !remove_working_log.is_empty() {
                 eprintln!();
                 for entry in &remove_working_log {
                     eprintln!("    \x1b[31mRemove:\x1b[0m {}", entry);
@@ -104,4 +108,5 @@ pub fn remove(files: Vec<String>, recursive: bool, cached: bool, verbose: bool)
         }
     }
 
+    Ok(())
 }
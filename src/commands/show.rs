@@ -0,0 +1,70 @@
+//! Module: show
+//!
+//! Implements displaying a single commit, similar to `git show`: its metadata in the format
+//! of `log`, followed by the files it added, removed or modified compared to its first parent.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{core::*, utils::*};
+use crate::commands::{log, status};
+use crate::core::commit::{Commit, CommitTrait};
+use crate::core::index::IndexEntry;
+use crate::error::Result;
+use crate::repository::Repository;
+
+
+/// Displays a commit and its changes.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `target` - Revision naming the commit, e.g. `HEAD`, `main~2`, `65df41d`.
+///
+/// # Behavior
+/// 1. Prints the commit metadata, as `log` does.
+/// 2. Prints the files added, removed and modified compared to the first parent; every file
+///    of a root commit is reported as added.
+///
+/// # Errors
+/// * `Error::InvalidRevision` if the revision does not name a commit.
+pub fn show(repo: &Repository, target: String) -> Result<()> {
+    let commit_hash = revision::resolve_commit(repo, &target)?;
+
+    let mut commit = Commit { hash: Some(commit_hash.clone()), data: None };
+    commit.read_commit(repo)?;
+    let commit_data = commit.data.unwrap();
+
+    log::print_commit(&commit_hash, &commit_data);
+
+    let mut commit_entries: HashMap<String, IndexEntry> = Default::default();
+    storage::restore_index_by_tree(repo, &commit_data.tree_hash, repo.path(), &mut commit_entries)?;
+    let entries: HashSet<IndexEntry> = commit_entries.into_values().collect();
+
+    let parent_hash = commit_data.parent_commits.first().cloned().unwrap_or_default();
+    let (add_log, remove_log, modify_log) = status::diff_index_entries_to_commit(repo, &entries, &parent_hash)?;
+
+    let mut add_log: Vec<_> = add_log.into_iter().collect();
+    let mut remove_log: Vec<_> = remove_log.into_iter().collect();
+    let mut modify_log: Vec<_> = modify_log.into_iter().collect();
+    add_log.sort_by(|a, b| a.path.cmp(&b.path));
+    remove_log.sort_by(|a, b| a.path.cmp(&b.path));
+    modify_log.sort_by(|a, b| a.1.path.cmp(&b.1.path));
+
+    eprintln!(
+        "Added {} file(s), Removed {} file(s), Modified {} file(s).",
+        add_log.len(), remove_log.len(), modify_log.len()
+    );
+    if !(add_log.is_empty() && remove_log.is_empty() && modify_log.is_empty()) {
+        eprintln!();
+        for entry in &add_log {
+            eprintln!("    \x1b[32mAdd:\x1b[0m    {} ({})", entry.path, entry.hash);
+        }
+        for entry in &remove_log {
+            eprintln!("    \x1b[31mRemove:\x1b[0m {} ({})", entry.path, entry.hash);
+        }
+        for entry in &modify_log {
+            eprintln!("    \x1b[33mModify:\x1b[0m {} ({} -> {})", entry.0.path, entry.0.hash, entry.1.hash);
+        }
+        eprintln!();
+    }
+    Ok(())
+}
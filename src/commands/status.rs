@@ -1,19 +1,25 @@
 //! Module: status
 //!
-//! Provides functionality to compare the index against the latest commit and display a human-readable status.
-//! Shows which files have been added, removed, or modified since the last commit.
+//! Provides functionality to compare the latest commit, the index and the working area, and display
+//! a human-readable status: the changes staged since the last commit, the changes not staged yet,
+//! and the untracked files. The working area is compared without writing any object.
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fs;
 
 use crate::{core::*, utils::*};
-use crate::core::index::IndexEntry;
+use crate::core::index::{Index, IndexEntry};
 use crate::core::commit::{Commit, CommitTrait};
+use crate::core::reference::Head;
+use crate::error::{Error, Result};
+use crate::repository::{Repository, Status};
 
 
 /// Compares the given index entries with the contents of a specific commit and returns differences.
 ///
 /// # Arguments
+/// * `repo` - The repository to operate on.
 /// * `entries` - A set of index entries currently in the staging area.
 /// * `commit_hash` - The hash of the commit to compare against.
 ///
@@ -22,21 +28,25 @@ use crate::core::commit::{Commit, CommitTrait};
 /// * Set of files added in the index.
 /// * Set of files removed compared to the commit.
 /// * Set of files modified (with both old and new hashes).
-pub fn diff_index_entries_to_commit(entries: &HashSet<IndexEntry>, commit_hash: &str) ->
-    (HashSet<IndexEntry>, HashSet<IndexEntry>, HashSet<(IndexEntry, IndexEntry)>)
+///
+/// # Errors
+/// * If the commit or one of its trees cannot be read.
+#[allow(clippy::type_complexity)]
+pub fn diff_index_entries_to_commit(repo: &Repository, entries: &HashSet<IndexEntry>, commit_hash: &str) ->
+    Result<(HashSet<IndexEntry>, HashSet<IndexEntry>, HashSet<(IndexEntry, IndexEntry)>)>
 {
 
     // let current_branch = reference::get_current_branch()?.to_string();
 
     // let commit_hash: String = reference::get_head(&current_branch);
     let mut commit = Commit { hash: Some(commit_hash.to_string()), data: None };
-    commit.read_commit();
+    commit.read_commit(repo)?;
 
     let commit_root_hash = commit.data.unwrap().tree_hash;
 
     let mut commit_entries: HashMap<String, IndexEntry> = Default::default();
 
-    storage::restore_index_by_tree(&commit_root_hash, &utils::pwd(), &mut commit_entries);
+    storage::restore_index_by_tree(repo, &commit_root_hash, repo.path(), &mut commit_entries)?;
 
     // println!("commit_entries");
     // for commit_entry in &commit_entries {
@@ -51,7 +61,7 @@ pub fn diff_index_entries_to_commit(entries: &HashSet<IndexEntry>, commit_hash:
     let mut remove_log: HashSet<IndexEntry> = Default::default();
     let mut modify_log: HashSet<(IndexEntry, IndexEntry)> = Default::default();
 
-    let repo_path = utils::pwd();
+    let repo_path = repo.path();
 
     // entry.path and commit_entry.path are all RELATIVE PATHS
     for entry in entries {
@@ -59,7 +69,7 @@ pub fn diff_index_entries_to_commit(entries: &HashSet<IndexEntry>, commit_hash:
         let abs_path = format!("{}/{}", repo_path, path);
         match commit_entries.get(&abs_path) {
             Some(commit_entry) => {
-                if commit_entry.hash != entry.hash {
+                if commit_entry.hash != entry.hash || commit_entry.entry_type != entry.entry_type {
                     modify_log.insert((commit_entry.clone(), entry.clone()));
                 }
             }
@@ -75,65 +85,372 @@ pub fn diff_index_entries_to_commit(entries: &HashSet<IndexEntry>, commit_hash:
     }
     for commit_entry_kv in &commit_entries {
         let commit_entry = commit_entry_kv.1;
-        if let None = index_entry_paths.get(&commit_entry.path) {
+        if !index_entry_paths.contains(&commit_entry.path) {
             let log = IndexEntry {
-                path: utils::get_relative_path(&repo_path, commit_entry_kv.0),
-                hash: commit_entry.hash.clone()
+                path: utils::get_relative_path(repo_path, commit_entry_kv.0)?,
+                ..commit_entry.clone()
             };
             remove_log.insert(log);
         }
     }
 
-    (add_log, remove_log, modify_log)
+    Ok((add_log, remove_log, modify_log))
+}
+
+
+/// Compares the working area with the given index, without writing any object.
+///
+/// Files whose stat data matches their index entry are assumed unchanged (see
+/// `IndexEntry::matches_stat`); other tracked files are hashed in memory, and the entries
+/// of those found unchanged get their current stat data, so that the caller can write the
+/// refreshed index back and spare the next command the hashing. Untracked files are not hashed.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `index` - The index to compare against and refresh.
+///
+/// # Returns
+/// A tuple containing:
+/// * Set of paths (relative to the repository root) of untracked files.
+/// * Set of index entries whose file is missing from the working area.
+/// * Set of files modified (with the entry in the index, and the one the file would get if added).
+///
+/// # Errors
+/// * If a file cannot be stat-ed or read.
+#[allow(clippy::type_complexity)]
+pub fn diff_worktree_to_index(repo: &Repository, index: &mut Index) ->
+    Result<(HashSet<String>, HashSet<IndexEntry>, HashSet<(IndexEntry, IndexEntry)>)>
+{
+    let repo_path = repo.path();
+
+    let mut worktree_entries: HashSet<IndexEntry> = Default::default();
+    index::register_files(repo, repo_path, "", &mut worktree_entries, &true)?;
+
+    let mut untracked_log: HashSet<String> = Default::default();
+    let mut delete_log: HashSet<IndexEntry> = Default::default();
+    let mut modify_log: HashSet<(IndexEntry, IndexEntry)> = Default::default();
+
+    let mut worktree_paths: HashSet<String> = Default::default();
+    for worktree_entry in worktree_entries {
+        let path = worktree_entry.path;
+        worktree_paths.insert(path.clone());
+
+        let Some(entry) = index.get_mut(&path) else {
+            untracked_log.insert(path);
+            continue;
+        };
+
+        let file_path = format!("{}/{}", repo_path, path);
+        let meta = fs::symlink_metadata(&file_path).map_err(Error::io(format!("Failed to stat {}", file_path)))?;
+        if entry.matches_stat(&meta) {
+            continue;
+        }
+
+        let hash = blob::hash_blob_file(repo, &file_path)?;
+        let file_entry = IndexEntry::from_metadata(&path, &hash, &file_path, &meta)?;
+        if file_entry.hash != entry.hash || file_entry.entry_type != entry.entry_type {
+            modify_log.insert((entry.clone(), file_entry));
+        } else {
+            entry.stat = file_entry.stat;
+        }
+    }
+
+    for entry in index.values() {
+        if !worktree_paths.contains(&entry.path) {
+            delete_log.insert(entry.clone());
+        }
+    }
+
+    Ok((untracked_log, delete_log, modify_log))
 }
 
 
 /// Displays the current status of the working directory and index compared to the last commit.
 ///
-/// Shows:
-/// - Files added to the index
-/// - Files removed since the last commit
-/// - Files modified (with old and new blob hashes)
+/// Shows the current branch (or the detached HEAD), then three sections:
+/// - Changes to be committed: files added, removed or modified in the index since the last commit
+/// - Changes not staged for commit: tracked files deleted or modified in the working area since they were staged
+/// - Untracked files
 ///
 /// Outputs the result to stderr with colored labels for better readability.
-pub fn status() {
-    let index = index::read_index();
-
-    let mut entries: HashSet<IndexEntry> = Default::default();
-    for kv in &index {
-        entries.insert(kv.1.clone());
-    }
-
-    let commit_hash = reference::get_current_commit();
-
-    let (add_log,
-        remove_log,
-        modify_log) = diff_index_entries_to_commit(&entries, &commit_hash);
-    // else {
-    //     println!("You are in 'detached HEAD' state.");
-    //     println!("Staging area now contains:");
-    //     for entry in entries {
-    //         println!("    {}", entry.path);
-    //     }
-    //     process::exit(0);
-    // };
-
-    eprintln!(
-        "Added {} file(s), Removed {} file(s), Modified {} file(s).", 
-        add_log.len(), remove_log.len(), modify_log.len()
-    );
-    if !(add_log.len() == 0 && remove_log.len() == 0 && modify_log.len() == 0) {
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+///
+/// # Errors
+/// * If the index or the current commit cannot be read, or a file cannot be stat-ed or read.
+pub fn status(repo: &Repository) -> Result<()> {
+    let status = status_core(repo)?;
+
+    match &status.head {
+        Head::Branch(branch) => eprintln!("On branch \x1b[32m{}\x1b[0m", branch),
+        Head::Detached(hash) => eprintln!("\x1b[31mHEAD detached at {}\x1b[0m", &hash[..7.min(hash.len())]),
+    }
+
+    if !(status.added.is_empty() && status.removed.is_empty() && status.modified.is_empty()) {
         eprintln!();
-        for entry in &add_log {
+        eprintln!("Changes to be committed:");
+        for entry in &status.added {
             eprintln!("    \x1b[32mAdd:\x1b[0m    {} ({})", entry.path, entry.hash);
         }
-        for entry in &remove_log {
+        for entry in &status.removed {
             eprintln!("    \x1b[31mRemove:\x1b[0m {} ({})", entry.path, entry.hash);
         }
-        for entry in &modify_log {
-            eprintln!("    \x1b[33mModify:\x1b[0m {} ({} -> {})", entry.0.path, entry.0.hash, entry.1.hash);
+        for (old_entry, new_entry) in &status.modified {
+            print_modified(old_entry, new_entry);
         }
+    }
+
+    if !(status.deleted.is_empty() && status.changed.is_empty()) {
         eprintln!();
+        eprintln!("Changes not staged for commit:");
+        for entry in &status.deleted {
+            eprintln!("    \x1b[31mDelete:\x1b[0m {} ({})", entry.path, entry.hash);
+        }
+        for (old_entry, new_entry) in &status.changed {
+            print_modified(old_entry, new_entry);
+        }
     }
 
-}
\ No newline at end of file
+    if !status.untracked.is_empty() {
+        eprintln!();
+        eprintln!("Untracked files:");
+        for path in &status.untracked {
+            eprintln!("    \x1b[90m{}\x1b[0m", path);
+        }
+    }
+
+    if status.is_clean() {
+        eprintln!("Nothing to commit, working tree clean.");
+    } else {
+        eprintln!();
+    }
+
+    Ok(())
+}
+
+
+/// Prints a modified file: its old and new hashes, or its old and new modes if only the mode changed.
+fn print_modified(old_entry: &IndexEntry, new_entry: &IndexEntry) {
+    if old_entry.hash != new_entry.hash {
+        eprintln!("    \x1b[33mModify:\x1b[0m {} ({} -> {})", new_entry.path, old_entry.hash, new_entry.hash);
+    } else {
+        eprintln!(
+            "    \x1b[33mMode:\x1b[0m   {} ({} -> {})",
+            new_entry.path, old_entry.entry_type.git_mode(), new_entry.entry_type.git_mode()
+        );
+    }
+}
+
+
+/// Core logic for comparing the current commit, the index and the working area.
+///
+/// Entries whose file was hashed and found unchanged are written back with their current
+/// stat data. This is opportunistic: if the index is locked by another command, or cannot be
+/// written, it is left as is.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+///
+/// # Returns
+/// * `Status` - What HEAD points to, the staged changes (index vs commit, as
+///   `diff_index_entries_to_commit`), the unstaged changes and the untracked files
+///   (working area vs index, as `diff_worktree_to_index`), each sorted by path.
+///
+/// # Errors
+/// * If the index or the current commit cannot be read, or a file cannot be stat-ed or read.
+pub fn status_core(repo: &Repository) -> Result<Status> {
+    let head = repo.head()?;
+    let lock = index::lock_index(repo).ok();
+    let mut index = index::read_index(repo)?;
+
+    let stale_index = lock.as_ref().map(|_| index.clone());
+    let (untracked, deleted, changed) = diff_worktree_to_index(repo, &mut index)?;
+    if let (Some(lock), Some(stale_index)) = (lock, stale_index) {
+        if stale_index != index {
+            let _ = index::write_locked_index(repo, lock, &index);
+        }
+    }
+
+    let entries: HashSet<IndexEntry> = index.into_values().collect();
+    let commit_hash = reference::get_current_commit(repo)?;
+    let (added, removed, modified) = diff_index_entries_to_commit(repo, &entries, &commit_hash)?;
+
+    let mut untracked: Vec<String> = untracked.into_iter().collect();
+    untracked.sort();
+
+    Ok(Status {
+        head,
+        added: sorted(added),
+        removed: sorted(removed),
+        modified: sorted_pairs(modified),
+        deleted: sorted(deleted),
+        changed: sorted_pairs(changed),
+        untracked,
+    })
+}
+
+
+/// Sorts a set of entries by path.
+fn sorted(entries: HashSet<IndexEntry>) -> Vec<IndexEntry> {
+    let mut entries: Vec<IndexEntry> = entries.into_iter().collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+
+/// Sorts a set of `(old, new)` entry pairs by the path of the new entry.
+fn sorted_pairs(entries: HashSet<(IndexEntry, IndexEntry)>) -> Vec<(IndexEntry, IndexEntry)> {
+    let mut entries: Vec<(IndexEntry, IndexEntry)> = entries.into_iter().collect();
+    entries.sort_by(|a, b| a.1.path.cmp(&b.1.path));
+    entries
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::core::config::RepositoryFormat;
+    use crate::core::index::FileStat;
+    use crate::core::tree::TreeEntryType;
+    use crate::utils::hash::HashAlgorithm;
+
+    /// Rewrites a file with the same content, dated an hour ago so that it is not racily clean.
+    fn touch(path: &std::path::Path) {
+        let content = fs::read(path).unwrap();
+        fs::write(path, content).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600)).unwrap();
+    }
+
+    fn recorded_stat(repo: &Repository, path: &str) -> FileStat {
+        index::read_index(repo).unwrap()[path].stat
+    }
+
+    fn current_stat(dir: &tempfile::TempDir, path: &str) -> FileStat {
+        FileStat::from_metadata(&fs::symlink_metadata(dir.path().join(path)).unwrap())
+    }
+
+    fn paths(entries: &[IndexEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn staged_unstaged_and_untracked_changes_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Native, HashAlgorithm::Sha1).unwrap();
+        for name in ["modified", "removed", "edited", "deleted", "script"] {
+            fs::write(dir.path().join(name), format!("{}\n", name)).unwrap();
+        }
+        repo.add(&["modified", "removed", "edited", "deleted", "script"]).unwrap();
+        let commit_hash = repo.commit("base").unwrap();
+        let status = repo.status().unwrap();
+        assert!(status.is_clean());
+        assert_eq!(status.head, Head::Branch("master".to_string()));
+
+        // staged: index vs HEAD
+        fs::write(dir.path().join("added"), b"added\n").unwrap();
+        fs::write(dir.path().join("modified"), b"modified again\n").unwrap();
+        repo.add(&["added", "modified"]).unwrap();
+        let mut staged = index::read_index(&repo).unwrap();
+        staged.remove("removed");
+        index::write_index(&repo, &staged).unwrap();
+        fs::remove_file(dir.path().join("removed")).unwrap();
+        // not staged: working area vs index
+        fs::write(dir.path().join("edited"), b"edited again\n").unwrap();
+        fs::remove_file(dir.path().join("deleted")).unwrap();
+        fs::set_permissions(dir.path().join("script"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.path().join("untracked"), b"untracked\n").unwrap();
+
+        let status = repo.status().unwrap();
+        assert_eq!(paths(&status.added), ["added"]);
+        assert_eq!(paths(&status.removed), ["removed"]);
+        assert_eq!(status.modified.len(), 1);
+        assert_eq!(status.modified[0].1.path, "modified");
+        assert_eq!(paths(&status.deleted), ["deleted"]);
+        assert_eq!(status.changed.len(), 2);
+        let (old_edited, new_edited) = &status.changed[0];
+        assert_eq!(new_edited.path, "edited");
+        assert_ne!(old_edited.hash, new_edited.hash);
+        let (old_script, new_script) = &status.changed[1];
+        assert_eq!(old_script.hash, new_script.hash);
+        assert_eq!((&old_script.entry_type, &new_script.entry_type), (&TreeEntryType::Blob, &TreeEntryType::Bexe));
+        assert_eq!(status.untracked, ["untracked"]);
+        assert!(!status.is_clean());
+
+        fs::remove_file(dir.path().join("untracked")).unwrap();
+        fs::write(dir.path().join("deleted"), b"deleted\n").unwrap();
+        repo.add(&["."]).unwrap();
+        repo.commit("all").unwrap();
+        repo.checkout(&commit_hash, false).unwrap();
+        assert_eq!(repo.status().unwrap().head, Head::Detached(commit_hash));
+    }
+
+    #[test]
+    fn verified_clean_entries_are_refreshed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        fs::write(dir.path().join("file"), b"content\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        repo.commit("file").unwrap();
+        repo.create_branch("other", None).unwrap();
+
+        touch(&dir.path().join("file"));
+        assert_ne!(recorded_stat(&repo, "file"), current_stat(&dir, "file"));
+        assert!(repo.status().unwrap().is_clean());
+        assert_eq!(recorded_stat(&repo, "file"), current_stat(&dir, "file"));
+
+        // the safety check of checkout writes the refreshed index back even when it refuses
+        touch(&dir.path().join("file"));
+        fs::write(dir.path().join("untracked"), b"untracked\n").unwrap();
+        assert!(matches!(repo.checkout("other", false), Err(Error::DirtyWorktree(_))));
+        assert_eq!(recorded_stat(&repo, "file"), current_stat(&dir, "file"));
+    }
+
+    #[test]
+    fn racily_clean_entries_are_rehashed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, b"before\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        repo.commit("file").unwrap();
+
+        // the file changes without its stat data changing: only its timestamp can tell
+        fs::write(&path, b"after!\n").unwrap();
+        let stale_entry = |modified: SystemTime| {
+            fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+            let mut index = index::read_index(&repo).unwrap();
+            index.get_mut("file").unwrap().stat = current_stat(&dir, "file");
+            index::write_index(&repo, &index).unwrap();
+        };
+
+        stale_entry(SystemTime::now() - Duration::from_secs(3600));
+        assert!(repo.status().unwrap().is_clean());
+
+        stale_entry(SystemTime::now() + Duration::from_secs(3600));
+        assert_eq!(index::read_index(&repo).unwrap()["file"].stat, FileStat::default());
+        let status = repo.status().unwrap();
+        assert_eq!(status.changed.len(), 1);
+        assert_eq!(status.changed[0].1.hash, blob::hash_blob_file(&repo, &path.to_string_lossy()).unwrap());
+    }
+
+    #[test]
+    fn locked_indexes_are_not_refreshed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        fs::write(dir.path().join("file"), b"content\n").unwrap();
+        repo.add(&["file"]).unwrap();
+
+        touch(&dir.path().join("file"));
+        let recorded = recorded_stat(&repo, "file");
+        let lock = index::lock_index(&repo).unwrap();
+        assert_eq!(repo.status().unwrap().added.len(), 1);
+        assert_eq!(recorded_stat(&repo, "file"), recorded);
+        drop(lock);
+    }
+}
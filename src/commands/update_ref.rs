@@ -0,0 +1,47 @@
+//! Module: update_ref
+//!
+//! Implements safely moving a ref, similar to `git update-ref`. When the old value is given,
+//! the ref is only updated if it still holds that value, which lets scripts detect that
+//! another process moved it in the meantime.
+
+use crate::core::*;
+use crate::core::object::ObjectType;
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+
+
+/// Points a ref to a new object, optionally checking its current value first.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+/// * `ref_name` - Full name of the ref (e.g. `refs/heads/main`), or `HEAD`.
+/// * `new_value` - Revision of the object to store.
+/// * `old_value` - If given, revision of the object the ref must currently point to; an
+///   empty string or an all-zero hash requires the ref not to exist yet.
+/// * `verbose` - If true, displays beautified output instead of nothing on success.
+///
+/// # Errors
+/// * `Error::InvalidRevision` if a revision is invalid, or a branch or `HEAD` would point to
+///   something else than a commit.
+/// * `Error::StaleRef` if the ref does not hold `old_value`, `Error::Locked` if it is locked by
+///   another process.
+pub fn update_ref(repo: &Repository, ref_name: String, new_value: String, old_value: Option<String>, verbose: bool) -> Result<()> {
+    let new_hash = revision::resolve_revision(repo, &new_value)?;
+    let is_branch = ref_name == "HEAD" || ref_name.starts_with("refs/heads/");
+    if is_branch && object::get_object_type(repo, &new_hash)? != ObjectType::Commit {
+        return Err(Error::InvalidRevision(format!("{} cannot point to {}, which is not a commit.", ref_name, new_value)));
+    }
+
+    let expected_hash = match old_value {
+        Some(old_value) if old_value.trim_start_matches('0').is_empty() => Some(String::new()),
+        Some(old_value) => Some(revision::resolve_revision(repo, &old_value)?),
+        None => None,
+    };
+
+    reference::update_ref(repo, &ref_name, &new_hash, expected_hash.as_deref())?;
+
+    if verbose {
+        eprintln!("Updated {} to {}.", ref_name, new_hash);
+    }
+    Ok(())
+}
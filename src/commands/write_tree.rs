@@ -0,0 +1,69 @@
+//! Module: write_tree
+//!
+//! Implements turning the index into tree objects without committing, similar to
+//! `git write-tree`. The hash of the root tree is printed to stdout.
+
+use std::collections::HashSet;
+
+use crate::core::*;
+use crate::core::index::IndexEntry;
+use crate::commands::commit;
+use crate::error::Result;
+use crate::repository::Repository;
+
+
+/// Writes the trees of the current index.
+///
+/// File types (regular, executable, symlink) are taken from the index, as `commit` does.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+///
+/// # Returns
+/// * `String` - Hash of the root tree.
+///
+/// # Errors
+/// * If the index cannot be read, or a tree cannot be written.
+pub fn write_tree_core(repo: &Repository) -> Result<String> {
+    let entries: HashSet<IndexEntry> = index::read_index(repo)?.into_values().collect();
+    commit::write_tree(repo, &entries, None)
+}
+
+
+/// Writes the trees of the current index and prints the hash of the root tree.
+///
+/// # Arguments
+/// * `repo` - The repository to operate on.
+///
+/// # Errors
+/// * See `write_tree_core`.
+pub fn write_tree(repo: &Repository) -> Result<()> {
+    println!("{}", write_tree_core(repo)?);
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+    use crate::core::config::RepositoryFormat;
+    use crate::utils::hash::HashAlgorithm;
+
+    #[test]
+    fn index_trees_are_written_as_git_does() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        fs::write(dir.path().join("hello"), b"hello\n").unwrap();
+        fs::create_dir(dir.path().join("dir")).unwrap();
+        fs::write(dir.path().join("dir/nested"), b"nested\n").unwrap();
+        fs::set_permissions(dir.path().join("dir/nested"), fs::Permissions::from_mode(0o755)).unwrap();
+        repo.add(&["hello", "dir"]).unwrap();
+
+        assert_eq!(write_tree_core(&repo).unwrap(), "c08c3e075e99c9cfba0c9ae0e4f43a911d5c58d3");
+        // no commit is created
+        assert_eq!(reference::get_current_commit(&repo).unwrap(), "");
+    }
+}
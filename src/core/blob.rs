@@ -4,11 +4,20 @@
 //! file blobs in a Git-like object storage system. Blobs represent file content,
 //! and can be regular files or symbolic links.
 
-use std::{fs, process};
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::os::unix::fs::MetadataExt;
 
-use crate::utils::{hash, serialize, storage};
-use super::{object::*, tree::TreeEntryType};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+use crate::utils::{hash, storage, utils};
+use super::{config::RepositoryFormat, object::*, odb::ObjectDatabase, tree::TreeEntryType};
+
+/// Size of the chunks in which large files are read, hashed and compressed.
+const CHUNK_SIZE: usize = 64 * 1024;
 
 /// Represents a Git blob object, which stores file content.
 ///
@@ -20,58 +29,87 @@ pub struct Blob {
 }
 
 pub trait BlobTrait {
-    fn read_blob(&mut self);
-    fn write_blob(&mut self);
-    fn calculate_hash(&mut self);
+    fn read_blob(&mut self, repo: &Repository) -> Result<()>;
+    fn write_blob(&mut self, repo: &Repository) -> Result<()>;
+    fn calculate_hash(&mut self, repo: &Repository) -> Result<()>;
+    fn read_blob_from(&mut self, db: &dyn ObjectDatabase) -> Result<()>;
+    fn write_blob_to(&mut self, db: &dyn ObjectDatabase) -> Result<()>;
 }
 
 
 impl BlobTrait for Blob {
 
-    /// Reads the blob object from storage and populates `self.data`.
+    /// Reads the blob object from the repository's object store and populates `self.data`.
     ///
-    /// Requires that `self.hash` is set and `self.data` is empty.
-    fn read_blob(&mut self) {
+    /// Requires that `self.hash` is set and `self.data` is empty, or returns `Error::InvalidArgument`.
+    fn read_blob(&mut self, repo: &Repository) -> Result<()> {
+        self.read_blob_from(&repo.odb())
+    }
 
-        assert!(self.hash.is_none() == false);
-        assert!(self.data.is_none() == true);
 
-        let hash = self.hash.as_ref().unwrap();
-        
-        let raw_content = read_object_file(hash);
-        let full_content = serialize::deserialize(&raw_content);
+    /// Serializes and writes the blob to the repository's object store.
+    ///
+    /// Calculates the hash if not already present. Prepends the type header of the repository format.
+    fn write_blob(&mut self, repo: &Repository) -> Result<()> {
+        if self.hash.is_none() {
+            self.calculate_hash(repo)?;
+        }
+        self.write_blob_to(&repo.odb())
+    }
+
 
-        assert!(full_content.len() >= 4);
-        assert!(full_content.starts_with(b"BLOB"));
-        
-        self.data = Some(full_content[4..].to_vec());
+    /// Computes the SHA-1 hash for the blob's content.
+    ///
+    /// Native repositories hash the raw content, Git repositories hash the content
+    /// together with its `blob <length>\0` header.
+    fn calculate_hash(&mut self, repo: &Repository) -> Result<()> {
+        let Some(data) = self.data.as_ref() else {
+            return Err(Error::InvalidArgument("calculate_hash: the blob has no data.".to_string()));
+        };
+        self.hash = Some(hash_blob_data(&repo.odb(), data));
+        Ok(())
     }
 
 
-    /// Serializes and writes the blob to storage.
+    /// Reads the blob object from the given object database and populates `self.data`.
     ///
-    /// Calculates the hash if not already present. Prepends "BLOB" as a type header.
-    fn write_blob(&mut self) {
+    /// Requires that `self.hash` is set and `self.data` is empty, or returns `Error::InvalidArgument`.
+    fn read_blob_from(&mut self, db: &dyn ObjectDatabase) -> Result<()> {
 
-        assert!(self.data.is_none() == false);
+        let (Some(hash), None) = (self.hash.as_ref(), self.data.as_ref()) else {
+            return Err(Error::InvalidArgument("read_blob: the blob needs a hash and no data.".to_string()));
+        };
 
+        self.data = Some(read_object_payload_from(db, hash, ObjectType::Blob)?);
+        Ok(())
+    }
+
+
+    /// Serializes and writes the blob to the given object database, in the database's format.
+    ///
+    /// Calculates the hash if not already present.
+    fn write_blob_to(&mut self, db: &dyn ObjectDatabase) -> Result<()> {
+
+        let Some(data) = self.data.as_ref() else {
+            return Err(Error::InvalidArgument("write_blob: the blob has no data.".to_string()));
+        };
         if self.hash.is_none() {
-            self.calculate_hash();
+            self.hash = Some(hash_blob_data(db, data));
         }
 
-        let data = self.data.as_ref().unwrap();
-        let mut full_content = b"BLOB".to_vec();
-        full_content.extend(data);
-        let raw_content = serialize::serialize(&full_content);
+        let full_content = encode_object_as(db.format(), ObjectType::Blob, data);
 
-        write_object_file(self.hash.as_ref().unwrap(), &raw_content);
+        db.put(self.hash.as_ref().unwrap(), &full_content)
     }
+}
 
-    
-    /// Computes the SHA-1 hash for the blob's content.
-    fn calculate_hash(&mut self) {
-        assert!(self.data.is_none() == false);
-        self.hash = Some(hash::sha1(self.data.as_ref().unwrap()));
+
+/// Computes the hash of a blob's content as named in the given database (see `calculate_hash`).
+fn hash_blob_data(db: &dyn ObjectDatabase, data: &[u8]) -> String {
+    let algorithm = db.hash_algorithm();
+    match db.format() {
+        RepositoryFormat::Native => algorithm.digest(data),
+        RepositoryFormat::Git => algorithm.digest(&encode_object_as(db.format(), ObjectType::Blob, data)),
     }
 }
 
@@ -83,41 +121,184 @@ impl BlobTrait for Blob {
 ///
 /// # Arguments
 ///
+/// * `repo` - The repository the blob is hashed for.
 /// * `file_path` - The path to the file or symlink.
 ///
-/// # Panics / Exits
+/// # Errors
 ///
-/// This function prints an error and exits if the file cannot be read or stat-ed.
-pub fn get_blob_from_file(file_path: &str) -> Blob {
-    let meta = fs::symlink_metadata(file_path).unwrap_or_else(|e| {
-        eprintln!("Error when stat file {} : {}", file_path, e);
-        process::exit(1)
-    });
-
-    let blob_data = if meta.file_type().is_symlink() {
+/// `Error::Io` if the file cannot be read or stat-ed.
+pub fn get_blob_from_file(repo: &Repository, file_path: &str) -> Result<Blob> {
+    let blob_data = if is_symlink(file_path)? {
         // This is a SymLink
-        match fs::read_link(file_path) {
-            Ok(target_path) => target_path.to_string_lossy().to_string().into_bytes(),
-            Err(e) => {
-                eprintln!("Error when reading symlink {} : {}", file_path, e);
-                process::exit(1)
-            }
-        }
+        fs::read_link(file_path)
+            .map_err(Error::io(format!("Error when reading symlink {}", file_path)))?
+            .to_string_lossy()
+            .to_string()
+            .into_bytes()
     } else {
         // This is an ordinary file
-        match storage::read_file(file_path) {
-            Ok(res) => res,
-            Err(e) => {
-                eprintln!("Error when reading blob file {} : {}", file_path, e);
-                process::exit(1)
-            }
-        }
+        storage::read_file(file_path).map_err(Error::io(format!("Error when reading blob file {}", file_path)))?
     };
 
-    Blob {
-        hash: Some(hash::sha1(&blob_data)),
-        data: Some(blob_data),
+    let mut blob = Blob { hash: None, data: Some(blob_data) };
+    blob.calculate_hash(repo)?;
+    Ok(blob)
+}
+
+
+/// Opens a regular file for streaming, returning it with its length.
+///
+/// # Errors
+/// * `Error::Io` if the file cannot be opened or stat-ed.
+fn open_blob_file(file_path: &str) -> Result<(fs::File, u64)> {
+    let file = fs::File::open(file_path).map_err(Error::io(format!("Error when reading blob file {}", file_path)))?;
+    let length = file.metadata()
+        .map(|meta| meta.len())
+        .map_err(Error::io(format!("Error when stat file {}", file_path)))?;
+    Ok((file, length))
+}
+
+
+/// Reads a file in chunks of `CHUNK_SIZE` bytes, passing each chunk to `consume`.
+///
+/// # Arguments
+/// * `file_path` - Path of the file (for error messages).
+/// * `file` - The opened file.
+/// * `length` - Length of the file when it was opened.
+/// * `consume` - Called with every chunk, in order.
+///
+/// # Errors
+/// * `Error::Io` if reading fails or `consume` fails.
+/// * `Error::InvalidState` if the file length changed while it was being read.
+fn read_in_chunks(
+    file_path: &str,
+    file: fs::File,
+    length: u64,
+    mut consume: impl FnMut(&[u8]) -> Result<()>
+) -> Result<()> {
+    let mut reader = BufReader::with_capacity(CHUNK_SIZE, file);
+    let mut total: u64 = 0;
+    loop {
+        let chunk = reader.fill_buf().map_err(Error::io(format!("Error when reading blob file {}", file_path)))?;
+        if chunk.is_empty() {
+            break;
+        }
+        consume(chunk)?;
+        let chunk_length = chunk.len();
+        total += chunk_length as u64;
+        reader.consume(chunk_length);
     }
+    if total != length {
+        return Err(Error::InvalidState(format!("File {} changed while being read.", file_path)));
+    }
+    Ok(())
+}
+
+
+/// Computes the hash a file would have as a blob, reading it in fixed-size chunks.
+///
+/// Symlinks are hashed by their target, as in `get_blob_from_file`.
+///
+/// # Arguments
+///
+/// * `repo` - The repository the blob is hashed for.
+/// * `file_path` - The path to the file or symlink.
+///
+/// # Errors
+///
+/// `Error::Io` if the file cannot be read or stat-ed.
+///
+/// # Returns
+///
+/// The hash of the blob.
+pub fn hash_blob_file(repo: &Repository, file_path: &str) -> Result<String> {
+    if is_symlink(file_path)? {
+        return Ok(get_blob_from_file(repo, file_path)?.hash.unwrap());
+    }
+
+    let (file, length) = open_blob_file(file_path)?;
+    let config = repo.config();
+    let mut hasher = hash::StreamHasher::new(config.hash_algorithm);
+    if config.format == RepositoryFormat::Git {
+        hasher.update(&encode_object_header(repo, ObjectType::Blob, length));
+    }
+    read_in_chunks(file_path, file, length, |chunk| {
+        hasher.update(chunk);
+        Ok(())
+    })?;
+
+    Ok(hasher.finish())
+}
+
+
+/// Stores a file as a blob, reading, hashing and compressing it in fixed-size chunks.
+///
+/// The compressed object is first written to a temporary file in `.git/objects/`,
+/// then flushed to disk and renamed to its final path once the hash is known, so memory
+/// use does not depend on the size of the file.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to store the blob in.
+/// * `file_path` - The path to the file or symlink.
+///
+/// # Errors
+///
+/// `Error::Io` if the file cannot be read or the object cannot be written.
+///
+/// # Returns
+///
+/// The hash of the stored blob.
+pub fn write_blob_file(repo: &Repository, file_path: &str) -> Result<String> {
+    if is_symlink(file_path)? {
+        let mut blob = get_blob_from_file(repo, file_path)?;
+        blob.write_blob(repo)?;
+        return Ok(blob.hash.unwrap());
+    }
+
+    let write_error = || Error::io(format!("Failed to write blob of {}", file_path));
+
+    let (file, length) = open_blob_file(file_path)?;
+    let header = encode_object_header(repo, ObjectType::Blob, length);
+
+    let db = repo.odb();
+    let mut hasher = hash::StreamHasher::new(db.hash_algorithm());
+    if db.format() == RepositoryFormat::Git {
+        hasher.update(&header);
+    }
+
+    let objects_path = repo.git_dir().to_string() + "/objects";
+    let temp_file = tempfile::NamedTempFile::new_in(&objects_path).map_err(write_error())?;
+    let mut encoder = ZlibEncoder::new(BufWriter::new(temp_file), Compression::default());
+    encoder.write_all(&header).map_err(write_error())?;
+
+    read_in_chunks(file_path, file, length, |chunk| {
+        hasher.update(chunk);
+        encoder.write_all(chunk).map_err(write_error())
+    })?;
+
+    let temp_file = encoder.finish()
+        .and_then(|writer| writer.into_inner().map_err(|e| e.into_error()))
+        .map_err(write_error())?;
+    let hash = hasher.finish();
+
+    if !db.contains(&hash)? {
+        let object_path = get_object_path(repo, &hash)?;
+        storage::create_directory(&utils::split_path_by_last(&object_path).0)?;
+        storage::persist_file(temp_file, &object_path).map_err(write_error())?;
+    }
+
+    Ok(hash)
+}
+
+
+/// Checks whether a path is a symlink (without following it).
+///
+/// # Errors
+/// * `Error::Io` if the path cannot be stat-ed.
+fn is_symlink(file_path: &str) -> Result<bool> {
+    let meta = fs::symlink_metadata(file_path).map_err(Error::io(format!("Error when stat file {}", file_path)))?;
+    Ok(meta.file_type().is_symlink())
 }
 
 
@@ -132,28 +313,38 @@ pub fn get_blob_from_file(file_path: &str) -> Blob {
 ///
 /// * `blob_path` - The path to the file or symlink.
 ///
-/// # Panics / Exits
+/// # Errors
 ///
-/// Prints an error and exits the process for unsupported file types or on I/O failure.
-pub fn get_blob_type(blob_path: &str) -> TreeEntryType {
-    let meta = fs::symlink_metadata(blob_path).unwrap_or_else(|e| {
-        eprintln!("Failed to stat {}: {}", blob_path, e);
-        std::process::exit(1);
-    });
+/// `Error::InvalidArgument` for unsupported file types, `Error::Io` on I/O failure.
+pub fn get_blob_type(blob_path: &str) -> Result<TreeEntryType> {
+    let meta = fs::symlink_metadata(blob_path).map_err(Error::io(format!("Failed to stat {}", blob_path)))?;
+    get_blob_type_from_metadata(blob_path, &meta)
+}
+
 
+/// Determines the type of the blob from already fetched metadata (see `get_blob_type`).
+///
+/// # Arguments
+///
+/// * `blob_path` - The path to the file or symlink (for error messages).
+/// * `meta` - Metadata of the file, not following symlinks.
+///
+/// # Errors
+///
+/// `Error::InvalidArgument` for unsupported file types.
+pub fn get_blob_type_from_metadata(blob_path: &str, meta: &fs::Metadata) -> Result<TreeEntryType> {
     let file_type = meta.file_type();
 
     if file_type.is_symlink() {
-        TreeEntryType::Bsym
+        Ok(TreeEntryType::Bsym)
     } else if file_type.is_file() {
         let mode = meta.mode();
         if (mode & 0o111) != 0 {
-            TreeEntryType::Bexe
+            Ok(TreeEntryType::Bexe)
         } else {
-            TreeEntryType::Blob
+            Ok(TreeEntryType::Blob)
         }
     } else {
-        eprintln!("Unsupported file type: {}", blob_path);
-        std::process::exit(1);
+        Err(Error::InvalidArgument(format!("Unsupported file type: {}", blob_path)))
     }
 }
\ No newline at end of file
@@ -3,15 +3,18 @@
 //! Provides structures and logic for reading, writing, and comparing commit objects,
 //! including traversal, merge base identification, and detecting uncommitted changes.
 
-use std::{collections::{HashMap, HashSet, VecDeque}, i32::MAX, process};
+use std::collections::{HashMap, HashSet, VecDeque};
 
-use crate::{commands::*, utils::*};
-use super::{index::IndexEntry, object::*, reference, tree::TreeEntry};
+use crate::{commands::*, utils::{hash::HashAlgorithm, *}};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+use super::{config::RepositoryFormat, index::{Index, IndexEntry}, object::*, odb::ObjectDatabase, reference, tree::TreeEntry};
 
 /// Struct holding all metadata associated with a commit.
-#[derive(Default)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct CommitData {
-    pub message: String,
+    /// Raw bytes of the message, which need not be valid UTF-8.
+    pub message: Vec<u8>,
     pub user: String,
     pub time: String,
     pub tree_hash: String,
@@ -28,94 +31,381 @@ pub struct Commit {
 }
 
 pub trait CommitTrait {
-    fn read_commit(&mut self);
-    fn write_commit(&mut self);
-    fn calculate_hash(&mut self);
+    fn read_commit(&mut self, repo: &Repository) -> Result<()>;
+    fn write_commit(&mut self, repo: &Repository) -> Result<()>;
+    fn calculate_hash(&mut self, repo: &Repository) -> Result<()>;
+    fn read_commit_from(&mut self, db: &dyn ObjectDatabase) -> Result<()>;
+    fn write_commit_to(&mut self, db: &dyn ObjectDatabase) -> Result<()>;
 }
 
 impl CommitTrait for Commit {
 
-    /// Reads the commit object from storage and populates `self.data`.
+    /// Reads the commit object from the repository's object store and populates `self.data`.
     ///
-    /// Requires that `self.hash` is set and `self.data` is empty.
-    fn read_commit(&mut self) {
+    /// Requires that `self.hash` is set and `self.data` is empty, or returns `Error::InvalidArgument`.
+    fn read_commit(&mut self, repo: &Repository) -> Result<()> {
+        self.read_commit_from(&repo.odb())
+    }
+
+
+    /// Serializes and writes the commit to the repository's object store.
+    ///
+    /// Calculates the hash if not already present. Prepends the type header of the repository format.
+    fn write_commit(&mut self, repo: &Repository) -> Result<()> {
+        if self.hash.is_none() {
+            self.calculate_hash(repo)?;
+        }
+        self.write_commit_to(&repo.odb())
+    }
 
-        assert!(self.hash.is_none() == false);
-        assert!(self.data.is_none() == true);
 
-        let hash = self.hash.as_ref().unwrap();
+    /// Computes the SHA-1 hash for the commit's content.
+    fn calculate_hash(&mut self, repo: &Repository) -> Result<()> {
+        let Some(data) = self.data.as_ref() else {
+            return Err(Error::InvalidArgument("calculate_hash: the commit has no data.".to_string()));
+        };
+        self.hash = Some(hash_commit_data(&repo.odb(), data)?);
+        Ok(())
+    }
 
-        if hash == "" {
+
+    /// Reads the commit object from the given object database and populates `self.data`.
+    ///
+    /// Requires that `self.hash` is set and `self.data` is empty, or returns `Error::InvalidArgument`.
+    fn read_commit_from(&mut self, db: &dyn ObjectDatabase) -> Result<()> {
+
+        let (Some(hash), None) = (self.hash.as_ref(), self.data.as_ref()) else {
+            return Err(Error::InvalidArgument("read_commit: the commit needs a hash and no data.".to_string()));
+        };
+
+        if hash.is_empty() {
             self.data = Some(Default::default());
-            return ;
+            return Ok(());
         }
-        
-        let raw_content = read_object_file(hash);
-        let vecu8_content = serialize::deserialize(&raw_content);
-        let full_content = std::str::from_utf8(&vecu8_content).expect("Invalid UTF-8");
-
-        assert!(full_content.len() >= 4);
-        assert!(full_content.starts_with("CMIT"));
-
-        let parts: Vec<&str> = full_content[4..].split('\0').collect();
-
-        let data: CommitData = CommitData {  
-            message:    parts[0].to_string(),
-            user:       parts[1].to_string(),
-            time:       parts[2].to_string(),
-            tree_hash:  parts[3].to_string(),
-            parent_commits: parts[4].split('&').map(|s| s.to_string()).collect(),
-        };
 
-        self.data = Some(data);
+        let payload = read_object_payload_from(db, hash, ObjectType::Commit)?;
 
+        let data = parse_commit_payload(db.format(), &payload)
+            .map_err(|e| Error::Corrupt(format!("corrupt commit {}: {}", hash, e)))?;
+
+        self.data = Some(data);
+        Ok(())
     }
 
 
-    /// Serializes and writes the commit to storage.
+    /// Serializes and writes the commit to the given object database, in the database's format.
     ///
-    /// Calculates the hash if not already present. Prepends "CMIT" as a type header.
-    fn write_commit(&mut self) {
-
-        assert!(self.data.is_none() == false);
+    /// Calculates the hash if not already present.
+    fn write_commit_to(&mut self, db: &dyn ObjectDatabase) -> Result<()> {
 
+        let Some(commit_data) = self.data.as_ref() else {
+            return Err(Error::InvalidArgument("write_commit: the commit has no data.".to_string()));
+        };
         if self.hash.is_none() {
-            self.calculate_hash();
+            self.hash = Some(hash_commit_data(db, commit_data)?);
         }
 
-        let commit_data = self.data.as_ref().unwrap();
-        let data: String = format!("{}\0{}\0{}\0{}\0{}", 
-            commit_data.message,
-            commit_data.user,
-            commit_data.time,
-            commit_data.tree_hash,
-            commit_data.parent_commits.join("&")
-        );
+        let payload = serialize_commit(db.format(), db.format_version(), commit_data)?;
+        let full_content = encode_object_as(db.format(), ObjectType::Commit, &payload);
+
+        db.put(self.hash.as_ref().unwrap(), &full_content)
+    }
+}
+
+
+/// Marker opening the payload of native commits in the field-based encoding,
+/// followed by the encoding version and a newline.
+///
+/// Legacy payloads start with the message itself; one starting with this marker would need
+/// an empty message and a user name holding a newline, which `commit` never writes.
+const NATIVE_COMMIT_MARKER: &[u8] = b"\0version ";
 
-        let full_content = "CMIT".to_string() + &data;
-        let raw_content = serialize::serialize(&full_content.as_bytes());
+/// Version of the field-based native commit encoding (legacy commits, without a marker, are version 1).
+const NATIVE_COMMIT_ENCODING: u32 = 2;
 
-        write_object_file(self.hash.as_ref().unwrap(), &raw_content);
+
+/// Computes the hash of a commit as named in the given database (see `calculate_hash`).
+///
+/// Native repositories before format version 2 hash the commit without its parents;
+/// later versions hash the whole payload, so that a commit ID commits to its history.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if the commit cannot be encoded (see `serialize_commit`).
+fn hash_commit_data(db: &dyn ObjectDatabase, commit_data: &CommitData) -> Result<String> {
+    let algorithm = db.hash_algorithm();
+    let format = db.format();
+    let version = db.format_version();
+    Ok(match format {
+        RepositoryFormat::Native if version < 2 => legacy_native_hash(algorithm, commit_data),
+        RepositoryFormat::Native => algorithm.digest(&serialize_commit(format, version, commit_data)?),
+        RepositoryFormat::Git => {
+            algorithm.digest(&encode_object_as(format, ObjectType::Commit, &serialize_commit(format, version, commit_data)?))
+        }
+    })
+}
+
+
+/// Computes the hash a native commit is named with before format version 2,
+/// which covers message, user, time and tree, but not the parents.
+///
+/// # Arguments
+/// * `algorithm` - The hash algorithm of the repository.
+/// * `commit_data` - Metadata of the commit.
+pub fn legacy_native_hash(algorithm: HashAlgorithm, commit_data: &CommitData) -> String {
+    let mut data = commit_data.message.clone();
+    for field in [&commit_data.user, &commit_data.time, &commit_data.tree_hash] {
+        data.push(0);
+        data.extend_from_slice(field.as_bytes());
     }
 
-    
-    /// Computes the SHA-1 hash for the commit's content.
-    fn calculate_hash(&mut self) {
-        assert!(self.data.is_none() == false);
-        let commit_data = self.data.as_ref().unwrap();
-        let mut data: String = Default::default();
+    algorithm.digest(&data)
+}
+
+
+/// Serializes commit metadata into the payload of a commit object, according to the repository format.
+///
+/// - Native format before version 3: `message\0user\0time\0tree\0parent&parent...`
+/// - Native format from version 3: the marker `\0version 2\n`, then one `<key> <length>\n<value>\n`
+///   field for the tree, each parent, the user, the time and the message, in that order.
+/// - Git format: `tree`, `parent`, `author` and `committer` header lines, a blank line and the message.
+///
+/// # Arguments
+/// * `format` - The format of the repository the commit belongs to.
+/// * `version` - The format version of the repository (see `config::FORMAT_VERSION`).
+/// * `commit_data` - Metadata of the commit.
+///
+/// # Returns
+/// * `Vec<u8>` - The serialized payload.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if the legacy native encoding cannot hold the commit,
+///   i.e. a field contains a NUL byte or a parent contains `&`.
+fn serialize_commit(format: RepositoryFormat, version: u32, commit_data: &CommitData) -> Result<Vec<u8>> {
+    match format {
+        RepositoryFormat::Native if version < 3 => {
+            let fields = [commit_data.user.as_bytes(), commit_data.time.as_bytes(), commit_data.tree_hash.as_bytes()];
+            if commit_data.message.contains(&0)
+                || fields.iter().any(|field| field.contains(&0))
+                || commit_data.parent_commits.iter().any(|parent| parent.contains(['\0', '&'])) {
+                return Err(Error::InvalidArgument(format!(
+                    "repository version {} cannot store a commit holding NUL bytes, run `migrate` first.", version
+                )));
+            }
+
+            let mut data = commit_data.message.clone();
+            for field in fields {
+                data.push(0);
+                data.extend_from_slice(field);
+            }
+            data.push(0);
+            data.extend_from_slice(commit_data.parent_commits.join("&").as_bytes());
+            Ok(data)
+        }
+        RepositoryFormat::Native => {
+            let mut data = NATIVE_COMMIT_MARKER.to_vec();
+            data.extend_from_slice(format!("{}\n", NATIVE_COMMIT_ENCODING).as_bytes());
+            push_native_field(&mut data, "tree", commit_data.tree_hash.as_bytes());
+            for parent in commit_data.parent_commits.iter().filter(|parent| !parent.is_empty()) {
+                push_native_field(&mut data, "parent", parent.as_bytes());
+            }
+            push_native_field(&mut data, "user", commit_data.user.as_bytes());
+            push_native_field(&mut data, "time", commit_data.time.as_bytes());
+            push_native_field(&mut data, "message", &commit_data.message);
+            Ok(data)
+        }
+        RepositoryFormat::Git => {
+            let signature = format!("{} <> {}", commit_data.user, utils::time_string_to_git(&commit_data.time));
+            let mut data = format!("tree {}\n", commit_data.tree_hash);
+            for parent in &commit_data.parent_commits {
+                if !parent.is_empty() {
+                    data.push_str(&format!("parent {}\n", parent));
+                }
+            }
+            data.push_str(&format!("author {}\ncommitter {}\n\n", signature, signature));
+
+            let mut data = data.into_bytes();
+            data.extend_from_slice(&commit_data.message);
+            if !commit_data.message.ends_with(b"\n") {
+                data.push(b'\n');
+            }
+            Ok(data)
+        }
+    }
+}
+
+
+/// Appends a `<key> <length>\n<value>\n` field of the native commit encoding.
+fn push_native_field(data: &mut Vec<u8>, key: &str, value: &[u8]) {
+    data.extend_from_slice(format!("{} {}\n", key, value.len()).as_bytes());
+    data.extend_from_slice(value);
+    data.push(b'\n');
+}
 
-        data.push_str(&format!("{}\0{}\0{}\0{}", 
-            commit_data.message, commit_data.user, commit_data.time, commit_data.tree_hash));
-        
-        self.hash = Some(hash::sha1(&data.as_bytes()));
+
+/// Parses the payload of a commit object.
+///
+/// # Arguments
+/// * `format` - The format of the repository the commit belongs to.
+/// * `payload` - The commit payload (header stripped).
+///
+/// # Returns
+/// * The commit metadata, or `Err` describing what is malformed.
+pub fn parse_commit_payload(format: RepositoryFormat, payload: &[u8]) -> std::result::Result<CommitData, String> {
+    match format {
+        RepositoryFormat::Native => match payload.strip_prefix(NATIVE_COMMIT_MARKER) {
+            Some(fields) => parse_native_commit(fields),
+            None => parse_legacy_native_commit(payload),
+        },
+        RepositoryFormat::Git => parse_git_commit(payload),
     }
 }
 
 
+/// Parses the payload of a native commit in the field-based encoding (see `serialize_commit`).
+///
+/// Fields with an unknown key are skipped. A root commit gets a single empty parent,
+/// as in the legacy encoding.
+///
+/// # Arguments
+/// * `payload` - The commit payload, after `NATIVE_COMMIT_MARKER`.
+///
+/// # Returns
+/// * `Err` if the encoding version is unsupported, a field is truncated, or a field is missing.
+fn parse_native_commit(payload: &[u8]) -> std::result::Result<CommitData, String> {
+    let newline = payload.iter().position(|&b| b == b'\n').ok_or("truncated version")?;
+    let version = String::from_utf8_lossy(&payload[..newline]);
+    if version != NATIVE_COMMIT_ENCODING.to_string() {
+        return Err(format!("unsupported commit encoding version {}", version));
+    }
+
+    let text = |value: &[u8]| String::from_utf8_lossy(value).to_string();
+    let (mut tree_hash, mut user, mut time, mut message) = (None, None, None, None);
+    let mut parent_commits = Vec::new();
+
+    let mut rest = &payload[newline + 1..];
+    while !rest.is_empty() {
+        let (key, value, next) = next_native_field(rest)?;
+        match key {
+            "tree" => tree_hash = Some(text(value)),
+            "parent" => parent_commits.push(text(value)),
+            "user" => user = Some(text(value)),
+            "time" => time = Some(text(value)),
+            "message" => message = Some(value.to_vec()),
+            _ => {}
+        }
+        rest = next;
+    }
+
+    if parent_commits.is_empty() {
+        parent_commits.push(String::new());
+    }
+
+    Ok(CommitData {
+        message: message.ok_or("missing message field")?,
+        user: user.ok_or("missing user field")?,
+        time: time.ok_or("missing time field")?,
+        tree_hash: tree_hash.ok_or("missing tree field")?,
+        parent_commits,
+    })
+}
+
+
+/// Splits the first `<key> <length>\n<value>\n` field off the native commit encoding.
+///
+/// # Returns
+/// * The key, the value and the remaining fields, or `Err` if the field is malformed or truncated.
+fn next_native_field(fields: &[u8]) -> std::result::Result<(&str, &[u8], &[u8]), String> {
+    let newline = fields.iter().position(|&b| b == b'\n').ok_or("truncated field")?;
+    let header = std::str::from_utf8(&fields[..newline]).map_err(|_| "invalid field header".to_string())?;
+    let (key, length) = header.split_once(' ').ok_or_else(|| format!("malformed field header: {:?}", header))?;
+    let length: usize = length.parse().map_err(|_| format!("invalid length of field {}", key))?;
+
+    let start = newline + 1;
+    let end = start.checked_add(length)
+        .filter(|&end| fields.get(end) == Some(&b'\n'))
+        .ok_or_else(|| format!("truncated field {}", key))?;
+
+    Ok((key, &fields[start..end], &fields[end + 1..]))
+}
+
+
+/// Parses the payload of a native commit in the legacy encoding: `message\0user\0time\0tree\0parent&parent...`.
+///
+/// # Arguments
+/// * `payload` - The commit payload (header stripped).
+fn parse_legacy_native_commit(payload: &[u8]) -> std::result::Result<CommitData, String> {
+    let parts: Vec<&[u8]> = payload.split(|&b| b == 0).collect();
+    if parts.len() != 5 {
+        return Err(format!("expected 5 fields, found {}", parts.len()));
+    }
+
+    let text = |value: &[u8]| String::from_utf8_lossy(value).to_string();
+    Ok(CommitData {  
+        message:    parts[0].to_vec(),
+        user:       text(parts[1]),
+        time:       text(parts[2]),
+        tree_hash:  text(parts[3]),
+        parent_commits: text(parts[4]).split('&').map(|s| s.to_string()).collect(),
+    })
+}
+
+
+/// Parses the payload of a Git commit object.
+///
+/// Unknown headers (e.g. `gpgsig`, `encoding`) and their continuation lines are skipped.
+/// The message is kept as raw bytes, whatever its encoding.
+///
+/// # Arguments
+/// * `payload` - The commit payload (header stripped).
+///
+/// # Returns
+/// * `Err` if the `tree` or `author` header is missing.
+fn parse_git_commit(payload: &[u8]) -> std::result::Result<CommitData, String> {
+    let (headers, message) = match payload.windows(2).position(|pair| pair == b"\n\n") {
+        Some(blank) => (&payload[..blank], &payload[blank + 2..]),
+        None => (payload, &b""[..]),
+    };
+    let headers = String::from_utf8_lossy(headers);
+
+    let mut data = CommitData {
+        message: message.strip_suffix(b"\n").unwrap_or(message).to_vec(),
+        ..Default::default()
+    };
+    let mut has_author = false;
+
+    for line in headers.lines() {
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        match key {
+            "tree" => data.tree_hash = value.to_string(),
+            "parent" => data.parent_commits.push(value.to_string()),
+            "author" => {
+                let (name, rest) = value.split_once('<').unwrap_or((value, ""));
+                let (_, when) = rest.split_once('>').unwrap_or(("", rest));
+                data.user = name.trim().to_string();
+                data.time = utils::git_to_time_string(when.trim());
+                has_author = true;
+            }
+            _ => {}
+        }
+    }
+
+    if data.tree_hash.is_empty() {
+        return Err("missing tree header".to_string());
+    }
+    if !has_author {
+        return Err("missing author header".to_string());
+    }
+
+    Ok(data)
+}
+
+
 /// Recursively determines whether `prev_commit_hash` is an ancestor of `post_commit_hash`.
 ///
 /// # Arguments
+/// * `repo` - The repository holding the commits.
 /// * `prev_commit_hash` - The potential ancestor commit hash.
 /// * `post_commit_hash` - The commit hash to start searching from.
 /// * `searched_commits` - A mutable set used to track visited commits and prevent cycles.
@@ -124,34 +414,33 @@ impl CommitTrait for Commit {
 /// * `true` if `prev_commit_hash` is an ancestor (or equal to) `post_commit_hash`, or if `prev_commit_hash` is empty.
 /// * `false` otherwise.
 fn is_prev_commit_search(
+    repo: &Repository,
     prev_commit_hash: &str,
     post_commit_hash: &str,
     searched_commits: &mut HashSet<String>
-) -> bool {
-    if prev_commit_hash == post_commit_hash {
-        return true;
-    } else if prev_commit_hash == "" {
-        return true;
-    } else if post_commit_hash == "" {
-        return false;
+) -> Result<bool> {
+    if prev_commit_hash == post_commit_hash || prev_commit_hash.is_empty() {
+        return Ok(true);
+    } else if post_commit_hash.is_empty() {
+        return Ok(false);
     }
 
-    if let Some(_) = searched_commits.get(post_commit_hash) {
-        return false;
+    if searched_commits.contains(post_commit_hash) {
+        return Ok(false);
     }
     searched_commits.insert(post_commit_hash.to_string());
-    
+
     // prev != post, and both != ""
     let mut post_commit = Commit { hash: Some(post_commit_hash.to_string()), data: None };
-    post_commit.read_commit();
+    post_commit.read_commit(repo)?;
 
     for parent_commit_hash in post_commit.data.unwrap().parent_commits {
-        if is_prev_commit_search(prev_commit_hash, &parent_commit_hash, searched_commits) {
-            return true;
+        if is_prev_commit_search(repo, prev_commit_hash, &parent_commit_hash, searched_commits)? {
+            return Ok(true);
         }
     }
 
-    false
+    Ok(false)
 }
 
 
@@ -160,51 +449,57 @@ fn is_prev_commit_search(
 /// This is a public interface that wraps the internal recursive function with a fresh `searched_commits` set.
 ///
 /// # Arguments
+/// * `repo` - The repository holding the commits.
 /// * `prev_commit_hash` - The potential ancestor commit hash.
 /// * `post_commit_hash` - The descendant commit hash to check against.
 ///
 /// # Returns
 /// * `true` if `prev_commit_hash` is an ancestor or the same commit.
 /// * `false` otherwise.
-pub fn is_prev_commit(prev_commit_hash: &str, post_commit_hash: &str) -> bool {
+pub fn is_prev_commit(repo: &Repository, prev_commit_hash: &str, post_commit_hash: &str) -> Result<bool> {
     let mut searched_commits = HashSet::new();
-    is_prev_commit_search(prev_commit_hash, post_commit_hash, &mut searched_commits)
+    is_prev_commit_search(repo, prev_commit_hash, post_commit_hash, &mut searched_commits)
 }
 
 
 /// Checks if there are any uncommitted changes in either the staging area or working directory.
 ///
-/// This function checks two aspects:
-/// 1. If the index (staging area) contains any entries.
-/// 2. If there are changes in the working directory compared to the last commit.
+/// This function checks three aspects, without writing any object:
+/// 1. If the index differs from the current commit (staged changes).
+/// 2. If tracked files in the working directory differ from the index (unstaged changes).
+/// 3. If the working directory holds untracked files.
+///
+/// The entries of files that had to be hashed and were found unchanged get their current stat
+/// data in `index` (see `status::diff_worktree_to_index`). The caller reads the index while
+/// holding its lock, and writes it back when it does not replace it anyway.
+///
+/// # Arguments
+/// * `repo` - The repository to check.
+/// * `index` - The index of the repository, refreshed in place.
 ///
 /// # Returns
 /// * `true` if there are any uncommitted changes.
 /// * `false` if the working directory is clean.
-pub fn check_has_uncommitted() -> bool {
-
-    // Check the staging area
-    // let index = index::read_index();
-    // if index.len() > 0 {
-    //     return true;
-    // }
+///
+/// # Errors
+/// * If the current commit cannot be read, or a file cannot be stat-ed or read.
+pub fn check_has_uncommitted(repo: &Repository, index: &mut Index) -> Result<bool> {
 
     // Check the working area
-    let (index, _, _, _) = add::add_core(&[utils::pwd()].to_vec());
-    let mut entries: HashSet<IndexEntry> = Default::default();
-    for kv in &index {
-        entries.insert(kv.1.clone());
+    let (untracked_log,
+        delete_log,
+        worktree_modify_log) = status::diff_worktree_to_index(repo, index)?;
+    if !untracked_log.is_empty() || !delete_log.is_empty() || !worktree_modify_log.is_empty() {
+        return Ok(true);
     }
 
+    // Check the staging area
+    let entries: HashSet<IndexEntry> = index.values().cloned().collect();
     let (add_log,
         remove_log,
         modify_log) =
-        status::diff_index_entries_to_commit(&entries, &reference::get_current_commit());
-    if add_log.len() != 0 || remove_log.len() != 0 || modify_log.len() != 0 {
-        true
-    } else {
-        false
-    }
+        status::diff_index_entries_to_commit(repo, &entries, &reference::get_current_commit(repo)?)?;
+    Ok(!add_log.is_empty() || !remove_log.is_empty() || !modify_log.is_empty())
 }
 
 
@@ -213,11 +508,12 @@ pub fn check_has_uncommitted() -> bool {
 /// This is a BFS traversal over the commit graph.
 ///
 /// # Arguments
+/// * `repo` - The repository holding the commits.
 /// * `commit_hash` - The starting commit hash.
 ///
 /// # Returns
 /// * A map of ancestor commit hash → distance (integer depth from `commit_hash`).
-fn get_parent_commit_dis(commit_hash: &str) -> HashMap<String, i32> {
+fn get_parent_commit_dis(repo: &Repository, commit_hash: &str) -> Result<HashMap<String, i32>> {
     let mut dis: HashMap<String, i32> = Default::default();
 
     // run BFS
@@ -228,13 +524,13 @@ fn get_parent_commit_dis(commit_hash: &str) -> HashMap<String, i32> {
 
     while let Some((cur_hash, cur_dis)) = queue.pop_front() {
 
-        dis.insert(cur_hash.clone(), cur_dis.clone());
+        dis.insert(cur_hash.clone(), cur_dis);
 
         let mut commit = Commit {
             hash: Some(cur_hash.clone()),
             data: None,
         };
-        commit.read_commit();
+        commit.read_commit(repo)?;
 
         let commit_data = commit.data.unwrap();
         for parent in commit_data.parent_commits {
@@ -245,7 +541,7 @@ fn get_parent_commit_dis(commit_hash: &str) -> HashMap<String, i32> {
         }
     }
 
-    dis
+    Ok(dis)
 }
 
 
@@ -272,22 +568,23 @@ fn merge_parent_commit_dis(dis1: HashMap<String, i32>, dis2: HashMap<String, i32
 /// Finds the lowest common ancestor (merge base) of two commits by minimizing the sum of distances.
 ///
 /// # Arguments
+/// * `repo` - The repository holding the commits.
 /// * `c1` - First commit hash.
 /// * `c2` - Second commit hash.
 ///
 /// # Returns
 /// * The hash of the merge base commit.
 ///
-/// # Panics
-/// * If no common ancestor is found.
-/// * If multiple common ancestors have the same minimal distance (ambiguous base).
-pub fn get_merge_base(c1: &str, c2: &str) -> String {
-    
-    let dis1 = get_parent_commit_dis(c1);
-    let dis2 = get_parent_commit_dis(c2);
+/// # Errors
+/// * `Error::InvalidState` if no common ancestor is found, or if multiple common ancestors
+///   have the same minimal distance (ambiguous base).
+pub fn get_merge_base(repo: &Repository, c1: &str, c2: &str) -> Result<String> {
+
+    let dis1 = get_parent_commit_dis(repo, c1)?;
+    let dis2 = get_parent_commit_dis(repo, c2)?;
     let dis = merge_parent_commit_dis(dis1, dis2);
     
-    let mut min_dis: i32 = MAX;
+    let mut min_dis: i32 = i32::MAX;
     let mut min_cnt: i32 = 0;
     let mut base_commit: Option<String> = None;
 
@@ -302,13 +599,12 @@ pub fn get_merge_base(c1: &str, c2: &str) -> String {
     }
 
     if min_cnt == 0 {
-        panic!("core::commit::get_merge_base: cannot find any parent.");
+        return Err(Error::InvalidState(format!("Commits {} and {} have no common ancestor.", c1, c2)));
     } else if min_cnt > 1 {
-        eprintln!("core::commit::get_merge_base: found multiple base candidates.");
-        process::exit(1);
+        return Err(Error::InvalidState(format!("Commits {} and {} have multiple merge base candidates.", c1, c2)));
     }
 
-    base_commit.unwrap()
+    Ok(base_commit.unwrap())
 }
 
 
@@ -352,4 +648,122 @@ pub fn diff_commit_to_commit(
     }
 
     (add_log, remove_log, modify_log)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::FORMAT_VERSION;
+    use crate::core::{index, object};
+
+    fn sample(message: &[u8], parents: &[&str]) -> CommitData {
+        CommitData {
+            message: message.to_vec(),
+            user: "Jane Doe".to_string(),
+            time: "20240229235959123".to_string(),
+            tree_hash: "a".repeat(40),
+            parent_commits: parents.iter().map(|parent| parent.to_string()).collect(),
+        }
+    }
+
+    fn round_trip(format: RepositoryFormat, version: u32, data: &CommitData) -> CommitData {
+        let payload = serialize_commit(format, version, data).unwrap();
+        parse_commit_payload(format, &payload).unwrap()
+    }
+
+    #[test]
+    fn native_fields_hold_any_message() {
+        let parents = ["b".repeat(40), "c".repeat(40)];
+        let parents: Vec<&str> = parents.iter().map(String::as_str).collect();
+        let messages: [&[u8]; 6] = [
+            b"",
+            b"subject\n\nbody\n",
+            b"nul\0inside\0",
+            b"\nmessage 3\ntime 1\n",
+            b"\0version 2\n",
+            &[0xff, 0xfe, b'\n', 0],
+        ];
+        for message in messages {
+            let data = sample(message, &parents);
+            assert_eq!(round_trip(RepositoryFormat::Native, FORMAT_VERSION, &data), data);
+        }
+    }
+
+    #[test]
+    fn native_field_layout() {
+        let data = CommitData { user: "u".to_string(), time: "t".to_string(), tree_hash: "tr".to_string(), ..sample(b"a\nb", &["p"]) };
+        let payload = serialize_commit(RepositoryFormat::Native, 3, &data).unwrap();
+        assert_eq!(payload, b"\0version 2\ntree 2\ntr\nparent 1\np\nuser 1\nu\ntime 1\nt\nmessage 3\na\nb\n");
+    }
+
+    #[test]
+    fn root_commits_get_an_empty_parent() {
+        let data = sample(b"root", &[""]);
+        assert_eq!(round_trip(RepositoryFormat::Native, FORMAT_VERSION, &data), data);
+        assert_eq!(round_trip(RepositoryFormat::Native, 2, &data), data);
+    }
+
+    #[test]
+    fn malformed_native_fields_are_rejected() {
+        let payload = serialize_commit(RepositoryFormat::Native, FORMAT_VERSION, &sample(b"message", &["p"])).unwrap();
+
+        // the length of the last field runs past its end
+        let truncated = &payload[..payload.len() - 2];
+        assert!(parse_commit_payload(RepositoryFormat::Native, truncated).unwrap_err().starts_with("truncated field"));
+
+        let without_tree = b"\0version 2\nuser 1\nu\ntime 1\nt\nmessage 0\n\n";
+        assert_eq!(parse_commit_payload(RepositoryFormat::Native, without_tree).unwrap_err(), "missing tree field");
+
+        let future = b"\0version 9\n";
+        assert!(parse_commit_payload(RepositoryFormat::Native, future).unwrap_err().starts_with("unsupported"));
+
+        let bad_length = b"\0version 2\ntree x\n";
+        assert!(parse_commit_payload(RepositoryFormat::Native, bad_length).is_err());
+    }
+
+    #[test]
+    fn legacy_native_commits_round_trip_without_nul() {
+        let data = sample(b"multi\nline", &["p1", "p2"]);
+        assert_eq!(round_trip(RepositoryFormat::Native, 2, &data), data);
+        assert!(matches!(
+            serialize_commit(RepositoryFormat::Native, 2, &sample(b"nul\0", &[""])),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn git_commits_round_trip() {
+        // Git timestamps hold whole seconds
+        let data = CommitData {
+            time: "20240229235959000".to_string(),
+            ..sample(b"subject\n\nbody with\0nul", &["b".repeat(40).as_str()])
+        };
+        assert_eq!(round_trip(RepositoryFormat::Git, FORMAT_VERSION, &data), data);
+    }
+
+    #[test]
+    fn dirty_checks_write_no_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Native, HashAlgorithm::Sha1).unwrap();
+        std::fs::write(dir.path().join("file"), b"committed\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        repo.commit("file").unwrap();
+        repo.create_branch("other", None).unwrap();
+
+        let has_uncommitted = || check_has_uncommitted(&repo, &mut index::read_index(&repo).unwrap()).unwrap();
+        assert!(!has_uncommitted());
+
+        std::fs::write(dir.path().join("file"), b"modified\n").unwrap();
+        std::fs::write(dir.path().join("untracked"), b"untracked\n").unwrap();
+        let objects = object::list_loose_objects(&repo).unwrap();
+        assert!(has_uncommitted());
+        assert!(matches!(repo.checkout("other", false), Err(Error::DirtyWorktree(_))));
+        assert!(matches!(repo.merge("other", false), Err(Error::DirtyWorktree(_))));
+        assert_eq!(object::list_loose_objects(&repo).unwrap(), objects);
+
+        std::fs::remove_file(dir.path().join("untracked")).unwrap();
+        assert!(has_uncommitted());
+        std::fs::write(dir.path().join("file"), b"committed\n").unwrap();
+        assert!(!has_uncommitted());
+    }
+}
@@ -0,0 +1,171 @@
+//! Module: config
+//!
+//! Provides reading and writing of the repository metadata stored in `.git/config`.
+//! The file uses Git's INI-like syntax so that stock Git tooling can read it, and records
+//! properties chosen at `init` time, such as the object and repository format and the hash algorithm.
+
+use crate::error::{Error, Result};
+use crate::utils::{hash::HashAlgorithm, *};
+
+/// Version of the object encoding written by this build (`rustgit.version`).
+///
+/// - 1: native commit hashes cover message, user, time and tree, but not the parents.
+/// - 2: native commit hashes cover the whole commit, parents included.
+/// - 3: native commits are written as length-prefixed fields, so that messages may hold any bytes.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Default maximum length of a delta chain inside packs (`pack.depth`), as in Git.
+pub const DEFAULT_PACK_DEPTH: u32 = 50;
+
+/// The on-disk format used for objects and the index.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RepositoryFormat {
+    /// Self-designed `BLOB`/`TREE`/`CMIT` objects (binary-INcompatible with Git).
+    Native,
+    /// Objects, index and refs laid out exactly as Git does.
+    Git,
+}
+
+impl RepositoryFormat {
+    /// Returns the name of the format as written in `.git/config`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RepositoryFormat::Native => "native",
+            RepositoryFormat::Git => "git",
+        }
+    }
+
+    /// Parses a format name as written in `.git/config` or given on the command line.
+    ///
+    /// # Returns
+    /// * `Some(format)` if the name is recognized, `None` otherwise.
+    pub fn from_name(name: &str) -> Option<RepositoryFormat> {
+        match name {
+            "native" => Some(RepositoryFormat::Native),
+            "git" => Some(RepositoryFormat::Git),
+            _ => None,
+        }
+    }
+}
+
+/// Repository-wide settings stored in `.git/config`.
+#[derive(Clone)]
+pub struct Config {
+    pub format: RepositoryFormat,
+    /// Algorithm naming the objects (`extensions.objectformat`).
+    pub hash_algorithm: HashAlgorithm,
+    /// Version of the object encoding (`rustgit.version`), see `FORMAT_VERSION`.
+    pub format_version: u32,
+    /// Maximum length of a delta chain inside packs (`pack.depth`).
+    pub pack_depth: u32,
+}
+
+impl Default for Config {
+    /// Settings assumed for repositories created before `.git/config` existed.
+    fn default() -> Self {
+        Config { format: RepositoryFormat::Native, hash_algorithm: HashAlgorithm::Sha1, format_version: 1, pack_depth: DEFAULT_PACK_DEPTH }
+    }
+}
+
+
+/// Parses the content of a `.git/config` file.
+///
+/// Unknown sections and keys are ignored, missing keys keep their default values.
+///
+/// # Arguments
+/// * `content` - Text content of the config file.
+///
+/// # Errors
+/// * `Error::Config` if a known key holds an unrecognized value.
+fn parse_config(content: &str) -> Result<Config> {
+    let mut config = Config::default();
+    let mut section = String::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            section = line[1..line.len() - 1].trim().to_lowercase();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+
+        match (section.as_str(), key.as_str()) {
+            ("rustgit", "format") => {
+                config.format = RepositoryFormat::from_name(value)
+                    .ok_or_else(|| Error::Config(format!("unknown repository format {}", value)))?;
+            }
+            ("extensions", "objectformat") => {
+                config.hash_algorithm = HashAlgorithm::from_name(&value.to_lowercase())
+                    .ok_or_else(|| Error::Config(format!("unknown object format {}", value)))?;
+            }
+            ("rustgit", "version") => {
+                config.format_version = match value.parse() {
+                    Ok(version) if (1..=FORMAT_VERSION).contains(&version) => version,
+                    _ => return Err(Error::Config(format!("unsupported repository version {}", value))),
+                };
+            }
+            ("pack", "depth") => {
+                config.pack_depth = value
+                    .parse()
+                    .map_err(|_| Error::Config(format!("invalid pack.depth {}", value)))?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(config)
+}
+
+
+/// Reads the config of a repository.
+///
+/// # Arguments
+/// * `git_directory` - Path to the `.git` directory.
+///
+/// # Returns
+/// * `Config` - The repository settings, or the defaults if `.git/config` does not exist.
+///
+/// # Errors
+/// * `Error::Config` if `.git/config` is malformed.
+pub fn read_config(git_directory: &str) -> Result<Config> {
+    let config_path = format!("{}/config", git_directory);
+    match storage::read_text_file(&config_path) {
+        Ok(content) => parse_config(&content),
+        Err(_) => Ok(Config::default()),
+    }
+}
+
+
+/// Writes the given config to `.git/config` in Git's INI-like syntax.
+///
+/// # Arguments
+/// * `git_directory` - Path to the `.git` directory.
+/// * `config` - The settings to store.
+///
+/// # Errors
+/// * `Error::Io` if the write operation fails.
+pub fn write_config(git_directory: &str, config: &Config) -> Result<()> {
+    let config_path = format!("{}/config", git_directory);
+    // Git requires repository format version 1 to honor `[extensions]`
+    let extensions = match config.hash_algorithm {
+        HashAlgorithm::Sha1 => String::new(),
+        algorithm => format!("[extensions]\n\tobjectformat = {}\n", algorithm.name()),
+    };
+    let content = format!(
+        "[core]\n\trepositoryformatversion = {}\n\tfilemode = true\n\tbare = false\n{}[pack]\n\tdepth = {}\n[rustgit]\n\tformat = {}\n\tversion = {}\n",
+        if extensions.is_empty() { 0 } else { 1 },
+        extensions,
+        config.pack_depth,
+        config.format.name(),
+        config.format_version
+    );
+
+    storage::write_text_file(&config_path, &content).map_err(Error::io(format!("Cannot write to {}", config_path)))
+}
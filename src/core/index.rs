@@ -3,18 +3,104 @@
 //! Provides structures and functions for managing the Git index (staging area),
 //! including reading and writing the `.git/index` file and recursively registering
 //! files and directories into the index.
+//!
+//! Both repository formats store the index as Git's binary `DIRC` version 2 file: a header,
+//! one entry per path with its stat data, mode, hash and flags, and a trailing checksum
+//! of the whole file. Native indexes written before this layout (base64 text) are still read.
+
+use std::{collections::{HashMap, HashSet}, fs, path::Path};
+use std::os::unix::fs::MetadataExt;
 
-use std::{collections::{HashMap, HashSet}, fs, path::Path, process};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+use crate::utils::{hash::HashAlgorithm, *};
+use super::{blob, config::RepositoryFormat, tree::TreeEntryType};
 
-use crate::utils::*;
+/// Version of the `DIRC` index file written by `write_index`.
+const INDEX_VERSION: u32 = 2;
 
 /// Represents a file entry in the staging index.
-#[derive(PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct IndexEntry {
     /// Relative file path
     pub path: String,
     /// Hash (blob ID) of the file content
     pub hash: String,
+    /// Type of the file (regular, executable or symlink)
+    pub entry_type: TreeEntryType,
+    /// Stat data of the file when it was staged
+    pub stat: FileStat,
+}
+
+/// Stat data recorded for an index entry, truncated to 32 bits as Git does.
+///
+/// Entries whose file content is not known to match (e.g. restored from a tree, or racily
+/// clean, see `read_index`) hold zeroes.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct FileStat {
+    pub ctime: u32,
+    pub ctime_nsec: u32,
+    pub mtime: u32,
+    pub mtime_nsec: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+impl FileStat {
+    /// Extracts the stat data of a file from its metadata (not following symlinks).
+    pub fn from_metadata(meta: &fs::Metadata) -> FileStat {
+        FileStat {
+            ctime: meta.ctime() as u32,
+            ctime_nsec: meta.ctime_nsec() as u32,
+            mtime: meta.mtime() as u32,
+            mtime_nsec: meta.mtime_nsec() as u32,
+            dev: meta.dev() as u32,
+            ino: meta.ino() as u32,
+            uid: meta.uid(),
+            gid: meta.gid(),
+            size: meta.size() as u32,
+        }
+    }
+}
+
+impl IndexEntry {
+    /// Builds the entry of a staged file, taking its type and stat data from its metadata.
+    ///
+    /// The metadata must be fetched before the content is hashed, so that a change made
+    /// while hashing shows up as a stat mismatch later on.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file relative to the repository root.
+    /// * `hash` - Hash of the blob holding the file content.
+    /// * `file_path` - Absolute path of the file (for error messages).
+    /// * `meta` - Metadata of the file, not following symlinks.
+    ///
+    /// # Errors
+    /// * `Error::InvalidArgument` if the file is neither a regular file nor a symlink.
+    pub fn from_metadata(path: &str, hash: &str, file_path: &str, meta: &fs::Metadata) -> Result<IndexEntry> {
+        Ok(IndexEntry {
+            path: path.to_string(),
+            hash: hash.to_string(),
+            entry_type: blob::get_blob_type_from_metadata(file_path, meta)?,
+            stat: FileStat::from_metadata(meta),
+        })
+    }
+
+    /// Tells whether a file still has the stat data and type recorded in this entry,
+    /// in which case its content is assumed unchanged and need not be rehashed.
+    ///
+    /// Zeroed stat data (see `FileStat`) never matches.
+    ///
+    /// # Arguments
+    /// * `meta` - Metadata of the file, not following symlinks.
+    pub fn matches_stat(&self, meta: &fs::Metadata) -> bool {
+        self.stat != FileStat::default()
+            && self.stat == FileStat::from_metadata(meta)
+            && blob::get_blob_type_from_metadata(&self.path, meta).is_ok_and(|entry_type| entry_type == self.entry_type)
+    }
 }
 
 /// Type alias for the index (staging area), mapping file path → `IndexEntry`
@@ -23,80 +109,245 @@ pub type Index = HashMap<String, IndexEntry>;
 
 /// Reads and parses the `.git/index` file into an in-memory `Index`.
 ///
+/// An entry whose file was modified no earlier than the index was written is "racily clean":
+/// the file may have changed again within the same timestamp, without its stat data changing.
+/// Such entries are loaded with zeroed stat data, so their content is always rehashed.
+///
+/// # Arguments
+/// * `repo` - The repository whose index is read.
+///
 /// # Returns
 /// * `Index` - A map of file paths to their corresponding index entries.
 ///
-/// # Panics
-/// * If the index content is invalid UTF-8.
-/// * If index format is not prefixed with `"DIRC"` magic string.
-/// * If any line is malformed and doesn't contain two null-separated fields.
-///
-/// # Exits
-/// * If the index file cannot be read, prints an error and exits the process.
-pub fn read_index() -> Index {
-    
-    let index_path = utils::get_git_directory() + "/index";
-    let raw_content = match storage::read_text_file(&index_path) {
-        Ok(res) => res,
-        Err(e) => {
-            eprintln!("Failed to read index: {}", e);
-            process::exit(1)
+/// # Errors
+/// * `Error::Io` if the index file cannot be read, `Error::Corrupt` if it is malformed.
+pub fn read_index(repo: &Repository) -> Result<Index> {
+
+    let index_path = repo.git_dir().to_string() + "/index";
+    let raw_content = storage::read_file(&index_path).map_err(Error::io("Failed to read index"))?;
+    let index_meta = fs::metadata(&index_path).map_err(Error::io("Failed to stat index"))?;
+
+    let config = repo.config();
+    let mut entries = match config.format {
+        RepositoryFormat::Native if !raw_content.starts_with(b"DIRC") => parse_legacy_native_index(repo.path(), &raw_content),
+        _ => parse_index(config.hash_algorithm, &raw_content),
+    }.map_err(|e| Error::Corrupt(format!("Failed to read index: {}", e)))?;
+
+    let index_mtime = (index_meta.mtime() as u32, index_meta.mtime_nsec() as u32);
+    for entry in entries.values_mut() {
+        if (entry.stat.mtime, entry.stat.mtime_nsec) >= index_mtime {
+            entry.stat = Default::default();
         }
-    };
+    }
+    Ok(entries)
+}
+
+
+/// Parses a native index written before the `DIRC` layout: base64 of `"DIRC"` followed by `path\0hash\n` lines.
+///
+/// These indexes hold no file modes, so the type of each entry is taken from the working tree
+/// at `repo_path` (a regular file if it cannot be stat-ed); stat data is left zeroed.
+fn parse_legacy_native_index(repo_path: &str, raw_content: &[u8]) -> std::result::Result<Index, String> {
+    let raw_content = std::str::from_utf8(raw_content).map_err(|_| "invalid UTF-8".to_string())?;
 
     let mut entries: Index = Default::default();
-    let vecu8_content = serialize::deserialize(&raw_content);
-    let full_content = std::str::from_utf8(&vecu8_content).expect("Invalid UTF-8");
+    let vecu8_content = serialize::deserialize(raw_content).map_err(|e| e.to_string())?;
+    let full_content = std::str::from_utf8(&vecu8_content).map_err(|_| "invalid UTF-8".to_string())?;
 
-    if full_content.len() == 0 {
-        return entries;
+    if full_content.is_empty() {
+        return Ok(entries);
     }
 
-    assert!(full_content.len() >= 4);
-    assert!(full_content[..4] == "DIRC"[..]);
+    if !full_content.starts_with("DIRC") {
+        return Err("bad signature".to_string());
+    }
 
     for line in full_content[4..].lines() {
         let parts: Vec<&str> = line.split('\0').collect();
-        assert!(parts.len() == 2);
+        if parts.len() != 2 {
+            return Err(format!("malformed entry: {:?}", line));
+        }
 
         let path = parts[0].to_string();
         let hash = parts[1].to_string();
+        let entry_type = blob::get_blob_type(&format!("{}/{}", repo_path, path)).unwrap_or_default();
+
+        entries.insert(path.clone(), IndexEntry { path, hash, entry_type, stat: Default::default() });
+    }
+
+    Ok(entries)
+}
+
+
+/// Parses a `DIRC` index (version 2 or 3).
+///
+/// Extensions after the entries are ignored.
+///
+/// # Returns
+/// * `Err` if the signature, version or checksum is invalid, an entry is truncated,
+///   or an entry has a mode other than a regular file, executable or symlink.
+fn parse_index(algorithm: HashAlgorithm, raw_content: &[u8]) -> std::result::Result<Index, String> {
+    let raw_hash_length = algorithm.raw_length();
+    let read_u32 = |pos: usize| u32::from_be_bytes(raw_content[pos..pos + 4].try_into().unwrap());
+
+    if raw_content.len() < 12 + raw_hash_length || &raw_content[..4] != b"DIRC" {
+        return Err("bad signature".to_string());
+    }
+    let body_length = raw_content.len() - raw_hash_length;
+    if algorithm.digest(&raw_content[..body_length]) != hex::encode(&raw_content[body_length..]) {
+        return Err("checksum mismatch".to_string());
+    }
+    let version = read_u32(4);
+    if version != 2 && version != 3 {
+        return Err(format!("unsupported version {}", version));
+    }
+
+    let mut entries: Index = Default::default();
+    let mut pos = 12;
 
-        entries.insert(path.clone(), IndexEntry { path, hash });
+    for _ in 0..read_u32(8) {
+        // 40 bytes of stat data and mode, then hash and flags
+        let flags_pos = pos + 40 + raw_hash_length;
+        if flags_pos + 2 > body_length {
+            return Err("truncated entry".to_string());
+        }
+        let stat = FileStat {
+            ctime: read_u32(pos),
+            ctime_nsec: read_u32(pos + 4),
+            mtime: read_u32(pos + 8),
+            mtime_nsec: read_u32(pos + 12),
+            dev: read_u32(pos + 16),
+            ino: read_u32(pos + 20),
+            uid: read_u32(pos + 28),
+            gid: read_u32(pos + 32),
+            size: read_u32(pos + 36),
+        };
+        let mode = read_u32(pos + 24);
+        let hash = hex::encode(&raw_content[pos + 40..flags_pos]);
+        let flags = u16::from_be_bytes([raw_content[flags_pos], raw_content[flags_pos + 1]]);
+        let path_pos = flags_pos + 2 + if flags & 0x4000 != 0 { 2 } else { 0 };
+        let Some(path_length) = raw_content[path_pos.min(body_length)..body_length].iter().position(|&b| b == 0) else {
+            return Err("truncated entry".to_string());
+        };
+        let path = String::from_utf8_lossy(&raw_content[path_pos..path_pos + path_length]).to_string();
+        let entry_type = TreeEntryType::from_git_mode(&format!("{:o}", mode))
+            .filter(|entry_type| *entry_type != TreeEntryType::Tree)
+            .ok_or_else(|| format!("unsupported mode {:o} for {}", mode, path))?;
+
+        // entries are padded with 1 to 8 NUL bytes to a multiple of 8 bytes
+        let entry_length = path_pos - pos + path_length;
+        pos += (entry_length + 8) & !7;
+
+        entries.insert(path.clone(), IndexEntry { path, hash, entry_type, stat });
     }
 
-    entries
+    Ok(entries)
+}
+
+
+/// Records the current stat data of every entry's file, e.g. right after the working area
+/// was restored from the entries' blobs. Entries whose file cannot be stat-ed get zeroed stat data.
+///
+/// # Arguments
+/// * `repo` - The repository the entries belong to.
+/// * `index` - The entries to update.
+pub fn record_stat(repo: &Repository, index: &mut Index) {
+    for entry in index.values_mut() {
+        entry.stat = match fs::symlink_metadata(format!("{}/{}", repo.path(), entry.path)) {
+            Ok(meta) => FileStat::from_metadata(&meta),
+            Err(_) => Default::default(),
+        };
+    }
 }
 
 
 /// Serializes and writes the given `Index` into `.git/index`.
 ///
 /// # Arguments
+/// * `repo` - The repository whose index is written.
 /// * `index` - The staging index to write.
 ///
 /// # Format
-/// Each line after the `"DIRC"` magic string consists of:
-/// `path\0hash\n`
+/// `DIRC` version 2 in both repository formats, with entries sorted by path and a trailing checksum.
+///
+/// # Errors
+/// * `Error::Locked` if another process is writing the index.
+/// * `Error::InvalidArgument` if an entry holds an invalid hash.
+/// * `Error::Io` if the write operation fails.
+pub fn write_index(repo: &Repository, index: &Index) -> Result<()> {
+    write_locked_index(repo, lock_index(repo)?, index)
+}
+
+
+/// Takes the lock on `.git/index`.
+///
+/// Commands that read the index, update it and write it back hold this lock from before the
+/// read until `write_locked_index`, so that a concurrent command fails instead of losing
+/// the other's changes. Dropping the lock releases it without touching the index.
 ///
-/// # Exits
-/// * If the write operation fails, prints an error and exits the process.
-pub fn write_index(index: &Index) {
+/// # Arguments
+/// * `repo` - The repository whose index is locked.
+///
+/// # Errors
+/// * `Error::Locked` if another process holds the lock, `Error::Io` if it cannot be created.
+pub fn lock_index(repo: &Repository) -> Result<storage::LockFile> {
+    storage::LockFile::acquire(&(repo.git_dir().to_string() + "/index"))
+}
 
-    let index_path = utils::get_git_directory() + "/index";
-    let mut data: String = Default::default();
 
-    data.push_str("DIRC");
+/// Serializes the given `Index` and replaces `.git/index` with it, releasing the lock.
+///
+/// # Arguments
+/// * `repo` - The repository whose index is written.
+/// * `lock` - The lock taken with `lock_index`.
+/// * `index` - The staging index to write.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if an entry holds an invalid hash.
+/// * `Error::Io` if the write operation fails.
+pub fn write_locked_index(repo: &Repository, lock: storage::LockFile, index: &Index) -> Result<()> {
+    let raw_content = serialize_index(repo.hash_algorithm(), index)?;
+    lock.commit(&raw_content)
+}
 
-    for entry in index {
-        data.push_str(&format!("{}\0{}\n", entry.1.path, entry.1.hash));
-    }
 
-    let raw_content = serialize::serialize(&data.as_bytes());
+/// Serializes an index into Git's `DIRC` version 2 format.
+///
+/// Each entry holds its stat data, mode, hash and flags (the path length, capped at `0xFFF`,
+/// as the path itself is NUL-terminated), padded to a multiple of 8 bytes.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if an entry holds an invalid hash.
+fn serialize_index(algorithm: HashAlgorithm, index: &Index) -> Result<Vec<u8>> {
+    let mut sorted_entries: Vec<&IndexEntry> = index.values().collect();
+    sorted_entries.sort_by(|a, b| a.path.as_bytes().cmp(b.path.as_bytes()));
+
+    let mut data: Vec<u8> = b"DIRC".to_vec();
+    data.extend(INDEX_VERSION.to_be_bytes());
+    data.extend((sorted_entries.len() as u32).to_be_bytes());
+
+    for entry in sorted_entries {
+        let mode = u32::from_str_radix(entry.entry_type.git_mode(), 8).unwrap();
+        let raw_hash = hex::decode(&entry.hash).map_err(|_| {
+            Error::InvalidArgument(format!("Failed to write index: invalid hash {} for {}", entry.hash, entry.path))
+        })?;
 
-    if let Err(e) = storage::write_text_file(&index_path, &raw_content) {
-        eprintln!("Failed to write index: {}", e);
-        process::exit(1)
+        let stat = &entry.stat;
+        let entry_start = data.len();
+        for field in [stat.ctime, stat.ctime_nsec, stat.mtime, stat.mtime_nsec, stat.dev, stat.ino, mode, stat.uid, stat.gid, stat.size] {
+            data.extend(field.to_be_bytes());
+        }
+        data.extend(raw_hash);
+        data.extend((entry.path.len().min(0xFFF) as u16).to_be_bytes());
+        data.extend(entry.path.as_bytes());
+
+        let entry_length = data.len() - entry_start;
+        data.resize(entry_start + ((entry_length + 8) & !7), 0);
     }
+
+    let checksum = hex::decode(algorithm.digest(&data)).unwrap();
+    data.extend(checksum);
+    Ok(data)
 }
 
 
@@ -106,40 +357,38 @@ pub fn write_index(index: &Index) {
 /// including symlinks and regular files. Directories are handled recursively if specified.
 ///
 /// # Arguments
+/// * `repo` - The repository the files belong to; its `.git` directory is skipped.
 /// * `path` - Absolute path of the file or directory to process.
 /// * `rel_path` - Relative path for storing in the index.
 /// * `index` - A mutable set collecting index entries (`IndexEntry`) found under the given path.
 /// * `recursive` - If `true`, subdirectories will be traversed recursively.
 ///
-/// # Exits
-/// * If a directory is encountered but `recursive` is `false`, prints an error and exits.
-pub fn register_files(path: &str, rel_path: &str, index: &mut HashSet<IndexEntry>, recursive: &bool) {
+/// # Errors
+/// * `Error::InvalidArgument` if a directory is encountered but `recursive` is `false`.
+pub fn register_files(repo: &Repository, path: &str, rel_path: &str, index: &mut HashSet<IndexEntry>, recursive: &bool) -> Result<()> {
 
-    if &path == &utils::get_git_directory() {
-        return ;
+    if path == repo.git_dir() {
+        return Ok(());
     }
 
     let path = Path::new(path);
 
     if let Ok(metadata) = fs::symlink_metadata(path) {
-        if metadata.file_type().is_symlink() {
-            index.insert(
-                IndexEntry {
-                path: rel_path.to_string(),
-                hash: String::default(),
-                }
-            );
-        } else if metadata.is_file() {
+        // a symlink is registered as itself, never followed
+        if metadata.file_type().is_symlink() || metadata.is_file() {
             index.insert(
                 IndexEntry {
                 path: rel_path.to_string(),
                 hash: String::default(),
+                entry_type: Default::default(),
+                stat: Default::default(),
                 }
             );
         } else if metadata.is_dir() {
             if !recursive {
-                eprintln!("{} is a directory, use --recursive or -r to handle.", path.to_string_lossy());
-                process::exit(1);
+                return Err(Error::InvalidArgument(format!(
+                    "{} is a directory, use --recursive or -r to handle.", path.to_string_lossy()
+                )));
             }
             if let Ok(entries) = fs::read_dir(path) {
                 for entry in entries.flatten() {
@@ -151,9 +400,122 @@ pub fn register_files(path: &str, rel_path: &str, index: &mut HashSet<IndexEntry
                     } else {
                         format!("{}/{}", rel_path, file_name_str)
                     };
-                    register_files(new_abs_path.to_str().unwrap(), &new_rel_path, index, recursive);
+                    register_files(repo, &new_abs_path.to_string_lossy(), &new_rel_path, index, recursive)?;
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, entry_type: TreeEntryType, seed: u32) -> IndexEntry {
+        IndexEntry {
+            path: path.to_string(),
+            hash: format!("{:08x}", seed).repeat(8)[..40].to_string(),
+            entry_type,
+            stat: FileStat {
+                ctime: seed,
+                ctime_nsec: seed + 1,
+                mtime: seed + 2,
+                mtime_nsec: seed + 3,
+                dev: seed + 4,
+                ino: seed + 5,
+                uid: seed + 6,
+                gid: seed + 7,
+                size: u32::MAX - seed,
+            },
+        }
+    }
+
+    fn sample_index() -> Index {
+        let mut index: Index = Default::default();
+        // path lengths covering every amount of padding, and one beyond the 12-bit length field
+        let mut paths: Vec<String> = (1..=9).map(|length| "p".repeat(length)).collect();
+        paths.push(format!("dir/{}", "x".repeat(0x1000)));
+        for (i, path) in paths.into_iter().enumerate() {
+            let entry_type = match i % 3 {
+                0 => TreeEntryType::Blob,
+                1 => TreeEntryType::Bexe,
+                _ => TreeEntryType::Bsym,
+            };
+            index.insert(path.clone(), entry(&path, entry_type, i as u32 * 16));
+        }
+        index
+    }
+
+    #[test]
+    fn index_round_trips() {
+        let index = sample_index();
+        let raw_content = serialize_index(HashAlgorithm::Sha1, &index).unwrap();
+        assert_eq!(&raw_content[..12], &[b'D', b'I', b'R', b'C', 0, 0, 0, 2, 0, 0, 0, 10]);
+        assert_eq!(parse_index(HashAlgorithm::Sha1, &raw_content).unwrap(), index);
+        assert_eq!(parse_index(HashAlgorithm::Sha1, &serialize_index(HashAlgorithm::Sha1, &Default::default()).unwrap()).unwrap(), Index::new());
+    }
+
+    #[test]
+    fn sha256_index_round_trips() {
+        let mut index: Index = Default::default();
+        let mut file = entry("file", TreeEntryType::Blob, 1);
+        file.hash = "ab".repeat(32);
+        index.insert(file.path.clone(), file);
+        let raw_content = serialize_index(HashAlgorithm::Sha256, &index).unwrap();
+        assert_eq!(parse_index(HashAlgorithm::Sha256, &raw_content).unwrap(), index);
+        assert!(parse_index(HashAlgorithm::Sha1, &raw_content).is_err());
+    }
+
+    #[test]
+    fn entries_are_sorted_and_padded() {
+        let mut index: Index = Default::default();
+        for path in ["b", "a/c", "a"] {
+            index.insert(path.to_string(), entry(path, TreeEntryType::Blob, 0));
+        }
+        let raw_content = serialize_index(HashAlgorithm::Sha1, &index).unwrap();
+        // 62 bytes of fixed fields, the path and 1 to 8 NUL bytes: 64 bytes for "a", "b", 72 for "a/c"
+        assert_eq!(raw_content.len(), 12 + 64 + 72 + 64 + 20);
+        assert_eq!(&raw_content[12 + 62..12 + 64], b"a\0");
+        assert_eq!(&raw_content[12 + 64 + 62..12 + 64 + 66], b"a/c\0");
+        assert_eq!(&raw_content[12 + 64 + 72 + 62..12 + 64 + 72 + 64], b"b\0");
+    }
+
+    #[test]
+    fn checksum_mismatch_is_rejected() {
+        let mut raw_content = serialize_index(HashAlgorithm::Sha1, &sample_index()).unwrap();
+        raw_content[20] ^= 1;
+        assert_eq!(parse_index(HashAlgorithm::Sha1, &raw_content).unwrap_err(), "checksum mismatch");
+
+        let mut raw_content = serialize_index(HashAlgorithm::Sha1, &sample_index()).unwrap();
+        let last = raw_content.len() - 1;
+        raw_content[last] ^= 1;
+        assert_eq!(parse_index(HashAlgorithm::Sha1, &raw_content).unwrap_err(), "checksum mismatch");
+    }
+
+    #[test]
+    fn malformed_indexes_are_rejected() {
+        let with_checksum = |body: &[u8]| {
+            let mut raw_content = body.to_vec();
+            raw_content.extend(hex::decode(HashAlgorithm::Sha1.digest(body)).unwrap());
+            raw_content
+        };
+        let raw_content = serialize_index(HashAlgorithm::Sha1, &sample_index()).unwrap();
+        let body = &raw_content[..raw_content.len() - 20];
+
+        assert_eq!(parse_index(HashAlgorithm::Sha1, b"DIRC").unwrap_err(), "bad signature");
+
+        let mut wrong_version = body.to_vec();
+        wrong_version[7] = 4;
+        assert_eq!(parse_index(HashAlgorithm::Sha1, &with_checksum(&wrong_version)).unwrap_err(), "unsupported version 4");
+
+        let mut extra_entry = body.to_vec();
+        extra_entry[11] += 1;
+        assert_eq!(parse_index(HashAlgorithm::Sha1, &with_checksum(&extra_entry)).unwrap_err(), "truncated entry");
+
+        let mut tree_mode = body.to_vec();
+        tree_mode[12 + 24..12 + 28].copy_from_slice(&0o40000u32.to_be_bytes());
+        assert!(parse_index(HashAlgorithm::Sha1, &with_checksum(&tree_mode)).unwrap_err().starts_with("unsupported mode"));
+    }
+}
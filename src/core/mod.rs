@@ -1,6 +1,10 @@
 pub mod blob;
 pub mod commit;
+pub mod config;
 pub mod index;
 pub mod object;
+pub mod odb;
+pub mod pack;
 pub mod reference;
+pub mod revision;
 pub mod tree;
\ No newline at end of file
@@ -6,109 +6,606 @@
 //!
 //! Supported object types: Blob, Tree, Commit.
 
-use std::process;
+use std::fs;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
 
+use flate2::read::ZlibDecoder;
+
+use crate::error::{Error, Result};
+use crate::repository::Repository;
 use crate::utils::*;
+use super::{config::RepositoryFormat, odb::ObjectDatabase, pack};
+
+/// Inflating reader over a compressed loose object file.
+type LooseObjectReader = ZlibDecoder<BufReader<fs::File>>;
 
 /// Enum representing the type of a Git object.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ObjectType {
     Blob,
     Tree,
     Commit,
 }
 
+impl ObjectType {
+    /// Returns the 4-byte header used by the native object format.
+    fn native_header(&self) -> &'static [u8] {
+        match self {
+            ObjectType::Blob => b"BLOB",
+            ObjectType::Tree => b"TREE",
+            ObjectType::Commit => b"CMIT",
+        }
+    }
+
+    /// Returns the type name used by the Git object format.
+    pub fn git_name(&self) -> &'static str {
+        match self {
+            ObjectType::Blob => "blob",
+            ObjectType::Tree => "tree",
+            ObjectType::Commit => "commit",
+        }
+    }
+}
+
 
 /// Returns the full file path of a Git object in the `.git/objects/` directory.
 ///
 /// # Arguments
 ///
-/// * `object_name` - A SHA-1 hash string identifying the object.
+/// * `repo` - The repository holding the object.
+/// * `object_name` - A hash string identifying the object.
 ///
-/// # Panics / Exits
+/// # Errors
 ///
-/// This function prints an error and exits the process if the given hash length
-/// is not equal to the expected hash length.
+/// `Error::InvalidRevision` if the given hash length is not equal to the expected hash length.
 ///
 /// # Returns
 ///
 /// A `String` representing the full path to the object file.
-pub fn get_object_path(object_name: &str) -> String {
-    if (&object_name).len() != hash::HASH_LENGTH {
-        eprintln!("{} is not a valid object.", object_name);
-        process::exit(1);
+pub fn get_object_path(repo: &Repository, object_name: &str) -> Result<String> {
+    if object_name.len() != repo.hash_algorithm().hex_length() || !object_name.is_char_boundary(hash::FOLDER_LENGTH) {
+        return Err(Error::InvalidRevision(format!("{} is not a valid object name", object_name)));
     }
-    let git_directory = utils::get_git_directory();
-    let file_path = git_directory.clone() + 
+    let file_path = repo.git_dir().to_string() +
                             "/objects/" + &object_name[..hash::FOLDER_LENGTH] +
                             "/" + &object_name[hash::FOLDER_LENGTH..];
-    file_path
+    Ok(file_path)
 }
 
 
 /// Reads the content of a Git object file.
 ///
+/// Objects are normally stored as zlib streams. Objects written by older versions
+/// were stored as base64 text; those are detected and decoded transparently.
+/// Objects without a loose file are looked up in the packs.
+///
 /// # Arguments
 ///
-/// * `object_name` - A SHA-1 hash string identifying the object.
+/// * `repo` - The repository holding the object.
+/// * `object_name` - A hash string identifying the object.
 ///
-/// # Panics / Exits
+/// # Errors
 ///
-/// This function prints an error and exits the process if the file cannot be read.
+/// `Error::MissingObject` if the object cannot be found, `Error::Corrupt` if it is broken.
 ///
 /// # Returns
 ///
-/// A `String` containing the raw content of the object file.
-pub fn read_object_file(object_name: &str) -> String {
+/// A `Vec<u8>` containing the decoded content of the object (header included).
+pub fn read_object_file(repo: &Repository, object_name: &str) -> Result<Vec<u8>> {
+    load_object_file(repo, object_name)?.ok_or_else(|| Error::MissingObject(object_name.to_string()))
+}
 
-    let file_path = get_object_path(&object_name);
 
-    match storage::read_text_file(&file_path) {
-        Ok(res) => res,
-        Err(e) => {
-            eprintln!("Failed to read object '{}': {}", object_name, e);
-            process::exit(1)
-        }
+/// Reads the content of a Git object file, like `read_object_file`, telling missing objects apart.
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the object.
+/// * `object_name` - A hash string identifying the object.
+///
+/// # Errors
+///
+/// `Error::Corrupt` describing why the stored data cannot be decoded.
+///
+/// # Returns
+///
+/// `Some(content)` with the decoded content of the object (header included),
+/// or `None` if the object is neither loose nor packed.
+pub fn load_object_file(repo: &Repository, object_name: &str) -> Result<Option<Vec<u8>>> {
+
+    let file_path = get_object_path(repo, object_name)?;
+
+    let Ok(raw_content) = storage::read_file(&file_path) else {
+        return pack::read_packed_object(repo, object_name);
+    };
+
+    let corrupt = |reason: String| Error::Corrupt(format!("corrupt object {}: {}", object_name, reason));
+    if compress::is_compressed(&raw_content) {
+        compress::decompress(&raw_content)
+            .map(Some)
+            .map_err(|e| corrupt(format!("cannot inflate: {}", e)))
+    } else {
+        std::str::from_utf8(&raw_content)
+            .map_err(|_| corrupt("neither zlib nor base64 data".to_string()))
+            .and_then(|text| serialize::deserialize(text).map_err(|e| corrupt(format!("cannot decode base64: {}", e))))
+            .map(Some)
     }
 }
 
 
 /// Writes content to a Git object file if it does not already exist.
 ///
+/// The content is compressed with zlib before being written.
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the object.
+/// * `object_name` - A hash string identifying the object.
+/// * `content` - The content (header included) to write into the object file.
+///
+/// # Errors
+///
+/// `Error::Io` if writing the file fails.
+pub fn write_object_file(repo: &Repository, object_name: &str, content: &[u8]) -> Result<()> {
+
+    let file_path = get_object_path(repo, object_name)?;
+
+    if object_exists(repo, object_name)? {
+        return Ok(());
+    }
+
+    storage::write_file(&file_path, &compress::compress(content))
+        .map_err(Error::io(format!("Failed to write object '{}'", object_name)))
+}
+
+
+/// Checks whether an object exists, either as a loose file or inside a pack.
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the object.
+/// * `object_name` - A hash string identifying the object.
+pub fn object_exists(repo: &Repository, object_name: &str) -> Result<bool> {
+    Ok(std::path::Path::new(&get_object_path(repo, object_name)?).exists() || pack::contains_packed_object(repo, object_name)?)
+}
+
+
+/// Lists the hashes of all loose objects in `.git/objects/`.
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the objects.
+///
+/// # Returns
+///
+/// A `Vec<String>` of object hashes, in no particular order.
+pub fn list_loose_objects(repo: &Repository) -> Result<Vec<String>> {
+    let objects_path = repo.git_dir().to_string() + "/objects";
+    let is_hex = |name: &str| name.chars().all(|c| c.is_ascii_hexdigit());
+
+    let hash_length = repo.hash_algorithm().hex_length();
+    let mut res = Vec::new();
+    let Ok(folders) = fs::read_dir(&objects_path) else {
+        return Ok(res);
+    };
+    for folder in folders.flatten() {
+        let folder_name = folder.file_name().to_string_lossy().into_owned();
+        if folder_name.len() != hash::FOLDER_LENGTH || !is_hex(&folder_name) {
+            continue;
+        }
+        let Ok(files) = fs::read_dir(folder.path()) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let file_name = file.file_name().to_string_lossy().into_owned();
+            if folder_name.len() + file_name.len() == hash_length && is_hex(&file_name) {
+                res.push(folder_name.clone() + &file_name);
+            }
+        }
+    }
+    Ok(res)
+}
+
+
+/// Minimum number of hex digits accepted as an abbreviated object name.
+pub const MIN_ABBREV_LENGTH: usize = 4;
+
+
+/// Lists the objects whose hash starts with the given prefix, loose or packed.
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the objects.
+/// * `prefix` - Lowercase hex prefix of the hashes, at least `FOLDER_LENGTH` digits long.
+///
+/// # Returns
+///
+/// A sorted `Vec<String>` of matching object hashes, without duplicates.
+pub fn find_objects_by_prefix(repo: &Repository, prefix: &str) -> Result<Vec<String>> {
+    let folder_path = repo.git_dir().to_string() + "/objects/" + &prefix[..hash::FOLDER_LENGTH];
+    let hash_length = repo.hash_algorithm().hex_length();
+
+    let mut res: Vec<String> = Vec::new();
+    if let Ok(files) = fs::read_dir(&folder_path) {
+        for file in files.flatten() {
+            let name = prefix[..hash::FOLDER_LENGTH].to_string() + &file.file_name().to_string_lossy();
+            if name.len() == hash_length && name.starts_with(prefix) && name.chars().all(|c| c.is_ascii_hexdigit()) {
+                res.push(name);
+            }
+        }
+    }
+    res.extend(pack::list_packed_objects(repo)?.into_iter().filter(|name| name.starts_with(prefix)));
+
+    res.sort();
+    res.dedup();
+    Ok(res)
+}
+
+
+/// Expands a full or abbreviated object name into the full hash of an existing object.
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the object.
+/// * `object_name` - Hex digits of the hash, at least `MIN_ABBREV_LENGTH` of them.
+///
+/// # Returns
+///
+/// The full hash of the object.
+///
+/// # Errors
+///
+/// `Error::InvalidRevision` describing why the name does not designate exactly one
+/// object; ambiguous names list the candidates with their types.
+pub fn resolve_object_name(repo: &Repository, object_name: &str) -> Result<String> {
+    let prefix = object_name.to_ascii_lowercase();
+    if prefix.len() < MIN_ABBREV_LENGTH
+        || prefix.len() > repo.hash_algorithm().hex_length()
+        || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err(Error::InvalidRevision(format!("{} is not a valid object name", object_name)));
+    }
+
+    let mut candidates = find_objects_by_prefix(repo, &prefix)?;
+    match candidates.len() {
+        0 => Err(Error::InvalidRevision(format!("{} does not match any object", object_name))),
+        1 => Ok(candidates.remove(0)),
+        _ => {
+            let mut message = format!("short object ID {} is ambiguous; the candidates are:", object_name);
+            for candidate in &candidates {
+                let object_type = match get_object_type(repo, candidate) {
+                    Ok(object_type) => object_type.git_name(),
+                    Err(_) => "corrupt object",
+                };
+                message.push_str(&format!("\n  {} {}", candidate, object_type));
+            }
+            Err(Error::InvalidRevision(message))
+        }
+    }
+}
+
+
+/// Builds the full stored content of an object from its type and payload.
+///
+/// - Native format: a 4-byte type header (`BLOB`, `TREE`, `CMIT`) followed by the payload.
+/// - Git format: `<type> <length>\0` followed by the payload.
+///
+/// # Arguments
+///
+/// * `repo` - The repository the object belongs to.
+/// * `object_type` - The type of the object.
+/// * `payload` - The serialized object body.
+///
+/// # Returns
+///
+/// A `Vec<u8>` containing the header and the payload.
+pub fn encode_object(repo: &Repository, object_type: ObjectType, payload: &[u8]) -> Vec<u8> {
+    encode_object_as(repo.config().format, object_type, payload)
+}
+
+
+/// Builds the full stored content of an object in the given format (see `encode_object`).
+///
+/// # Arguments
+///
+/// * `format` - The format of the repository the object belongs to.
+/// * `object_type` - The type of the object.
+/// * `payload` - The serialized object body.
+pub fn encode_object_as(format: RepositoryFormat, object_type: ObjectType, payload: &[u8]) -> Vec<u8> {
+    let mut full_content = encode_object_header_as(format, object_type, payload.len() as u64);
+    full_content.extend_from_slice(payload);
+    full_content
+}
+
+
+/// Builds the header that precedes the payload of an object (see `encode_object`).
+///
+/// # Arguments
+///
+/// * `repo` - The repository the object belongs to.
+/// * `object_type` - The type of the object.
+/// * `payload_length` - The length of the payload in bytes.
+pub fn encode_object_header(repo: &Repository, object_type: ObjectType, payload_length: u64) -> Vec<u8> {
+    encode_object_header_as(repo.config().format, object_type, payload_length)
+}
+
+
+/// Builds the header of an object in the given format (see `encode_object`).
+fn encode_object_header_as(format: RepositoryFormat, object_type: ObjectType, payload_length: u64) -> Vec<u8> {
+    match format {
+        RepositoryFormat::Native => object_type.native_header().to_vec(),
+        RepositoryFormat::Git => format!("{} {}\0", object_type.git_name(), payload_length).into_bytes(),
+    }
+}
+
+
+/// Splits the full stored content of an object into its type and payload.
+///
+/// # Arguments
+///
+/// * `repo` - The repository the object belongs to.
+/// * `object_name` - A hash string identifying the object (used for error messages).
+/// * `full_content` - The decoded content of the object file.
+///
+/// # Errors
+///
+/// `Error::Corrupt` if the header is malformed or unrecognized.
+///
+/// # Returns
+///
+/// The `ObjectType` of the object and a slice holding its payload.
+pub fn decode_object<'a>(repo: &Repository, object_name: &str, full_content: &'a [u8]) -> Result<(ObjectType, &'a [u8])> {
+    decode_object_as(repo.config().format, object_name, full_content)
+}
+
+
+/// Splits the full stored content of an object in the given format (see `decode_object`).
+///
+/// # Arguments
+///
+/// * `format` - The format of the repository the object belongs to.
+/// * `object_name` - A hash string identifying the object (used for error messages).
+/// * `full_content` - The decoded content of the object file.
+pub fn decode_object_as<'a>(format: RepositoryFormat, object_name: &str, full_content: &'a [u8]) -> Result<(ObjectType, &'a [u8])> {
+    let corrupt = |reason: String| Error::Corrupt(format!("corrupt object {}: {}", object_name, reason));
+    let (object_type, header_length, payload_length) = parse_object_header(format, full_content).map_err(corrupt)?;
+    let payload = &full_content[header_length..];
+
+    if let Some(length) = payload_length.filter(|&length| length != payload.len() as u64) {
+        return Err(corrupt(format!("header announces {} bytes, found {}", length, payload.len())));
+    }
+
+    Ok((object_type, payload))
+}
+
+
+/// Parses the header at the start of the stored content of an object.
+///
+/// # Arguments
+///
+/// * `format` - The format of the repository the object belongs to.
+/// * `content` - The decoded content of the object file, or at least its header.
+///
+/// # Returns
+///
+/// The `ObjectType` of the object, the length of the header, and the payload length
+/// recorded in the header (Git format only); or `Err` if the header is malformed or unrecognized.
+fn parse_object_header(format: RepositoryFormat, content: &[u8]) -> std::result::Result<(ObjectType, usize, Option<u64>), String> {
+    match format {
+        RepositoryFormat::Native => {
+            let object_type = match content.get(..4) {
+                Some(b"BLOB") => ObjectType::Blob,
+                Some(b"TREE") => ObjectType::Tree,
+                Some(b"CMIT") => ObjectType::Commit,
+                _ => return Err("unknown object header".to_string()),
+            };
+            Ok((object_type, 4, None))
+        }
+        RepositoryFormat::Git => {
+            let Some(nul) = content.iter().position(|&b| b == 0) else {
+                return Err("unterminated object header".to_string());
+            };
+            let header = String::from_utf8_lossy(&content[..nul]);
+            let (type_name, length) = header.split_once(' ').unwrap_or(("", ""));
+            let object_type = match type_name {
+                "blob" => ObjectType::Blob,
+                "tree" => ObjectType::Tree,
+                "commit" => ObjectType::Commit,
+                _ => return Err(format!("unknown object type '{}'", type_name)),
+            };
+            let Ok(length) = length.parse::<u64>() else {
+                return Err(format!("invalid object length '{}'", length));
+            };
+            Ok((object_type, nul + 1, Some(length)))
+        }
+    }
+}
+
+
+/// Reads an object and returns its payload, checking that it has the expected type.
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the object.
+/// * `object_name` - A hash string identifying the object.
+/// * `object_type` - The type the object is expected to have.
+///
+/// # Errors
+///
+/// `Error::MissingObject` if the object is missing, `Error::Corrupt` if it cannot be
+/// decoded or has a different type.
+///
+/// # Returns
+///
+/// A `Vec<u8>` containing the payload of the object (header stripped).
+pub fn read_object_payload(repo: &Repository, object_name: &str, object_type: ObjectType) -> Result<Vec<u8>> {
+    read_object_payload_from(&repo.odb(), object_name, object_type)
+}
+
+
+/// Reads an object from the given database and returns its payload (see `read_object_payload`).
+///
+/// # Arguments
+///
+/// * `db` - The object database to read from.
+/// * `object_name` - A hash string identifying the object.
+/// * `object_type` - The type the object is expected to have.
+///
+/// # Errors
+///
+/// `Error::MissingObject` if the object is missing, `Error::Corrupt` if it cannot be
+/// decoded or has a different type.
+pub fn read_object_payload_from(db: &dyn ObjectDatabase, object_name: &str, object_type: ObjectType) -> Result<Vec<u8>> {
+    let full_content = db.get(object_name)?.ok_or_else(|| Error::MissingObject(object_name.to_string()))?;
+    let (stored_type, payload) = decode_object_as(db.format(), object_name, &full_content)?;
+    check_object_type(object_name, stored_type, object_type)?;
+    Ok(payload.to_vec())
+}
+
+
+/// Checks that an object has the type its referrer expects.
+///
+/// # Errors
+///
+/// `Error::Corrupt` if `stored_type` differs from `expected_type`.
+fn check_object_type(object_name: &str, stored_type: ObjectType, expected_type: ObjectType) -> Result<()> {
+    if stored_type != expected_type {
+        return Err(Error::Corrupt(format!(
+            "object {} is a {}, expected a {}",
+            object_name, stored_type.git_name(), expected_type.git_name()
+        )));
+    }
+    Ok(())
+}
+
+
+/// Writes the payload of an object into `writer`, without holding it in memory when possible.
+///
+/// Compressed loose objects and packed objects stored whole are inflated in chunks straight
+/// into `writer`. Legacy base64 objects and packed deltas are read in memory first.
+///
 /// # Arguments
 ///
-/// * `object_name` - A SHA-1 hash string identifying the object.
-/// * `content` - The content to write into the object file.
+/// * `repo` - The repository holding the object.
+/// * `object_name` - A hash string identifying the object.
+/// * `object_type` - The type the object is expected to have.
+/// * `writer` - Destination of the payload.
 ///
-/// # Panics / Exits
+/// # Errors
 ///
-/// This function prints an error and exits the process if writing the file fails.
-pub fn write_object_file(object_name: &str, content: &str) {
+/// `Error::Corrupt` if the stored object cannot be decoded or has a different type,
+/// `Error::Io` if it cannot be read or `writer` fails.
+pub fn write_object_payload_to(repo: &Repository, object_name: &str, object_type: ObjectType, writer: &mut impl Write) -> Result<()> {
+    let io_error = || Error::io(format!("Failed to copy object '{}'", object_name));
+
+    if let Some((mut decoder, stored_type, _)) = open_loose_object(repo, object_name)? {
+        check_object_type(object_name, stored_type, object_type)?;
+        return io::copy(&mut decoder, writer).map(|_| ()).map_err(io_error());
+    }
 
-    let file_path = get_object_path(&object_name);
-    
-    if std::path::Path::new(&file_path).exists() {
-        return ;
+    let Some((stored_type, length, mut reader)) = pack::open_packed_object(repo, object_name)? else {
+        return writer.write_all(&read_object_payload(repo, object_name, object_type)?).map_err(io_error());
+    };
+    check_object_type(object_name, stored_type, object_type)?;
+    let copied = io::copy(&mut reader, writer).map_err(io_error())?;
+    if copied != length {
+        return Err(Error::Corrupt(format!(
+            "corrupt object {}: {} byte(s) declared, {} decoded", object_name, length, copied
+        )));
     }
-    
-    match storage::write_text_file(&file_path, content) {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!("Failed to write object '{}': {}", object_name, e);
-            process::exit(1)
+    Ok(())
+}
+
+
+/// Reads the type and the payload length of an object, without holding it in memory when possible.
+///
+/// Only the header of compressed loose objects is inflated in the Git format; native headers
+/// hold no length, so the payload is inflated and counted in chunks. Packed objects are
+/// described by the header of their entry (see `pack::read_packed_object_info`), and legacy
+/// base64 objects are read in memory.
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the object.
+/// * `object_name` - A hash string identifying the object.
+///
+/// # Errors
+///
+/// `Error::MissingObject` if the object is missing, `Error::Corrupt` if it cannot be decoded,
+/// `Error::Io` if it cannot be read.
+pub fn read_object_info(repo: &Repository, object_name: &str) -> Result<(ObjectType, u64)> {
+    match open_loose_object(repo, object_name)? {
+        Some((_, object_type, Some(length))) => Ok((object_type, length)),
+        Some((mut decoder, object_type, None)) => {
+            let length = io::copy(&mut decoder, &mut io::sink())
+                .map_err(|e| Error::Corrupt(format!("corrupt object {}: cannot inflate: {}", object_name, e)))?;
+            Ok((object_type, length))
+        }
+        None => {
+            if let Some(info) = pack::read_packed_object_info(repo, object_name)? {
+                return Ok(info);
+            }
+            let full_content = read_object_file(repo, object_name)?;
+            let (object_type, payload) = decode_object(repo, object_name, &full_content)?;
+            Ok((object_type, payload.len() as u64))
         }
     }
 }
 
 
+/// Opens a compressed loose object and inflates its header, leaving the payload to be read.
+///
+/// # Returns
+///
+/// The decoder positioned at the start of the payload, the type of the object and the payload
+/// length recorded in its header (Git format only); or `None` if the object is not stored as
+/// a compressed loose file.
+///
+/// # Errors
+///
+/// `Error::Corrupt` if the header cannot be inflated or parsed.
+fn open_loose_object(repo: &Repository, object_name: &str) -> Result<Option<(LooseObjectReader, ObjectType, Option<u64>)>> {
+    let file_path = get_object_path(repo, object_name)?;
+
+    let mut magic = [0u8; 2];
+    let Ok(mut file) = fs::File::open(&file_path) else {
+        return Ok(None);
+    };
+    if file.read_exact(&mut magic).is_err() || !compress::is_compressed(&magic) {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(0)).map_err(Error::io(format!("Failed to read object '{}'", object_name)))?;
+    let mut decoder = ZlibDecoder::new(BufReader::new(file));
+
+    // the header is at most a type name and a decimal length
+    let corrupt = |reason: String| Error::Corrupt(format!("corrupt object {}: {}", object_name, reason));
+    let mut header: Vec<u8> = Vec::new();
+    let format = repo.config().format;
+    let header_length = match format {
+        RepositoryFormat::Native => 4,
+        RepositoryFormat::Git => 64,
+    };
+    let mut byte = [0u8; 1];
+    while header.len() < header_length && !header.ends_with(b"\0") {
+        decoder.read_exact(&mut byte).map_err(|e| corrupt(format!("cannot inflate: {}", e)))?;
+        header.push(byte[0]);
+    }
+    let (object_type, _, length) = parse_object_header(format, &header).map_err(corrupt)?;
+
+    Ok(Some((decoder, object_type, length)))
+}
+
+
 /// Determines the type of the Git object (Blob, Tree, or Commit).
 ///
 /// # Arguments
 ///
-/// * `object_name` - A SHA-1 hash string identifying the object.
+/// * `repo` - The repository holding the object.
+/// * `object_name` - A hash string identifying the object.
 ///
-/// # Panics / Exits
+/// # Errors
 ///
-/// This function prints an error and exits the process if:
+/// `Error::MissingObject` if the object does not exist, `Error::Corrupt` if:
 /// - The object is malformed
 /// - The content cannot be decoded
 /// - The header is unrecognized
@@ -116,26 +613,58 @@ pub fn write_object_file(object_name: &str, content: &str) {
 /// # Returns
 ///
 /// An `ObjectType` enum indicating the object’s type.
-pub fn get_object_type(object_name: &str) -> ObjectType {
-
-    let raw_content = read_object_file(object_name);
-    let vecu8_content = serialize::deserialize(&raw_content);
-    let full_content = std::str::from_utf8(&vecu8_content).expect("Invalid UTF-8");
+///
+/// # Notes
+///
+/// Only the header of the object is read when possible (see `read_object_info`).
+pub fn get_object_type(repo: &Repository, object_name: &str) -> Result<ObjectType> {
 
-    if full_content.len() < 4 {
-        eprintln!("Broken object: {}", object_name);
-        process::exit(1);
+    if let Some((_, object_type, _)) = open_loose_object(repo, object_name)? {
+        return Ok(object_type);
     }
 
-    let header = &full_content[..4];
+    Ok(read_object_info(repo, object_name)?.0)
+}
 
-    match header {
-        "BLOB" => ObjectType::Blob,
-        "TREE" => ObjectType::Tree,
-        "CMIT" => ObjectType::Commit,
-        _ => {
-            eprintln!("Broken object: {}", object_name);
-            process::exit(1);
-        }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::hash::HashAlgorithm;
+
+    #[test]
+    fn legacy_base64_objects_are_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Native, HashAlgorithm::Sha1).unwrap();
+        let payload = b"written before zlib\n";
+        let full_content = encode_object(&repo, ObjectType::Blob, payload);
+        let name = repo.hash_algorithm().digest(payload);
+        storage::write_file(&get_object_path(&repo, &name).unwrap(), serialize::serialize(&full_content).as_bytes()).unwrap();
+
+        assert_eq!(read_object_file(&repo, &name).unwrap(), full_content);
+        assert_eq!(read_object_payload(&repo, &name, ObjectType::Blob).unwrap(), payload);
+        assert_eq!(read_object_info(&repo, &name).unwrap(), (ObjectType::Blob, payload.len() as u64));
+        assert_eq!(get_object_type(&repo, &name).unwrap(), ObjectType::Blob);
+        let mut streamed = Vec::new();
+        write_object_payload_to(&repo, &name, ObjectType::Blob, &mut streamed).unwrap();
+        assert_eq!(streamed, payload);
+
+        // new objects are compressed, and smaller than their content
+        let payload = vec![b'x'; 4096];
+        let name = repo.hash_algorithm().digest(&payload);
+        write_object_file(&repo, &name, &encode_object(&repo, ObjectType::Blob, &payload)).unwrap();
+        let raw_content = fs::read(get_object_path(&repo, &name).unwrap()).unwrap();
+        assert!(compress::is_compressed(&raw_content) && raw_content.len() < payload.len());
+        assert_eq!(read_object_payload(&repo, &name, ObjectType::Blob).unwrap(), payload);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn broken_loose_objects_are_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Native, HashAlgorithm::Sha1).unwrap();
+        let name = repo.hash_algorithm().digest(b"broken");
+        storage::write_file(&get_object_path(&repo, &name).unwrap(), b"not base64 !").unwrap();
+        assert!(matches!(read_object_file(&repo, &name), Err(Error::Corrupt(_))));
+        assert!(matches!(read_object_file(&repo, &"0".repeat(40)), Err(Error::MissingObject(_))));
+    }
+}
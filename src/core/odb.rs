@@ -0,0 +1,244 @@
+//! Module: odb
+//!
+//! Defines the `ObjectDatabase` trait, the storage backend behind `Blob`, `Tree` and `Commit`.
+//! Two implementations are provided:
+//! - `LooseObjectDatabase`: the repository's `.git/objects` directory (loose files and packs),
+//! - `MemoryObjectDatabase`: a map held in memory, which never touches the disk.
+//!
+//! Objects are exchanged in their full stored form (type header included), as produced by
+//! `object::encode_object_as`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crate::error::Result;
+use crate::repository::Repository;
+use crate::utils::hash::HashAlgorithm;
+use super::{config::{self, RepositoryFormat}, object, pack};
+
+/// A store of objects addressed by their hash.
+pub trait ObjectDatabase {
+    /// Returns the format the objects of this database are encoded and hashed with.
+    fn format(&self) -> RepositoryFormat;
+
+    /// Returns the algorithm objects are named with.
+    fn hash_algorithm(&self) -> HashAlgorithm;
+
+    /// Returns the version of the object encoding, see `config::FORMAT_VERSION`.
+    fn format_version(&self) -> u32;
+
+    /// Returns the full stored content of an object (header included), or `None` if it is absent.
+    fn get(&self, object_name: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Stores the full content of an object under its hash. Storing an existing object is a no-op.
+    fn put(&self, object_name: &str, content: &[u8]) -> Result<()>;
+
+    /// Checks whether an object is present.
+    fn contains(&self, object_name: &str) -> Result<bool>;
+
+    /// Lists the hashes of all objects, in no particular order.
+    fn iterate(&self) -> Result<Vec<String>>;
+}
+
+
+/// The object store of a repository: loose files in `.git/objects` and packs.
+pub struct LooseObjectDatabase<'r> {
+    repo: &'r Repository,
+}
+
+impl<'r> LooseObjectDatabase<'r> {
+    /// Opens the object store of a repository.
+    pub fn new(repo: &'r Repository) -> Self {
+        LooseObjectDatabase { repo }
+    }
+}
+
+impl ObjectDatabase for LooseObjectDatabase<'_> {
+    fn format(&self) -> RepositoryFormat {
+        self.repo.config().format
+    }
+
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.repo.config().hash_algorithm
+    }
+
+    fn format_version(&self) -> u32 {
+        self.repo.config().format_version
+    }
+
+    fn get(&self, object_name: &str) -> Result<Option<Vec<u8>>> {
+        object::load_object_file(self.repo, object_name)
+    }
+
+    fn put(&self, object_name: &str, content: &[u8]) -> Result<()> {
+        object::write_object_file(self.repo, object_name, content)
+    }
+
+    fn contains(&self, object_name: &str) -> Result<bool> {
+        object::object_exists(self.repo, object_name)
+    }
+
+    /// Objects stored both loose and in a pack are listed once.
+    fn iterate(&self) -> Result<Vec<String>> {
+        let mut names: Vec<String> = object::list_loose_objects(self.repo)?;
+        let mut seen: HashSet<String> = names.iter().cloned().collect();
+        for name in pack::list_packed_objects(self.repo)? {
+            if seen.insert(name.clone()) {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+}
+
+
+/// An object store held entirely in memory.
+///
+/// Not used by the command line front-end; meant for library users and for tests.
+pub struct MemoryObjectDatabase {
+    format: RepositoryFormat,
+    hash_algorithm: HashAlgorithm,
+    objects: RefCell<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryObjectDatabase {
+    /// Creates an empty store whose objects use the given format and hash algorithm,
+    /// at the current format version.
+    pub fn new(format: RepositoryFormat, hash_algorithm: HashAlgorithm) -> Self {
+        MemoryObjectDatabase { format, hash_algorithm, objects: Default::default() }
+    }
+}
+
+impl ObjectDatabase for MemoryObjectDatabase {
+    fn format(&self) -> RepositoryFormat {
+        self.format
+    }
+
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    fn format_version(&self) -> u32 {
+        config::FORMAT_VERSION
+    }
+
+    fn get(&self, object_name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.objects.borrow().get(object_name).cloned())
+    }
+
+    fn put(&self, object_name: &str, content: &[u8]) -> Result<()> {
+        self.objects.borrow_mut().entry(object_name.to_string()).or_insert_with(|| content.to_vec());
+        Ok(())
+    }
+
+    fn contains(&self, object_name: &str) -> Result<bool> {
+        Ok(self.objects.borrow().contains_key(object_name))
+    }
+
+    fn iterate(&self) -> Result<Vec<String>> {
+        Ok(self.objects.borrow().keys().cloned().collect())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::blob::{Blob, BlobTrait};
+    use crate::core::commit::{Commit, CommitData, CommitTrait};
+    use crate::core::tree::{Tree, TreeEntry, TreeEntryType, TreeTrait};
+    use crate::error::Error;
+
+    fn write_blob(db: &dyn ObjectDatabase, data: &[u8]) -> String {
+        let mut blob = Blob { hash: None, data: Some(data.to_vec()) };
+        blob.write_blob_to(db).unwrap();
+        blob.hash.unwrap()
+    }
+
+    #[test]
+    fn git_objects_are_named_as_git_names_them() {
+        let db = MemoryObjectDatabase::new(RepositoryFormat::Git, HashAlgorithm::Sha1);
+        assert_eq!(write_blob(&db, b"hello\n"), "ce013625030ba8dba906f756967f9e9ca394464a");
+
+        let mut tree = Tree { hash: None, data: Some(Vec::new()) };
+        tree.write_tree_to(&db).unwrap();
+        assert_eq!(tree.hash.unwrap(), "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+
+        let db = MemoryObjectDatabase::new(RepositoryFormat::Git, HashAlgorithm::Sha256);
+        assert_eq!(write_blob(&db, b"hello\n"), "2cf8d83d9ee29543b34a87727421fdecb7e3f3a183d337639025de576db9ebb4");
+    }
+
+    #[test]
+    fn objects_round_trip_in_both_formats() {
+        for format in [RepositoryFormat::Native, RepositoryFormat::Git] {
+            let db = MemoryObjectDatabase::new(format, HashAlgorithm::Sha1);
+            let blob_hash = write_blob(&db, b"content");
+
+            let entries = vec![
+                TreeEntry { entry_type: TreeEntryType::Bexe, name: "run".to_string(), hash: blob_hash.clone() },
+                TreeEntry { entry_type: TreeEntryType::Blob, name: "file".to_string(), hash: blob_hash.clone() },
+            ];
+            let mut tree = Tree { hash: None, data: Some(entries) };
+            tree.write_tree_to(&db).unwrap();
+            let tree_hash = tree.hash.unwrap();
+
+            let data = CommitData {
+                message: b"message".to_vec(),
+                user: "user".to_string(),
+                time: "20240101000000000".to_string(),
+                tree_hash: tree_hash.clone(),
+                parent_commits: Vec::new(),
+            };
+            let mut commit = Commit { hash: None, data: Some(data) };
+            commit.write_commit_to(&db).unwrap();
+            let commit_hash = commit.hash.unwrap();
+
+            let mut blob = Blob { hash: Some(blob_hash.clone()), data: None };
+            blob.read_blob_from(&db).unwrap();
+            assert_eq!(blob.data.unwrap(), b"content");
+
+            // entries are read back in canonical order
+            let mut tree = Tree { hash: Some(tree_hash.clone()), data: None };
+            tree.read_tree_from(&db).unwrap();
+            let names: Vec<String> = tree.data.unwrap().into_iter().map(|entry| entry.name).collect();
+            assert_eq!(names, ["file", "run"]);
+
+            let mut commit = Commit { hash: Some(commit_hash.clone()), data: None };
+            commit.read_commit_from(&db).unwrap();
+            let data = commit.data.unwrap();
+            assert_eq!(data.tree_hash, tree_hash);
+            assert_eq!(data.message, b"message");
+
+            let mut names = db.iterate().unwrap();
+            names.sort();
+            let mut expected = vec![blob_hash, tree_hash, commit_hash];
+            expected.sort();
+            assert_eq!(names, expected);
+        }
+    }
+
+    #[test]
+    fn put_keeps_the_first_content() {
+        let db = MemoryObjectDatabase::new(RepositoryFormat::Native, HashAlgorithm::Sha1);
+        assert!(!db.contains("name").unwrap());
+        assert_eq!(db.get("name").unwrap(), None);
+
+        db.put("name", b"first").unwrap();
+        db.put("name", b"second").unwrap();
+        assert!(db.contains("name").unwrap());
+        assert_eq!(db.get("name").unwrap(), Some(b"first".to_vec()));
+    }
+
+    #[test]
+    fn reading_an_object_as_another_type_fails() {
+        let db = MemoryObjectDatabase::new(RepositoryFormat::Git, HashAlgorithm::Sha1);
+        let blob_hash = write_blob(&db, b"content");
+
+        let mut tree = Tree { hash: Some(blob_hash.clone()), data: None };
+        assert!(matches!(tree.read_tree_from(&db), Err(Error::Corrupt(_))));
+
+        let mut commit = Commit { hash: Some("0".repeat(40)), data: None };
+        assert!(matches!(commit.read_commit_from(&db), Err(Error::MissingObject(_))));
+    }
+}
@@ -0,0 +1,1146 @@
+//! Module: pack
+//!
+//! Provides reading and writing of packfiles, which store many objects in a single data file
+//! under `.git/objects/pack/`. Each `pack-<checksum>.pack` is accompanied by a
+//! `pack-<checksum>.idx` holding the sorted object hashes, a 256-entry fanout table and
+//! the offset of every object inside the pack.
+//!
+//! Both files follow Git's pack format (version 2) and index format (version 2).
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use tempfile::NamedTempFile;
+
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+use crate::utils::*;
+use super::config;
+use super::object::*;
+
+const PACK_SIGNATURE: &[u8] = b"PACK";
+const INDEX_SIGNATURE: &[u8] = b"\xfftOc";
+const PACK_VERSION: u32 = 2;
+const INDEX_VERSION: u32 = 2;
+
+/// Pack type number of a delta whose base is given by its offset in the same pack.
+const OFS_DELTA: u8 = 6;
+/// Pack type number of a delta whose base is given by its hash.
+const REF_DELTA: u8 = 7;
+/// Number of preceding objects of the same path tried as delta bases.
+const DELTA_WINDOW: usize = 10;
+/// Extra size allowed for a delta base, so that tiny files can still be compared.
+const BLOCK_SLACK: usize = 64;
+/// Blobs larger than this are never compared for deltas, and are streamed into packs as-is.
+const BIG_FILE_THRESHOLD: u64 = 16 << 20;
+/// Largest buffer reserved upfront for an entry; the size an entry declares is not trusted.
+const MAX_PREALLOCATION: u64 = 1 << 20;
+
+/// Reader over the payload of a packed object, inflated from the pack or resolved in memory.
+pub type PackedObjectReader = Box<dyn Read>;
+
+/// Delta chosen for an object: the index of its base and the encoded delta, if any.
+type DeltaChoice = Option<(usize, Vec<u8>)>;
+
+/// Cache of the indexes of all packs of a repository, loaded on first lookup.
+#[derive(Default)]
+pub struct PackCache(Mutex<Option<Vec<PackIndex>>>);
+
+/// In-memory form of a `.idx` file.
+struct PackIndex {
+    /// Path of the `.pack` file this index describes.
+    pack_path: String,
+    /// `fanout[b]` is the number of objects whose first hash byte is `<= b`.
+    fanout: [u32; 256],
+    /// Concatenated raw (binary) object hashes, sorted.
+    names: Vec<u8>,
+    /// Offset of each object in the pack, in the same order as `names`.
+    offsets: Vec<u64>,
+    /// Length (in bytes) of a binary object hash.
+    raw_hash_length: usize,
+}
+
+impl PackIndex {
+    /// Returns the number of objects in the pack.
+    fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns the raw hash of the `i`-th object.
+    fn name(&self, i: usize) -> &[u8] {
+        &self.names[i * self.raw_hash_length..(i + 1) * self.raw_hash_length]
+    }
+
+    /// Looks up the pack offset of an object using the fanout table and a binary search.
+    ///
+    /// # Arguments
+    /// * `raw_hash` - The binary hash of the object.
+    fn find(&self, raw_hash: &[u8]) -> Option<u64> {
+        if raw_hash.len() != self.raw_hash_length {
+            return None;
+        }
+        let first_byte = raw_hash[0] as usize;
+        let mut low = if first_byte == 0 { 0 } else { self.fanout[first_byte - 1] as usize };
+        let mut high = self.fanout[first_byte] as usize;
+
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.name(mid).cmp(raw_hash) {
+                std::cmp::Ordering::Equal => return Some(self.offsets[mid]),
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+        None
+    }
+}
+
+
+/// Returns the path of the `.git/objects/pack` directory.
+fn get_pack_directory(repo: &Repository) -> String {
+    repo.git_dir().to_string() + "/objects/pack"
+}
+
+
+/// Parses a `.idx` file.
+///
+/// # Arguments
+/// * `index_path` - Path of the `.idx` file.
+/// * `raw_hash_length` - Length (in bytes) of a binary object hash in the repository.
+///
+/// # Errors
+/// * `Error::Io` if the file cannot be read, `Error::Corrupt` if it is malformed.
+fn load_pack_index(index_path: &str, raw_hash_length: usize) -> Result<PackIndex> {
+    let broken = |reason: &str| Error::Corrupt(format!("Broken pack index {}: {}", index_path, reason));
+
+    let content = storage::read_file(index_path).map_err(Error::io(format!("Cannot read pack index {}", index_path)))?;
+    let read_u32 = |pos: usize| u32::from_be_bytes(content[pos..pos + 4].try_into().unwrap());
+
+    if content.len() < 8 + 256 * 4 || &content[..4] != INDEX_SIGNATURE || read_u32(4) != INDEX_VERSION {
+        return Err(broken("bad signature"));
+    }
+
+    let mut fanout = [0u32; 256];
+    for (i, count) in fanout.iter_mut().enumerate() {
+        *count = read_u32(8 + i * 4);
+    }
+    if fanout.windows(2).any(|pair| pair[0] > pair[1]) {
+        return Err(broken("fanout table is not sorted"));
+    }
+    let count = fanout[255] as usize;
+
+    let names_pos = 8 + 256 * 4;
+    let crc_pos = names_pos + count * raw_hash_length;
+    let offsets_pos = crc_pos + count * 4;
+    let large_offsets_pos = offsets_pos + count * 4;
+    if content.len() < large_offsets_pos + 2 * raw_hash_length {
+        return Err(broken("truncated"));
+    }
+
+    let mut offsets = Vec::with_capacity(count);
+    for i in 0..count {
+        let offset = read_u32(offsets_pos + i * 4);
+        if offset & 0x8000_0000 == 0 {
+            offsets.push(offset as u64);
+        } else {
+            let pos = large_offsets_pos + (offset & 0x7fff_ffff) as usize * 8;
+            if pos + 8 > content.len() {
+                return Err(broken("truncated"));
+            }
+            offsets.push(u64::from_be_bytes(content[pos..pos + 8].try_into().unwrap()));
+        }
+    }
+
+    Ok(PackIndex {
+        pack_path: index_path.strip_suffix(".idx").unwrap_or(index_path).to_string() + ".pack",
+        fanout,
+        names: content[names_pos..crc_pos].to_vec(),
+        offsets,
+        raw_hash_length,
+    })
+}
+
+
+/// Lists the paths of all `.idx` files in the repository.
+fn list_pack_index_paths(repo: &Repository) -> Vec<String> {
+    let mut res = Vec::new();
+    if let Ok(entries) = fs::read_dir(get_pack_directory(repo)) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "idx") {
+                res.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+    res.sort();
+    res
+}
+
+
+/// Runs `f` on the cached pack indexes of a repository, loading them first if needed.
+///
+/// # Errors
+/// * If an index cannot be loaded (see `load_pack_index`).
+fn with_pack_indexes<T>(repo: &Repository, f: impl FnOnce(&Vec<PackIndex>) -> T) -> Result<T> {
+    let mut cache = repo.packs().0.lock().unwrap();
+    if cache.is_none() {
+        let raw_hash_length = repo.hash_algorithm().raw_length();
+        let indexes = list_pack_index_paths(repo)
+            .iter()
+            .map(|path| load_pack_index(path, raw_hash_length))
+            .collect::<Result<_>>()?;
+        *cache = Some(indexes);
+    }
+    Ok(f(cache.as_ref().unwrap()))
+}
+
+
+/// Drops the cached pack indexes of a repository, so that packs added or removed since are seen.
+pub fn reset_pack_cache(repo: &Repository) {
+    *repo.packs().0.lock().unwrap() = None;
+}
+
+
+/// Checks whether an object is stored in any pack.
+///
+/// # Arguments
+/// * `repo` - The repository holding the packs.
+/// * `object_name` - A SHA-1 hash string identifying the object.
+pub fn contains_packed_object(repo: &Repository, object_name: &str) -> Result<bool> {
+    let Ok(raw_hash) = hex::decode(object_name) else {
+        return Ok(false);
+    };
+    with_pack_indexes(repo, |indexes| indexes.iter().any(|index| index.find(&raw_hash).is_some()))
+}
+
+
+/// Lists the hashes of all objects stored in packs.
+///
+/// # Returns
+/// * `Vec<String>` - Hex-encoded object hashes (may contain duplicates across packs).
+pub fn list_packed_objects(repo: &Repository) -> Result<Vec<String>> {
+    with_pack_indexes(repo, |indexes| {
+        let mut res = Vec::new();
+        for index in indexes {
+            for i in 0..index.len() {
+                res.push(hex::encode(index.name(i)));
+            }
+        }
+        res
+    })
+}
+
+
+/// Returns the longest delta chain read from packs.
+///
+/// This is `pack.depth`, but never less than its default, so that packs written by Git or
+/// before `pack.depth` was lowered stay readable.
+pub fn max_read_depth(repo: &Repository) -> u32 {
+    repo.config().pack_depth.max(config::DEFAULT_PACK_DEPTH)
+}
+
+
+/// Finds the pack holding an object and the offset of its entry.
+///
+/// # Arguments
+/// * `repo` - The repository holding the packs.
+/// * `raw_hash` - The binary hash of the object.
+fn find_pack_entry(repo: &Repository, raw_hash: &[u8]) -> Result<Option<(String, u64)>> {
+    with_pack_indexes(repo, |indexes| {
+        indexes.iter().find_map(|index| index.find(raw_hash).map(|offset| (index.pack_path.clone(), offset)))
+    })
+}
+
+
+/// Reads an object from the packs.
+///
+/// # Arguments
+/// * `repo` - The repository holding the packs.
+/// * `object_name` - A hash string identifying the object.
+///
+/// # Returns
+/// * `Some(content)` with the full content of the object (header included), in the same
+///   form as a decoded loose object, or `None` if no pack contains the object.
+///
+/// # Errors
+/// * `Error::Corrupt` naming the pack and the problem if the entry cannot be read.
+/// * `Error::Io` if the pack cannot be read.
+pub fn read_packed_object(repo: &Repository, object_name: &str) -> Result<Option<Vec<u8>>> {
+    let Some((pack_path, offset)) = find_packed_entry(repo, object_name)? else {
+        return Ok(None);
+    };
+    let (object_type, payload) = read_pack_entry(repo, &pack_path, offset, 0).map_err(in_object(object_name))?;
+    Ok(Some(encode_object(repo, object_type, &payload)))
+}
+
+
+/// Reads the type and payload length of a packed object from the header of its entry.
+///
+/// Only the start of a delta is inflated, to read the size of the object it produces; its
+/// type is read from the header of its base.
+///
+/// # Arguments
+/// * `repo` - The repository holding the packs.
+/// * `object_name` - A hash string identifying the object.
+///
+/// # Returns
+/// * `Some((type, length))`, or `None` if no pack contains the object.
+///
+/// # Errors
+/// * See `read_packed_object`.
+pub fn read_packed_object_info(repo: &Repository, object_name: &str) -> Result<Option<(ObjectType, u64)>> {
+    let Some((pack_path, offset)) = find_packed_entry(repo, object_name)? else {
+        return Ok(None);
+    };
+    read_pack_entry_info(repo, &pack_path, offset, 0).map(Some).map_err(in_object(object_name))
+}
+
+
+/// Opens a packed object to read its payload.
+///
+/// Entries stored whole are inflated from the pack as the payload is read, so that large
+/// blobs never need to fit in memory; deltas are resolved in memory first.
+///
+/// # Arguments
+/// * `repo` - The repository holding the packs.
+/// * `object_name` - A hash string identifying the object.
+///
+/// # Returns
+/// * `Some((type, length, reader))`, where `reader` yields at most `length` bytes, or `None`
+///   if no pack contains the object.
+///
+/// # Errors
+/// * See `read_packed_object`.
+pub fn open_packed_object(repo: &Repository, object_name: &str) -> Result<Option<(ObjectType, u64, PackedObjectReader)>> {
+    let Some((pack_path, offset)) = find_packed_entry(repo, object_name)? else {
+        return Ok(None);
+    };
+    let (reader, header) = open_pack_entry(repo, &pack_path, offset).map_err(in_object(object_name))?;
+    if header.base.is_some() {
+        let (object_type, payload) = read_pack_entry(repo, &pack_path, offset, 0).map_err(in_object(object_name))?;
+        return Ok(Some((object_type, payload.len() as u64, Box::new(io::Cursor::new(payload)))));
+    }
+    let object_type = entry_object_type(&pack_path, offset, header.type_id).map_err(in_object(object_name))?;
+    Ok(Some((object_type, header.size, Box::new(ZlibDecoder::new(reader).take(header.size)))))
+}
+
+
+/// Finds the pack holding an object and the offset of its entry.
+///
+/// # Arguments
+/// * `repo` - The repository holding the packs.
+/// * `object_name` - A hash string identifying the object.
+fn find_packed_entry(repo: &Repository, object_name: &str) -> Result<Option<(String, u64)>> {
+    match hex::decode(object_name) {
+        Ok(raw_hash) => find_pack_entry(repo, &raw_hash),
+        Err(_) => Ok(None),
+    }
+}
+
+
+/// Names the object in the message of `Error::Corrupt`, leaving other errors as they are.
+fn in_object(object_name: &str) -> impl Fn(Error) -> Error + '_ {
+    move |e| match e {
+        Error::Corrupt(reason) => Error::Corrupt(format!("corrupt object {}: {}", object_name, reason)),
+        e => e,
+    }
+}
+
+
+/// Describes a problem with the entry at `offset` in a pack.
+fn corrupt_entry(pack_path: &str, offset: u64, reason: impl std::fmt::Display) -> Error {
+    Error::Corrupt(format!("{}: entry at offset {}: {}", pack_path, offset, reason))
+}
+
+
+/// Where the base of a delta entry is stored.
+enum DeltaBase {
+    /// Offset of the base entry in the same pack.
+    Offset(u64),
+    /// Binary hash of the base object, which may be in any pack or loose.
+    Hash(Vec<u8>),
+}
+
+/// Header of a pack entry.
+struct EntryHeader {
+    /// Pack type number of the entry.
+    type_id: u8,
+    /// Length of the inflated entry data: the payload, or the delta.
+    size: u64,
+    /// Base of a delta entry, `None` for objects stored whole.
+    base: Option<DeltaBase>,
+}
+
+
+/// Opens a pack and parses the header of the entry at `offset`.
+///
+/// # Returns
+/// * A reader positioned at the compressed data of the entry, and the parsed header.
+///
+/// # Errors
+/// * `Error::Io` if the pack cannot be opened.
+/// * `Error::Corrupt` if the header is truncated or invalid.
+fn open_pack_entry(repo: &Repository, pack_path: &str, offset: u64) -> Result<(BufReader<File>, EntryHeader)> {
+    let corrupt = |reason: &str| corrupt_entry(pack_path, offset, reason);
+
+    let file = File::open(pack_path).map_err(Error::io(format!("Cannot read pack {}", pack_path)))?;
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(offset)).map_err(|e| corrupt(&e.to_string()))?;
+
+    let mut read_byte = || -> Result<u8> {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte).map_err(|e| corrupt(&e.to_string()))?;
+        Ok(byte[0])
+    };
+
+    // type and size header: 3 type bits and 4 size bits, then 7 size bits per byte
+    let mut byte = read_byte()?;
+    let type_id = (byte >> 4) & 0x7;
+    let mut size = (byte & 0x0f) as u64;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        if shift > 57 {
+            return Err(corrupt("invalid entry size"));
+        }
+        byte = read_byte()?;
+        size |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+    }
+
+    let base = match type_id {
+        OFS_DELTA => {
+            // base offset, relative to this entry, as a big-endian varint with an offset of 1 per byte
+            byte = read_byte()?;
+            let mut distance = (byte & 0x7f) as u64;
+            while byte & 0x80 != 0 {
+                byte = read_byte()?;
+                distance = distance.checked_add(1)
+                    .and_then(|distance| distance.checked_mul(0x80))
+                    .ok_or_else(|| corrupt("invalid delta base offset"))?
+                    | (byte & 0x7f) as u64;
+            }
+            if distance == 0 || distance > offset {
+                return Err(corrupt("invalid delta base offset"));
+            }
+            Some(DeltaBase::Offset(offset - distance))
+        }
+        REF_DELTA => {
+            let mut raw_hash = Vec::new();
+            for _ in 0..repo.hash_algorithm().raw_length() {
+                raw_hash.push(read_byte()?);
+            }
+            Some(DeltaBase::Hash(raw_hash))
+        }
+        _ => None,
+    };
+
+    Ok((reader, EntryHeader { type_id, size, base }))
+}
+
+
+/// Returns the object type of an entry stored whole.
+///
+/// # Errors
+/// * `Error::Corrupt` if the type number is not one of an object.
+fn entry_object_type(pack_path: &str, offset: u64, type_id: u8) -> Result<ObjectType> {
+    match type_id {
+        1 => Ok(ObjectType::Commit),
+        2 => Ok(ObjectType::Tree),
+        3 => Ok(ObjectType::Blob),
+        _ => Err(corrupt_entry(pack_path, offset, format!("unsupported entry type {}", type_id))),
+    }
+}
+
+
+/// Checks that one more delta may be resolved on top of `depth` others.
+///
+/// Delta chains may not be longer than `max_read_depth`, so that a cycle of deltas cannot
+/// recurse forever.
+///
+/// # Errors
+/// * `Error::Corrupt` if the chain is too long.
+fn check_delta_depth(repo: &Repository, pack_path: &str, offset: u64, depth: u32) -> Result<()> {
+    if depth >= max_read_depth(repo) {
+        return Err(corrupt_entry(pack_path, offset, format!("delta chain longer than {}", max_read_depth(repo))));
+    }
+    Ok(())
+}
+
+
+/// Reads and inflates the object stored at `offset` in a pack, resolving deltas.
+///
+/// Delta bases are read recursively, whether given by offset or by hash (see `check_delta_depth`).
+///
+/// # Arguments
+/// * `repo` - The repository holding the pack, where `REF_DELTA` bases are looked up.
+/// * `pack_path` - Path of the `.pack` file.
+/// * `offset` - Offset of the entry in the pack.
+/// * `depth` - Number of deltas already being resolved on top of this entry.
+///
+/// # Returns
+/// * The type of the object and its payload.
+///
+/// # Errors
+/// * `Error::Io` if the pack cannot be opened.
+/// * `Error::Corrupt` if the entry cannot be read, has an unsupported type, its delta cannot
+///   be applied or its delta chain is too long.
+/// * `Error::MissingObject` if the base of a `REF_DELTA` does not exist.
+fn read_pack_entry(repo: &Repository, pack_path: &str, offset: u64, depth: u32) -> Result<(ObjectType, Vec<u8>)> {
+    let corrupt = |reason: String| corrupt_entry(pack_path, offset, reason);
+    let (reader, header) = open_pack_entry(repo, pack_path, offset)?;
+    let size = header.size;
+
+    let base = match header.base {
+        Some(DeltaBase::Offset(base_offset)) => {
+            check_delta_depth(repo, pack_path, offset, depth)?;
+            Some(read_pack_entry(repo, pack_path, base_offset, depth + 1)?)
+        }
+        Some(DeltaBase::Hash(raw_hash)) => {
+            check_delta_depth(repo, pack_path, offset, depth)?;
+            // packed bases count towards the chain; loose ones end it
+            match find_pack_entry(repo, &raw_hash)? {
+                Some((base_pack_path, base_offset)) => Some(read_pack_entry(repo, &base_pack_path, base_offset, depth + 1)?),
+                None => {
+                    let base_name = hex::encode(raw_hash);
+                    let full_content = load_object_file(repo, &base_name)?
+                        .ok_or_else(|| Error::MissingObject(base_name.clone()))?;
+                    let (base_type, base_payload) = decode_object(repo, &base_name, &full_content)?;
+                    Some((base_type, base_payload.to_vec()))
+                }
+            }
+        }
+        None => None,
+    };
+
+    // at most one byte more than declared is decoded, which is enough to detect a mismatch
+    let mut data = Vec::with_capacity(size.min(MAX_PREALLOCATION) as usize);
+    ZlibDecoder::new(reader).take(size.saturating_add(1)).read_to_end(&mut data).map_err(|e| corrupt(e.to_string()))?;
+    if data.len() as u64 != size {
+        return Err(corrupt(format!("size mismatch: {} byte(s) declared, {} decoded", size, data.len())));
+    }
+
+    match base {
+        Some((base_type, base_payload)) => {
+            match delta::apply_delta(&base_payload, &data) {
+                Some(payload) => Ok((base_type, payload)),
+                None => Err(corrupt("delta does not apply to its base".to_string())),
+            }
+        }
+        None => Ok((entry_object_type(pack_path, offset, header.type_id)?, data)),
+    }
+}
+
+
+/// Reads the type and payload length of the object stored at `offset` in a pack, without
+/// inflating it (see `read_packed_object_info`).
+///
+/// # Arguments
+/// * See `read_pack_entry`.
+///
+/// # Errors
+/// * See `read_pack_entry`.
+fn read_pack_entry_info(repo: &Repository, pack_path: &str, offset: u64, depth: u32) -> Result<(ObjectType, u64)> {
+    let (reader, header) = open_pack_entry(repo, pack_path, offset)?;
+
+    let base_type = match header.base {
+        None => return Ok((entry_object_type(pack_path, offset, header.type_id)?, header.size)),
+        Some(DeltaBase::Offset(base_offset)) => {
+            check_delta_depth(repo, pack_path, offset, depth)?;
+            read_pack_entry_info(repo, pack_path, base_offset, depth + 1)?.0
+        }
+        Some(DeltaBase::Hash(raw_hash)) => {
+            check_delta_depth(repo, pack_path, offset, depth)?;
+            match find_pack_entry(repo, &raw_hash)? {
+                Some((base_pack_path, base_offset)) => read_pack_entry_info(repo, &base_pack_path, base_offset, depth + 1)?.0,
+                None => read_object_info(repo, &hex::encode(raw_hash))?.0,
+            }
+        }
+    };
+
+    // a delta starts with the length of its base and the length of the object it produces
+    let mut start = Vec::new();
+    ZlibDecoder::new(reader).take(20).read_to_end(&mut start).map_err(|e| corrupt_entry(pack_path, offset, e))?;
+    let size = delta::read_target_size(&start).ok_or_else(|| corrupt_entry(pack_path, offset, "truncated delta header"))?;
+    Ok((base_type, size as u64))
+}
+
+
+/// Returns the type number a pack records for an object type.
+fn pack_type_id(object_type: ObjectType) -> u8 {
+    match object_type {
+        ObjectType::Commit => 1,
+        ObjectType::Tree => 2,
+        ObjectType::Blob => 3,
+    }
+}
+
+
+/// Encodes the type and size header of a pack entry.
+///
+/// # Arguments
+/// * `type_id` - Pack type number of the entry.
+/// * `size` - Uncompressed size of the entry data.
+fn encode_entry_header(type_id: u8, size: u64) -> Vec<u8> {
+    let mut header = Vec::new();
+    let mut byte = (type_id << 4) | (size & 0x0f) as u8;
+    let mut size = size >> 4;
+    while size != 0 {
+        header.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    header.push(byte);
+    header
+}
+
+
+/// Orders the objects to write so that delta bases always precede their deltas.
+///
+/// Blobs up to `BIG_FILE_THRESHOLD` are grouped by the path they appear at, and ordered by
+/// decreasing size inside a group; every other object is stored whole and comes first.
+///
+/// # Arguments
+/// * `objects` - Objects to pack, as `(hash, type, payload length)`.
+/// * `path_hints` - Map of blob hash → path the blob was found at.
+///
+/// # Returns
+/// * The objects stored whole, then the groups of delta candidates sorted by path
+///   (indices into `objects`).
+fn plan_order(
+    objects: &[(String, ObjectType, u64)],
+    path_hints: &HashMap<String, String>
+) -> (Vec<usize>, Vec<Vec<usize>>) {
+    let mut whole: Vec<usize> = Vec::new();
+    let mut groups: HashMap<&str, Vec<usize>> = HashMap::new();
+
+    for (i, (name, object_type, size)) in objects.iter().enumerate() {
+        match (object_type, path_hints.get(name)) {
+            (ObjectType::Blob, Some(path)) if *size <= BIG_FILE_THRESHOLD => groups.entry(path.as_str()).or_default().push(i),
+            _ => whole.push(i),
+        }
+    }
+
+    let mut paths: Vec<&str> = groups.keys().copied().collect();
+    paths.sort();
+    let groups = paths.into_iter().map(|path| {
+        let mut group = groups.remove(path).unwrap();
+        group.sort_by_key(|&i| std::cmp::Reverse(objects[i].2));
+        group
+    }).collect();
+
+    (whole, groups)
+}
+
+
+/// Finds the smallest delta of a blob against the previous blobs of its group.
+///
+/// Each base whose size is similar (within a factor of 2) and whose delta chain is shorter than
+/// `max_depth` is tried, and the smallest delta is kept if it saves space.
+///
+/// # Arguments
+/// * `window` - The last `DELTA_WINDOW` blobs of the group, as `(index, payload)`.
+/// * `depths` - Delta chain depth of every object written so far.
+/// * `target_payload` - Payload of the blob to store.
+/// * `max_depth` - Maximum length of a delta chain; 0 disables deltas.
+///
+/// # Returns
+/// * The index of the chosen base together with the encoded delta, if any.
+fn find_delta(window: &VecDeque<(usize, Vec<u8>)>, depths: &[u32], target_payload: &[u8], max_depth: u32) -> DeltaChoice {
+    let mut best: DeltaChoice = None;
+    for (base, base_payload) in window.iter().rev() {
+        if depths[*base] >= max_depth || base_payload.len() > target_payload.len() * 2 + BLOCK_SLACK {
+            continue;
+        }
+        let delta = delta::create_delta(base_payload, target_payload);
+        let best_length = best.as_ref().map_or(target_payload.len() / 2, |(_, d)| d.len());
+        if delta.len() < best_length {
+            best = Some((*base, delta));
+        }
+    }
+    best
+}
+
+
+/// Encodes the distance back to the base of an `OFS_DELTA` entry.
+///
+/// Big-endian base-128, where every continuation byte also adds 1, as Git does.
+fn encode_base_distance(mut distance: u64) -> Vec<u8> {
+    let mut encoded = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        encoded.insert(0, 0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    encoded
+}
+
+
+/// Destination of a pack being written: a temporary file in the pack directory, along with
+/// the checksum of everything written so far and the CRC32 of the current entry.
+struct PackWriter {
+    file: BufWriter<NamedTempFile>,
+    hasher: hash::StreamHasher,
+    crc: crc32fast::Hasher,
+    offset: u64,
+}
+
+impl PackWriter {
+    /// Marks the start of a new entry, returning its offset in the pack.
+    fn start_entry(&mut self) -> u64 {
+        self.crc = crc32fast::Hasher::new();
+        self.offset
+    }
+
+    /// Returns the CRC32 of the data written since the last `start_entry`.
+    fn entry_crc(&self) -> u32 {
+        self.crc.clone().finalize()
+    }
+}
+
+impl Write for PackWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.crc.update(&buf[..written]);
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+
+/// Writes the given objects into a new pack and its index.
+///
+/// Entries are streamed into the pack file and hashed as they are written, so only the
+/// blobs being compared for deltas are held in memory. Blobs are stored as deltas against
+/// similar blobs of the same path when this saves space (see `find_delta`); blobs larger than
+/// `BIG_FILE_THRESHOLD` and all other objects are copied as they are stored.
+///
+/// # Arguments
+/// * `repo` - The repository to write the pack into, which holds the objects.
+/// * `objects` - Objects to pack, as `(hash, type, payload length)`.
+/// * `path_hints` - Map of blob hash → path the blob was found at, used to pick delta bases.
+/// * `max_depth` - Maximum length of a delta chain; 0 disables deltas.
+///
+/// # Returns
+/// * `String` - Path of the written `.pack` file.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if an object name is not a hexadecimal hash of the repository.
+/// * `Error::MissingObject` or `Error::Corrupt` if an object cannot be read or does not match
+///   its type and length.
+/// * `Error::Io` if writing either file fails.
+pub fn write_pack(
+    repo: &Repository,
+    objects: &[(String, ObjectType, u64)],
+    path_hints: &HashMap<String, String>,
+    max_depth: u32
+) -> Result<String> {
+    let algorithm = repo.hash_algorithm();
+    let raw_hash_length = algorithm.raw_length();
+    let pack_directory = get_pack_directory(repo);
+    let write_error = || Error::io(format!("Failed to write pack in {}", pack_directory));
+
+    let raw_hashes = objects.iter().map(|(object_name, _, _)| {
+        hex::decode(object_name)
+            .ok()
+            .filter(|raw_hash| raw_hash.len() == raw_hash_length)
+            .ok_or_else(|| Error::InvalidArgument(format!("write_pack: invalid object name {}", object_name)))
+    }).collect::<Result<Vec<Vec<u8>>>>()?;
+    let (whole, groups) = plan_order(objects, path_hints);
+
+    // (raw hash, crc32, offset) of every entry
+    let mut entries: Vec<(Vec<u8>, u32, u64)> = Vec::with_capacity(objects.len());
+    let mut offsets: Vec<u64> = vec![0; objects.len()];
+    let mut depths: Vec<u32> = vec![0; objects.len()];
+
+    fs::create_dir_all(&pack_directory).map_err(write_error())?;
+    let temp_file = NamedTempFile::new_in(&pack_directory).map_err(write_error())?;
+    let mut pack = PackWriter {
+        file: BufWriter::new(temp_file),
+        hasher: hash::StreamHasher::new(algorithm),
+        crc: crc32fast::Hasher::new(),
+        offset: 0,
+    };
+
+    pack.write_all(PACK_SIGNATURE).map_err(write_error())?;
+    pack.write_all(&PACK_VERSION.to_be_bytes()).map_err(write_error())?;
+    pack.write_all(&(objects.len() as u32).to_be_bytes()).map_err(write_error())?;
+
+    for i in whole {
+        let (object_name, object_type, size) = &objects[i];
+        let offset = pack.start_entry();
+        pack.write_all(&encode_entry_header(pack_type_id(*object_type), *size)).map_err(write_error())?;
+        let mut encoder = ZlibEncoder::new(&mut pack, Compression::default());
+        write_object_payload_to(repo, object_name, *object_type, &mut encoder)?;
+        if encoder.total_in() != *size {
+            return Err(Error::Corrupt(format!(
+                "corrupt object {}: {} byte(s) read, {} expected", object_name, encoder.total_in(), size
+            )));
+        }
+        encoder.finish().map_err(write_error())?;
+        entries.push((raw_hashes[i].clone(), pack.entry_crc(), offset));
+        offsets[i] = offset;
+    }
+
+    for group in groups {
+        let mut window: VecDeque<(usize, Vec<u8>)> = VecDeque::with_capacity(DELTA_WINDOW + 1);
+        for target in group {
+            let payload = read_object_payload(repo, &objects[target].0, ObjectType::Blob)?;
+            let offset = pack.start_entry();
+
+            let (header, data) = match find_delta(&window, &depths, &payload, max_depth) {
+                None => (encode_entry_header(pack_type_id(ObjectType::Blob), payload.len() as u64), compress::compress(&payload)),
+                Some((base, delta)) => {
+                    depths[target] = depths[base] + 1;
+                    let mut header = encode_entry_header(OFS_DELTA, delta.len() as u64);
+                    header.extend(encode_base_distance(offset - offsets[base]));
+                    (header, compress::compress(&delta))
+                }
+            };
+            pack.write_all(&header).and_then(|_| pack.write_all(&data)).map_err(write_error())?;
+            entries.push((raw_hashes[target].clone(), pack.entry_crc(), offset));
+            offsets[target] = offset;
+
+            window.push_back((target, payload));
+            if window.len() > DELTA_WINDOW {
+                window.pop_front();
+            }
+        }
+    }
+
+    let PackWriter { mut file, hasher, .. } = pack;
+    let pack_checksum = hex::decode(hasher.finish()).unwrap();
+    file.write_all(&pack_checksum).map_err(write_error())?;
+    let temp_file = file.into_inner().map_err(|e| e.into_error()).map_err(write_error())?;
+
+    entries.sort();
+    entries.dedup_by(|a, b| a.0 == b.0);
+
+    let mut index: Vec<u8> = INDEX_SIGNATURE.to_vec();
+    index.extend(INDEX_VERSION.to_be_bytes());
+
+    let mut fanout = [0u32; 256];
+    for (raw_hash, _, _) in &entries {
+        fanout[raw_hash[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in fanout.iter_mut() {
+        total += *count;
+        *count = total;
+        index.extend(total.to_be_bytes());
+    }
+
+    for (raw_hash, _, _) in &entries {
+        index.extend(raw_hash);
+    }
+    for (_, crc, _) in &entries {
+        index.extend(crc.to_be_bytes());
+    }
+    let mut large_offsets: Vec<u8> = Vec::new();
+    for (_, _, offset) in &entries {
+        if *offset < 0x8000_0000 {
+            index.extend((*offset as u32).to_be_bytes());
+        } else {
+            index.extend((0x8000_0000 | (large_offsets.len() / 8) as u32).to_be_bytes());
+            large_offsets.extend(offset.to_be_bytes());
+        }
+    }
+    index.extend(large_offsets);
+    index.extend(&pack_checksum);
+    let index_checksum = hex::decode(algorithm.digest(&index)).unwrap();
+    index.extend(index_checksum);
+
+    let base_path = PathBuf::from(&pack_directory).join(format!("pack-{}", hex::encode(&pack_checksum)));
+    let pack_path = base_path.with_extension("pack").to_string_lossy().into_owned();
+    let index_path = base_path.with_extension("idx").to_string_lossy().into_owned();
+
+    // The index is written last: a pack is only visible once its index exists.
+    storage::persist_file(temp_file, &pack_path).map_err(Error::io(format!("Failed to write pack file {}", pack_path)))?;
+    storage::write_file(&index_path, &index).map_err(Error::io(format!("Failed to write pack file {}", index_path)))?;
+
+    reset_pack_cache(repo);
+    Ok(pack_path)
+}
+
+
+/// Removes a pack and its index.
+///
+/// # Arguments
+/// * `repo` - The repository holding the pack.
+/// * `pack_path` - Path of the `.pack` file.
+///
+/// # Errors
+/// * `Error::Io` if either file cannot be removed.
+pub fn remove_pack(repo: &Repository, pack_path: &str) -> Result<()> {
+    let index_path = pack_path.strip_suffix(".pack").unwrap_or(pack_path).to_string() + ".idx";
+    let result = storage::remove_file(&index_path).and_then(|_| storage::remove_file(pack_path));
+    reset_pack_cache(repo);
+    result
+}
+
+
+/// Returns the path of the first `.pack` file holding an object.
+///
+/// # Arguments
+/// * `repo` - The repository holding the packs.
+/// * `object_name` - A hash string identifying the object.
+pub fn find_packed_object(repo: &Repository, object_name: &str) -> Result<Option<String>> {
+    let Ok(raw_hash) = hex::decode(object_name) else {
+        return Ok(None);
+    };
+    with_pack_indexes(repo, |indexes| {
+        indexes.iter().find(|index| index.find(&raw_hash).is_some()).map(|index| index.pack_path.clone())
+    })
+}
+
+
+/// Lists the paths of all `.pack` files that have an index.
+pub fn list_packs(repo: &Repository) -> Result<Vec<String>> {
+    with_pack_indexes(repo, |indexes| indexes.iter().map(|index| index.pack_path.clone()).collect())
+}
+
+
+/// Checks that a pack ends with the checksum of its content and has a valid header.
+///
+/// The pack is hashed in chunks, so that it never needs to fit in memory.
+///
+/// # Arguments
+/// * `repo` - The repository holding the pack.
+/// * `pack_path` - Path of the `.pack` file.
+///
+/// # Errors
+/// * `Error::Io` if the pack is unreadable, `Error::Corrupt` describing the problem if it is corrupt.
+pub fn verify_pack(repo: &Repository, pack_path: &str) -> Result<()> {
+    let algorithm = repo.hash_algorithm();
+    let raw_hash_length = algorithm.raw_length() as u64;
+    let read_error = || Error::io(format!("Cannot read pack {}", pack_path));
+    let corrupt = |reason: &str| Error::Corrupt(format!("corrupt pack {}: {}", pack_path, reason));
+
+    let mut file = File::open(pack_path).map_err(read_error())?;
+    let length = file.metadata().map_err(read_error())?.len();
+    let mut signature = [0u8; 4];
+    if length < 12 + raw_hash_length || file.read_exact(&mut signature).is_err() || signature != PACK_SIGNATURE {
+        return Err(corrupt("bad signature"));
+    }
+
+    file.seek(SeekFrom::Start(0)).map_err(read_error())?;
+    let mut reader = BufReader::new(file);
+    let mut hasher = hash::StreamHasher::new(algorithm);
+    io::copy(&mut (&mut reader).take(length - raw_hash_length), &mut hasher).map_err(read_error())?;
+    let mut checksum = Vec::new();
+    reader.read_to_end(&mut checksum).map_err(read_error())?;
+    if hasher.finish() != hex::encode(&checksum) {
+        return Err(corrupt("checksum mismatch"));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::core::blob::{Blob, BlobTrait};
+    use crate::core::config::RepositoryFormat;
+    use crate::utils::hash::HashAlgorithm;
+
+    /// Creates a Git-format repository holding `count` blobs, packed, and returns their names.
+    fn packed_repository(dir: &tempfile::TempDir, count: usize) -> (Repository, Vec<String>, String) {
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        let mut names = Vec::new();
+        for i in 0..count {
+            let mut blob = Blob { hash: None, data: Some(format!("blob {}\n", i).into_bytes()) };
+            blob.write_blob(&repo).unwrap();
+            names.push(blob.hash.unwrap());
+        }
+        let objects: Vec<(String, ObjectType, u64)> = names.iter()
+            .map(|name| (name.clone(), ObjectType::Blob, read_object_info(&repo, name).unwrap().1))
+            .collect();
+        let pack_path = write_pack(&repo, &objects, &HashMap::new(), 0).unwrap();
+        (repo, names, pack_path)
+    }
+
+    #[test]
+    fn fanout_lookup_finds_every_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, names, pack_path) = packed_repository(&dir, 300);
+        let index_path = pack_path.strip_suffix(".pack").unwrap().to_string() + ".idx";
+        let index = load_pack_index(&index_path, 20).unwrap();
+
+        assert_eq!(index.len(), names.len());
+        assert_eq!(index.fanout[255] as usize, names.len());
+        assert!(index.fanout.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let mut offsets = HashSet::new();
+        for name in &names {
+            let offset = index.find(&hex::decode(name).unwrap()).unwrap();
+            assert!(offsets.insert(offset));
+        }
+
+        // names missing from the pack, at both ends of the fanout table and next to a packed name
+        assert_eq!(index.find(&[0x00; 20]), None);
+        assert_eq!(index.find(&[0xff; 20]), None);
+        let mut near = hex::decode(&names[0]).unwrap();
+        near[19] ^= 1;
+        assert_eq!(index.find(&near), None);
+    }
+
+    #[test]
+    fn written_files_are_readable_by_everyone() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, _, pack_path) = packed_repository(&dir, 1);
+        let file_path = dir.path().join("file").to_string_lossy().into_owned();
+        fs::write(&file_path, b"content\n").unwrap();
+        let blob_hash = crate::core::blob::write_blob_file(&repo, &file_path).unwrap();
+
+        for path in [pack_path.clone(), get_object_path(&repo, &blob_hash).unwrap()] {
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o644, "{}", path);
+        }
+    }
+
+    #[test]
+    fn packed_objects_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, names, pack_path) = packed_repository(&dir, 20);
+        verify_pack(&repo, &pack_path).unwrap();
+
+        for (i, name) in names.iter().enumerate() {
+            let (object_type, payload) = read_pack_entry(&repo, &pack_path, find_offset(&repo, name), 0).unwrap();
+            assert_eq!(object_type, ObjectType::Blob);
+            assert_eq!(payload, format!("blob {}\n", i).into_bytes());
+        }
+    }
+
+    #[test]
+    fn truncated_index_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, _, pack_path) = packed_repository(&dir, 10);
+        let index_path = pack_path.strip_suffix(".pack").unwrap().to_string() + ".idx";
+        let content = fs::read(&index_path).unwrap();
+        fs::write(&index_path, &content[..content.len() / 2]).unwrap();
+        assert!(matches!(load_pack_index(&index_path, 20), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn entry_larger_than_declared_is_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, names, pack_path) = packed_repository(&dir, 1);
+        let offset = find_offset(&repo, &names[0]);
+
+        // declare a size of 1 byte for an entry holding 8
+        let mut content = fs::read(&pack_path).unwrap();
+        content[offset as usize] = (content[offset as usize] & 0xf0) | 1;
+        fs::write(&pack_path, &content).unwrap();
+        match read_pack_entry(&repo, &pack_path, offset, 0) {
+            Err(Error::Corrupt(message)) => assert!(message.contains("size mismatch"), "{}", message),
+            other => panic!("{:?}", other.map_err(|e| e.to_string())),
+        }
+    }
+
+    #[test]
+    fn unsorted_fanout_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, _, pack_path) = packed_repository(&dir, 10);
+        let index_path = pack_path.strip_suffix(".pack").unwrap().to_string() + ".idx";
+
+        // the first bucket claims more objects than the whole index holds
+        let mut content = fs::read(&index_path).unwrap();
+        content[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        fs::write(&index_path, &content).unwrap();
+        assert!(matches!(load_pack_index(&index_path, 20), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn delta_cycles_and_overflowing_offsets_are_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, names, pack_path) = packed_repository(&dir, 1);
+        let offset = find_offset(&repo, &names[0]);
+        let delta = compress::compress(&delta::create_delta(b"blob 0\n", b"blob 1\n"));
+
+        // a REF_DELTA whose base is the object itself
+        let mut entry = encode_entry_header(REF_DELTA, delta.len() as u64);
+        entry.extend(hex::decode(&names[0]).unwrap());
+        entry.extend(&delta);
+        replace_entry(&pack_path, offset, &entry);
+        match read_pack_entry(&repo, &pack_path, offset, 0) {
+            Err(Error::Corrupt(message)) => assert!(message.contains("delta chain"), "{}", message),
+            other => panic!("{:?}", other.map_err(|e| e.to_string())),
+        }
+
+        // an OFS_DELTA whose base distance does not fit in 64 bits
+        let mut entry = encode_entry_header(OFS_DELTA, delta.len() as u64);
+        entry.extend([0xff; 10]);
+        entry.push(0x7f);
+        entry.extend(&delta);
+        replace_entry(&pack_path, offset, &entry);
+        match read_pack_entry(&repo, &pack_path, offset, 0) {
+            Err(Error::Corrupt(message)) => assert!(message.contains("invalid delta base offset"), "{}", message),
+            other => panic!("{:?}", other.map_err(|e| e.to_string())),
+        }
+    }
+
+    #[test]
+    fn packed_objects_are_described_and_streamed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+
+        // two versions of the same file, the second one stored as a delta
+        let first: Vec<u8> = (0..2000).flat_map(|i| format!("line {}\n", i).into_bytes()).collect();
+        let mut second = first.clone();
+        second.extend(b"one more line\n");
+        let mut names = Vec::new();
+        for data in [&second, &first] {
+            let mut blob = Blob { hash: None, data: Some(data.clone()) };
+            blob.write_blob(&repo).unwrap();
+            names.push(blob.hash.unwrap());
+        }
+        let objects: Vec<(String, ObjectType, u64)> = names.iter()
+            .map(|name| (name.clone(), ObjectType::Blob, read_object_info(&repo, name).unwrap().1))
+            .collect();
+        let path_hints = names.iter().map(|name| (name.clone(), "file".to_string())).collect();
+        write_pack(&repo, &objects, &path_hints, 10).unwrap();
+        for name in &names {
+            fs::remove_file(get_object_path(&repo, name).unwrap()).unwrap();
+        }
+
+        for (name, data) in names.iter().zip([&second, &first]) {
+            assert_eq!(read_packed_object_info(&repo, name).unwrap(), Some((ObjectType::Blob, data.len() as u64)));
+            let mut payload = Vec::new();
+            write_object_payload_to(&repo, name, ObjectType::Blob, &mut payload).unwrap();
+            assert_eq!(&payload, data);
+        }
+        let (pack_path, offset) = find_packed_entry(&repo, &names[1]).unwrap().unwrap();
+        let (_, header) = open_pack_entry(&repo, &pack_path, offset).unwrap();
+        assert!(header.base.is_some());
+        assert!(matches!(
+            write_object_payload_to(&repo, &names[0], ObjectType::Tree, &mut Vec::new()),
+            Err(Error::Corrupt(_))
+        ));
+    }
+
+    /// Replaces everything from `offset` to the end of a pack with `entry`.
+    fn replace_entry(pack_path: &str, offset: u64, entry: &[u8]) {
+        let mut content = fs::read(pack_path).unwrap();
+        content.truncate(offset as usize);
+        content.extend(entry);
+        fs::write(pack_path, &content).unwrap();
+    }
+
+    fn find_offset(repo: &Repository, name: &str) -> u64 {
+        with_pack_indexes(repo, |indexes| indexes[0].find(&hex::decode(name).unwrap())).unwrap().unwrap()
+    }
+}
@@ -3,134 +3,169 @@
 //! Provides functions for working with Git references, including reading and writing branch heads,
 //! resolving the current branch or commit, and managing symbolic references like `.git/HEAD`.
 
-use std::{io, path::PathBuf, process};
+use std::{fs, io, path::{Path, PathBuf}};
 
 use crate::{core::*, utils::*};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
 
 
 /// Reads the commit hash pointed to by a specific branch head.
 ///
+/// The branch `.git/HEAD` points to may not have a ref file yet (an unborn branch,
+/// as created by `init --format git`); it is then reported as having no commit (`""`).
+///
 /// # Arguments
+/// * `repo` - The repository holding the refs.
 /// * `head_name` - The name of the branch (e.g., "main", "dev").
 ///
 /// # Returns
 /// * `String` - The SHA-1 hash of the commit that the branch points to.
 ///
-/// # Exits
-/// * If the ref file cannot be read or is not valid UTF-8.
-pub fn get_head(head_name: &str) -> String {
-    let ref_path = utils::get_git_directory() + "/refs/heads/" + head_name;
-    match storage::read_file(&ref_path) {
-        Ok(content) => {
-            std::str::from_utf8(&content).expect("Not valid UTF-8").to_string()
-        },
-        Err(e) => {
-            eprintln!("Error when reading head file {} : {}", ref_path, e);
-            process::exit(1)
+/// # Errors
+/// * `Error::MissingRef` if the branch does not exist.
+/// * `Error::Io` if the ref file cannot be read, `Error::Corrupt` if it is not valid UTF-8.
+pub fn get_head(repo: &Repository, head_name: &str) -> Result<String> {
+    let ref_path = repo.git_dir().to_string() + "/refs/heads/" + head_name;
+    match read_ref_file(&ref_path) {
+        Err(Error::Io(_, e)) if e.kind() == io::ErrorKind::NotFound => {
+            if is_unborn_branch(repo, head_name)? {
+                Ok(String::new())
+            } else {
+                Err(Error::MissingRef(format!("Branch {} does not exist.", head_name)))
+            }
         }
+        result => result,
     }
 }
 
 
-/// Attempts to read the commit hash from a branch head without exiting on error.
+/// Reads the commit hash pointed to by a tag.
 ///
 /// # Arguments
-/// * `head_name` - Name of the branch.
+/// * `repo` - The repository holding the refs.
+/// * `tag_name` - Name of the tag (e.g., "v1.0").
 ///
-/// # Returns
-/// * `Result<String, io::Error>` - Either the commit hash or an I/O error.
-pub fn try_get_head(head_name: &str) -> Result<String, io::Error> {
-    let ref_path = utils::get_git_directory() + "/refs/heads/" + head_name;
-    match storage::read_file(&ref_path) {
-        Ok(content) => {
-            Ok(std::str::from_utf8(&content).expect("Not valid UTF-8").to_string())
-        },
-        Err(e) => Err(e)
+/// # Errors
+/// * `Error::MissingRef` if the tag does not exist.
+/// * `Error::Io` if the ref file cannot be read, `Error::Corrupt` if it is not valid UTF-8.
+pub fn get_tag(repo: &Repository, tag_name: &str) -> Result<String> {
+    let ref_path = repo.git_dir().to_string() + "/refs/tags/" + tag_name;
+    match read_ref_file(&ref_path) {
+        Err(Error::Io(_, e)) if e.kind() == io::ErrorKind::NotFound => {
+            Err(Error::MissingRef(format!("Tag {} does not exist.", tag_name)))
+        }
+        result => result,
+    }
+}
+
+
+/// Reads the content of a ref file, without its trailing newline.
+///
+/// # Errors
+/// * `Error::Io` if the file cannot be read, `Error::Corrupt` if it is not valid UTF-8.
+fn read_ref_file(ref_path: &str) -> Result<String> {
+    let content = storage::read_file(ref_path).map_err(Error::io(format!("Error when reading ref file {}", ref_path)))?;
+    match std::str::from_utf8(&content) {
+        Ok(text) => Ok(text.trim_end().to_string()),
+        Err(_) => Err(Error::Corrupt(format!("Ref file {} is not valid UTF-8.", ref_path))),
     }
 }
 
 
+/// Checks whether `.git/HEAD` refers to the given branch by name.
+///
+/// # Arguments
+/// * `repo` - The repository holding the refs.
+/// * `head_name` - Name of the branch.
+fn is_unborn_branch(repo: &Repository, head_name: &str) -> Result<bool> {
+    let head_path = repo.git_dir().to_string() + "/HEAD";
+    Ok(match storage::read_text_file(&head_path) {
+        Ok(content) => content.trim_end().strip_prefix("ref: refs/heads/") == Some(head_name),
+        Err(_) => false,
+    })
+}
+
+
 /// Stores a commit hash into a branch reference file.
 ///
 /// # Arguments
+/// * `repo` - The repository holding the refs.
 /// * `ref_name` - Name of the branch.
 /// * `hash` - SHA-1 hash of the commit to store.
 ///
-/// # Exits
-/// * If the write operation fails.
-pub fn store_head(ref_name: &str, hash: &str) {
-    let ref_path = utils::get_git_directory() + "/refs/heads/" + ref_name;
-    if let Err(e) = storage::write_file(&ref_path, hash.as_bytes()) {
-        eprintln!("Error when writing head file {} : {}", ref_path, e);
-        process::exit(1)
-    }
+/// # Errors
+/// * `Error::Locked` if the branch is locked, `Error::Io` if the write operation fails.
+pub fn store_head(repo: &Repository, ref_name: &str, hash: &str) -> Result<()> {
+    let ref_path = repo.git_dir().to_string() + "/refs/heads/" + ref_name;
+    write_ref(&ref_path, hash)
+}
+
+
+/// Reads `.git/HEAD`, without its trailing newline.
+fn read_head_file(repo: &Repository) -> Result<String> {
+    let head_path = repo.git_dir().to_string() + "/HEAD";
+    read_ref_file(&head_path)
+}
+
+
+/// What `.git/HEAD` points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Head {
+    /// HEAD is on a branch, given by name.
+    Branch(String),
+    /// HEAD is detached on a commit, given by hash.
+    Detached(String),
 }
 
 
 /// Gets the name of the currently checked-out branch, if any.
 ///
+/// # Arguments
+/// * `repo` - The repository holding the refs.
+///
 /// # Returns
 /// * `Option<String>` - Some(branch_name) if HEAD is on a branch, None if in detached HEAD state.
 ///
-/// # Exits
-/// * If `.git/HEAD` is malformed or refers to an invalid object.
-pub fn get_current_branch() -> Option<String> {
-    let head_path = utils::get_git_directory() + "/HEAD";
-    match storage::read_file(&head_path) {
-        Ok(content) => {
-            let content = std::str::from_utf8(&content).expect("Not valid UTF-8").to_string();
-            if content.starts_with("ref: refs/heads/") {
-                return Some(content[16..].to_string());
-            }
-            // checks if content is a CMIT_hash
-            match object::get_object_type(&content) {
-                object::ObjectType::Commit => {
-                    return None
-                }
-                _ => {
-                    eprintln!(".git/HEAD: {} does not refer to a commit.", content);
-                    process::exit(1);
-                }
-            }
-        },
-        Err(e) => {
-            eprintln!("Error when reading HEAD {} : {}", head_path, e);
-            process::exit(1);
-        }
+/// # Errors
+/// * `Error::Corrupt` if `.git/HEAD` is malformed or refers to an invalid object.
+pub fn get_current_branch(repo: &Repository) -> Result<Option<String>> {
+    let content = read_head_file(repo)?;
+    if let Some(branch) = content.strip_prefix("ref: refs/heads/") {
+        return Ok(Some(branch.to_string()));
     }
+    // checks if content is a CMIT_hash
+    check_detached_head(repo, &content)?;
+    Ok(None)
 }
 
 
 /// Resolves the current commit hash pointed to by `.git/HEAD`.
 ///
+/// # Arguments
+/// * `repo` - The repository holding the refs.
+///
 /// # Returns
 /// * `String` - SHA-1 hash of the current commit.
 ///
-/// # Exits
-/// * If `.git/HEAD` is malformed or refers to an invalid object.
-pub fn get_current_commit() -> String {
-    let head_path = utils::get_git_directory() + "/HEAD";
-    match storage::read_file(&head_path) {
-        Ok(content) => {
-            let content = std::str::from_utf8(&content).expect("Not valid UTF-8").to_string();
-            if content.starts_with("ref: refs/heads/") {
-                return get_head(&content[16..].to_string());
-            }
-            // checks if content is a CMIT_hash
-            match object::get_object_type(&content) {
-                object::ObjectType::Commit => {
-                    return content;
-                }
-                _ => {
-                    eprintln!(".git/HEAD: {} does not refer to a commit.", content);
-                    process::exit(1);
-                }
-            }
-        },
-        Err(e) => {
-            eprintln!("Error when reading HEAD {} : {}", head_path, e);
-            process::exit(1);
-        }
+/// # Errors
+/// * `Error::Corrupt` if `.git/HEAD` is malformed or refers to an invalid object.
+pub fn get_current_commit(repo: &Repository) -> Result<String> {
+    let content = read_head_file(repo)?;
+    if let Some(branch) = content.strip_prefix("ref: refs/heads/") {
+        return get_head(repo, branch);
+    }
+    // checks if content is a CMIT_hash
+    check_detached_head(repo, &content)?;
+    Ok(content)
+}
+
+
+/// Checks that a detached `.git/HEAD` holds the hash of a commit.
+fn check_detached_head(repo: &Repository, content: &str) -> Result<()> {
+    match object::get_object_type(repo, content) {
+        Ok(object::ObjectType::Commit) => Ok(()),
+        _ => Err(Error::Corrupt(format!(".git/HEAD: {} does not refer to a commit.", content))),
     }
 }
 
@@ -138,88 +173,307 @@ pub fn get_current_commit() -> String {
 /// Updates `.git/HEAD` to point to a specific branch.
 ///
 /// # Arguments
+/// * `repo` - The repository holding the refs.
 /// * `ref_name` - Name of the branch to reference.
 ///
-/// # Exits
-/// * If the write operation fails.
-pub fn store_current_branch_ref(ref_name: &str) {
-    let head_path = utils::get_git_directory() + "/HEAD";
-    if let Err(e) = storage::write_text_file(&head_path, &format!("ref: refs/heads/{}", ref_name)) {
-        eprintln!("Error writing to HEAD file {} : {}", head_path, e);
-        process::exit(1);
-    }
+/// # Errors
+/// * `Error::Locked` if HEAD is locked, `Error::Io` if the write operation fails.
+pub fn store_current_branch_ref(repo: &Repository, ref_name: &str) -> Result<()> {
+    let head_path = repo.git_dir().to_string() + "/HEAD";
+    write_ref(&head_path, &format!("ref: refs/heads/{}", ref_name))
 }
 
 
 /// Sets `.git/HEAD` directly to a commit hash (detached HEAD state).
 ///
 /// # Arguments
+/// * `repo` - The repository holding the refs.
 /// * `commit_hash` - SHA-1 hash of the commit to set as current.
 ///
-/// # Exits
-/// * If the write operation fails.
-pub fn store_current_branch_commit(commit_hash: &str) {
-    let head_path = utils::get_git_directory() + "/HEAD";
-    if let Err(e) = storage::write_text_file(&head_path, commit_hash) {
-        eprintln!("Error writing to HEAD file {} : {}", head_path, e);
-        process::exit(1);
-    }
+/// # Errors
+/// * `Error::Locked` if HEAD is locked, `Error::Io` if the write operation fails.
+pub fn store_current_branch_commit(repo: &Repository, commit_hash: &str) -> Result<()> {
+    let head_path = repo.git_dir().to_string() + "/HEAD";
+    write_ref(&head_path, commit_hash)
 }
 
 
 /// Lists all available local branch names.
 ///
+/// # Arguments
+/// * `repo` - The repository holding the refs.
+///
 /// # Returns
 /// * `Vec<String>` - A vector containing the names of all local branches.
 ///
-/// # Panics
-/// * If the `refs/heads/` directory cannot be read.
-pub fn get_all_heads() -> Vec<String> {
-    let ref_path = PathBuf::from(utils::get_git_directory()).join("refs").join("heads");
+/// # Errors
+/// * `Error::Io` if the `refs/heads/` directory cannot be read.
+pub fn get_all_heads(repo: &Repository) -> Result<Vec<String>> {
+    let ref_path = PathBuf::from(repo.git_dir()).join("refs").join("heads");
+    let io_error = || Error::io(format!("Error when listing branches in {}", ref_path.display()));
+
+    let mut res = Vec::new();
+
+    for entry in fs::read_dir(&ref_path).map_err(io_error())? {
+        let entry = entry.map_err(io_error())?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        // a lock file is a ref being updated by another process, not a branch
+        if !file_name.ends_with(".lock") {
+            res.push(file_name);
+        }
+    }
 
+    Ok(res)
+}
+
+
+/// Lists every ref under `.git/refs/` (branches, tags and any ref written by `update_ref`).
+///
+/// Unborn branches (the empty branch file written by `init`) hold no commit and are skipped.
+///
+/// # Arguments
+/// * `repo` - The repository holding the refs.
+///
+/// # Returns
+/// * `Vec<(String, String)>` - The full name of each ref (e.g. `refs/tags/v1.0`) and the hash it holds,
+///   sorted by name.
+///
+/// # Errors
+/// * `Error::Io` if a directory under `refs/` or a ref file cannot be read.
+pub fn get_all_refs(repo: &Repository) -> Result<Vec<(String, String)>> {
     let mut res = Vec::new();
+    let mut stack = vec!["refs".to_string()];
 
-    for entry in std::fs::read_dir(ref_path).unwrap() {
-        let entry = entry.unwrap();
-        let file_name = entry.file_name();
-        res.push(file_name.to_string_lossy().to_string());
+    while let Some(ref_dir) = stack.pop() {
+        let dir_path = PathBuf::from(repo.git_dir()).join(&ref_dir);
+        let io_error = || Error::io(format!("Error when listing refs in {}", dir_path.display()));
+        let entries = match fs::read_dir(&dir_path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            entries => entries.map_err(io_error())?,
+        };
+
+        for entry in entries {
+            let entry = entry.map_err(io_error())?;
+            let ref_name = format!("{}/{}", ref_dir, entry.file_name().to_string_lossy());
+            if entry.file_type().map_err(io_error())?.is_dir() {
+                stack.push(ref_name);
+            } else if !ref_name.ends_with(".lock") {
+                // a lock file is a ref being updated by another process, not a ref
+                let hash = read_ref_file(&entry.path().to_string_lossy())?;
+                if !(hash.is_empty() && ref_name.starts_with("refs/heads/")) {
+                    res.push((ref_name, hash));
+                }
+            }
+        }
     }
 
-    res
+    res.sort();
+    Ok(res)
 }
 
 
 /// Creates a new branch reference pointing to a given commit.
 ///
 /// # Arguments
+/// * `repo` - The repository holding the refs.
 /// * `head_name` - Name of the new branch.
 /// * `content` - SHA-1 hash of the commit to point to.
 ///
-/// # Exits
-/// * If the file cannot be created or written to.
-pub fn create_head(head_name: &str, content: &str) {
-    let ref_path = utils::get_git_directory() + "/refs/heads/" + head_name;
-    storage::create_nonexist_file(&ref_path);
-    if let Err(e) = storage::write_text_file(&ref_path, content) {
-        eprintln!("Error writing to {} : {}", ref_path, e);
-        process::exit(1);
+/// # Errors
+/// * `Error::InvalidArgument` if the branch name is not a valid ref name.
+/// * `Error::InvalidState` if the branch already exists.
+/// * `Error::Locked` if it is locked, `Error::Io` if it cannot be written to.
+pub fn create_head(repo: &Repository, head_name: &str, content: &str) -> Result<()> {
+    check_ref_format(&format!("refs/heads/{}", head_name))?;
+    let ref_path = repo.git_dir().to_string() + "/refs/heads/" + head_name;
+    let lock = storage::LockFile::acquire(&ref_path)?;
+    if Path::new(&ref_path).exists() {
+        return Err(Error::InvalidState(format!("File {} already exists", ref_path)));
     }
+    lock.commit(content.as_bytes())
+}
+
+
+/// Deletes a branch reference.
+///
+/// # Arguments
+/// * `repo` - The repository holding the refs.
+/// * `head_name` - Name of the branch to delete.
+///
+/// # Errors
+/// * `Error::Locked` if the branch is locked by another process, `Error::Io` if it cannot be removed.
+pub fn delete_head(repo: &Repository, head_name: &str) -> Result<()> {
+    let ref_path = repo.git_dir().to_string() + "/refs/heads/" + head_name;
+    let _lock = storage::LockFile::acquire(&ref_path)?;
+    fs::remove_file(&ref_path).map_err(Error::io(format!("Error when deleting head file {}", ref_path)))
+}
+
+
+/// Checks that a full ref name follows the rules of `git check-ref-format`.
+///
+/// The name is split into `/`-separated components, none of which may be empty, start with
+/// `.` or end with `.lock`. It may not contain `..`, `@{`, a backslash, a space, a control
+/// character or any of `~ ^ : ? * [`, and may not end with `.`. These rules keep a ref inside
+/// the refs directory and keep it unambiguous in revision expressions.
+///
+/// # Arguments
+/// * `ref_name` - Full name of the ref (e.g. `refs/heads/main`).
+///
+/// # Errors
+/// * `Error::InvalidArgument` if the name breaks one of the rules.
+pub fn check_ref_format(ref_name: &str) -> Result<()> {
+    let invalid = |reason: &str| Err(Error::InvalidArgument(format!("invalid ref name '{}': {}", ref_name, reason)));
+    if ref_name.split('/').any(str::is_empty) {
+        return invalid("it has an empty component or a leading, trailing or double '/'");
+    }
+    if ref_name.split('/').any(|component| component.starts_with('.')) {
+        return invalid("a component starts with '.'");
+    }
+    if ref_name.split('/').any(|component| component.ends_with(".lock")) {
+        return invalid("a component ends with '.lock'");
+    }
+    if ref_name.ends_with('.') {
+        return invalid("it ends with '.'");
+    }
+    if ref_name.contains("..") || ref_name.contains("@{") {
+        return invalid("it contains '..' or '@{'");
+    }
+    if ref_name == "@" {
+        return invalid("'@' is reserved");
+    }
+    if let Some(c) = ref_name.chars().find(|c| c.is_control() || " ~^:?*[\\".contains(*c)) {
+        return invalid(&format!("it contains the character {:?}", c));
+    }
+    Ok(())
+}
+
+
+/// Updates a ref to a new hash, only if it still holds the expected hash (compare-and-swap).
+///
+/// The ref stays locked between the comparison and the write, so that two concurrent updates
+/// expecting the same old hash cannot both succeed.
+///
+/// # Arguments
+/// * `repo` - The repository holding the refs.
+/// * `ref_name` - Full name of the ref (e.g. `refs/heads/main`, `refs/tags/v1.0`), or `HEAD`;
+///   a `HEAD` pointing to a branch updates that branch.
+/// * `new_hash` - Hash to store.
+/// * `expected_hash` - If given, the hash the ref must currently hold; `""` requires the ref
+///   not to exist yet.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if the name is invalid.
+/// * `Error::Locked` if the ref is locked.
+/// * `Error::StaleRef` if it holds another hash than expected.
+/// * `Error::Io` if it cannot be read or written.
+pub fn update_ref(repo: &Repository, ref_name: &str, new_hash: &str, expected_hash: Option<&str>) -> Result<()> {
+    let git_directory = repo.git_dir();
+    let ref_name = match ref_name {
+        "HEAD" => match read_head_file(repo)?.strip_prefix("ref: ") {
+            Some(target) => target.to_string(),
+            None => "HEAD".to_string(),
+        },
+        _ => ref_name.to_string(),
+    };
+    if ref_name != "HEAD" {
+        if !ref_name.starts_with("refs/") {
+            return Err(Error::InvalidArgument(format!("invalid ref name '{}': it is not under refs/", ref_name)));
+        }
+        check_ref_format(&ref_name)?;
+    }
+
+    let ref_path = format!("{}/{}", git_directory, ref_name);
+    let lock = storage::LockFile::acquire(&ref_path)?;
+
+    if let Some(expected_hash) = expected_hash {
+        let current_hash = match read_ref_file(&ref_path) {
+            Ok(content) => content,
+            Err(Error::Io(_, e)) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        if current_hash != expected_hash {
+            let describe = |hash: &str| if hash.is_empty() { "missing".to_string() } else { format!("at {}", hash) };
+            return Err(Error::StaleRef(format!(
+                "cannot update ref '{}': is {} but expected {}",
+                ref_name, describe(&current_hash), describe(expected_hash)
+            )));
+        }
+    }
+
+    lock.commit(new_hash.as_bytes())
+}
+
+
+/// Replaces the content of a ref file while holding its lock.
+///
+/// # Arguments
+/// * `ref_path` - Path of the ref file (a branch head or `.git/HEAD`).
+/// * `content` - Commit hash or symbolic ref to store.
+///
+/// # Errors
+/// * `Error::Locked` if the ref is locked, `Error::Io` if it is unwritable.
+fn write_ref(ref_path: &str, content: &str) -> Result<()> {
+    storage::LockFile::acquire(ref_path)?.commit(content.as_bytes())
 }
 
 
 /// Checks whether one branch is an ancestor of another.
 ///
 /// # Arguments
+/// * `repo` - The repository holding the refs.
 /// * `prev_branch` - Name of the potential ancestor branch.
 /// * `post_branch` - Name of the potential descendant branch.
 ///
 /// # Returns
 /// * `bool` - True if `prev_branch` is an ancestor of `post_branch`.
 ///
-/// # Exits
+/// # Errors
 /// * If either branch does not exist or points to an invalid commit.
-pub fn is_prev_branch(prev_branch: &str, post_branch: &str) -> bool {
-    let prev_commit = get_head(prev_branch);
-    let post_commit = get_head(post_branch);
-    commit::is_prev_commit(&prev_commit, &post_commit)
-}
\ No newline at end of file
+pub fn is_prev_branch(repo: &Repository, prev_branch: &str, post_branch: &str) -> Result<bool> {
+    let prev_commit = get_head(repo, prev_branch)?;
+    let post_commit = get_head(repo, post_branch)?;
+    commit::is_prev_commit(repo, &prev_commit, &post_commit)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::RepositoryFormat;
+    use crate::utils::hash::HashAlgorithm;
+
+    #[test]
+    fn ref_names_are_checked() {
+        for name in ["refs/heads/main", "refs/heads/feature/x-1", "refs/tags/v1.0", "refs/heads/a.b"] {
+            assert!(check_ref_format(name).is_ok(), "{}", name);
+        }
+        for name in [
+            "refs/heads/../config", "/refs/heads/main", "refs/heads/main/", "refs//heads", "refs/heads/.hidden",
+            "refs/heads/main.lock", "refs/heads/main.", "refs/heads/a..b", "refs/heads/a b", "refs/heads/a~1",
+            "refs/heads/a^", "refs/heads/a:b", "refs/heads/a?", "refs/heads/a*", "refs/heads/a[", "refs/heads/a\\b",
+            "refs/heads/a\tb", "refs/heads/a@{1}", "@",
+        ] {
+            assert!(matches!(check_ref_format(name), Err(Error::InvalidArgument(_))), "{}", name);
+        }
+    }
+
+    #[test]
+    fn invalid_refs_are_never_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        let hash = "0".repeat(40);
+
+        for name in ["../../escaped", "a..b", "main.lock", "a b"] {
+            assert!(matches!(create_head(&repo, name, &hash), Err(Error::InvalidArgument(_))), "{}", name);
+        }
+        for name in ["refs/heads/../../escaped", "refs/heads/a~1", "heads/main"] {
+            assert!(matches!(update_ref(&repo, name, &hash, None), Err(Error::InvalidArgument(_))), "{}", name);
+        }
+        assert!(!dir.path().join("escaped").exists());
+        assert!(!dir.path().join(".git/escaped").exists());
+
+        create_head(&repo, "feature/x", &hash).unwrap();
+        update_ref(&repo, "refs/tags/v1", &hash, Some("")).unwrap();
+        assert_eq!(get_all_refs(&repo).unwrap().len(), 2);
+    }
+}
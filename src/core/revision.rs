@@ -0,0 +1,519 @@
+//! Module: revision
+//!
+//! Parses revision expressions naming objects, similar to `git rev-parse`. A revision is:
+//! - a starting point: `HEAD` (or `@`), a branch, a tag, a full ref such as `refs/heads/main`,
+//!   or a full or abbreviated object hash,
+//! - followed by any number of `~<n>` (n-th first-parent ancestor) and `^<n>` (n-th parent,
+//!   `^0` being the commit itself) suffixes, `<n>` defaulting to 1,
+//! - optionally followed by `:<path>`, naming the blob or tree at that path in the commit.
+//!
+//! Ranges name sets of commits: `A..B` is what is reachable from `B` but not from `A`, and
+//! `A...B` what is reachable from either but not from both. An omitted side means `HEAD`.
+//! `CommitWalk` iterates over the commits of such a set.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::core::*;
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+use crate::core::commit::{Commit, CommitData, CommitTrait};
+use crate::core::object::ObjectType;
+use crate::core::tree::{Tree, TreeEntryType, TreeTrait};
+
+
+/// A set of commits named by a revision expression.
+pub enum RevisionRange {
+    /// A single revision: every commit reachable from it.
+    Single(String),
+    /// `A..B`: the commits reachable from `include` but not from `exclude`.
+    Difference { exclude: String, include: String },
+    /// `A...B`: the commits reachable from exactly one of the two.
+    Symmetric(String, String),
+}
+
+impl RevisionRange {
+    /// Returns the commits to walk from, and the commits to leave out of the walk.
+    ///
+    /// # Arguments
+    /// * `repo` - The repository holding the commits.
+    ///
+    /// # Returns
+    /// * `(Vec<String>, HashSet<String>)` - The tips of the range, and the hidden commits.
+    ///
+    /// # Errors
+    /// * If a commit of the range cannot be read.
+    pub fn tips_and_hidden(&self, repo: &Repository) -> Result<(Vec<String>, HashSet<String>)> {
+        Ok(match self {
+            RevisionRange::Single(tip) => (vec![tip.clone()], Default::default()),
+            RevisionRange::Difference { exclude, include } => (vec![include.clone()], ancestors(repo, exclude)?),
+            RevisionRange::Symmetric(left, right) => {
+                let left_ancestors = ancestors(repo, left)?;
+                let hidden = ancestors(repo, right)?.into_iter().filter(|hash| left_ancestors.contains(hash)).collect();
+                (vec![left.clone(), right.clone()], hidden)
+            }
+        })
+    }
+}
+
+
+/// Iterates over commits and their ancestors, breadth-first, each commit once.
+///
+/// Yields `(hash, data)` of every commit; an error ends the walk.
+pub struct CommitWalk<'r> {
+    /// The repository holding the commits.
+    repo: &'r Repository,
+    /// Commits queued or already yielded, plus the hidden ones.
+    visited: HashSet<String>,
+    /// Commits to yield next.
+    queue: VecDeque<String>,
+}
+
+impl<'r> CommitWalk<'r> {
+    /// Starts a walk.
+    ///
+    /// # Arguments
+    /// * `repo` - The repository holding the commits.
+    /// * `tips` - The commits to start from; empty hashes (unborn branches) are skipped.
+    /// * `hidden` - Commits left out of the walk, see `RevisionRange::tips_and_hidden`.
+    pub fn new(repo: &'r Repository, tips: Vec<String>, hidden: HashSet<String>) -> Self {
+        let mut walk = CommitWalk { repo, visited: hidden, queue: Default::default() };
+        for tip in tips {
+            if walk.visited.insert(tip.clone()) {
+                walk.queue.push_back(tip);
+            }
+        }
+        walk
+    }
+}
+
+impl Iterator for CommitWalk<'_> {
+    type Item = Result<(String, CommitData)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let commit_hash = loop {
+            let hash = self.queue.pop_front()?;
+            if !hash.is_empty() {
+                break hash;
+            }
+        };
+
+        let mut commit = Commit { hash: Some(commit_hash.clone()), data: None };
+        if let Err(e) = commit.read_commit(self.repo) {
+            self.queue.clear();
+            return Some(Err(e));
+        }
+        let commit_data = commit.data.unwrap();
+
+        // handle merge commits
+        for parent in &commit_data.parent_commits {
+            if self.visited.insert(parent.clone()) {
+                self.queue.push_back(parent.clone());
+            }
+        }
+        Some(Ok((commit_hash, commit_data)))
+    }
+}
+
+
+/// Collects a commit and all of its ancestors.
+///
+/// # Arguments
+/// * `repo` - The repository holding the commits.
+/// * `commit_hash` - Hash of the commit to start from.
+///
+/// # Returns
+/// * `HashSet<String>` - Hashes of the commit and its ancestors.
+///
+/// # Errors
+/// * If a commit cannot be read.
+pub fn ancestors(repo: &Repository, commit_hash: &str) -> Result<HashSet<String>> {
+    let mut res: HashSet<String> = Default::default();
+    let mut stack: Vec<String> = vec![commit_hash.to_string()];
+
+    while let Some(hash) = stack.pop() {
+        if hash.is_empty() || !res.insert(hash.clone()) {
+            continue;
+        }
+        stack.extend(read_parents(repo, &hash)?);
+    }
+    Ok(res)
+}
+
+
+/// Resolves a revision expression into the hash of the object it names.
+///
+/// # Arguments
+/// * `repo` - The repository the revision is resolved in.
+/// * `spec` - The revision, e.g. `HEAD~2`, `main^2`, `v1.0`, `65df41d:src/main.rs`.
+///
+/// # Returns
+/// * `String` - The full object hash.
+///
+/// # Errors
+/// * `Error::InvalidRevision` describing why the revision is malformed or does not name an
+///   existing object.
+pub fn resolve_revision(repo: &Repository, spec: &str) -> Result<String> {
+    let invalid = |message: String| Error::InvalidRevision(message);
+
+    let (revision, path) = match spec.split_once(':') {
+        Some((revision, path)) => (revision, Some(path)),
+        None => (spec, None),
+    };
+
+    let suffix_start = revision.find(['~', '^']).unwrap_or(revision.len());
+    let (base, mut suffixes) = revision.split_at(suffix_start);
+    let mut hash = resolve_base(repo, base)?;
+
+    while let Some(operator) = suffixes.chars().next() {
+        if !matches!(operator, '~' | '^') {
+            return Err(invalid(format!("invalid revision '{}'", spec)));
+        }
+        let rest = &suffixes[1..];
+        let digits_length = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let count = match &rest[..digits_length] {
+            "" => 1,
+            digits => digits.parse::<usize>().map_err(|_| invalid(format!("invalid revision '{}'", spec)))?,
+        };
+        suffixes = &rest[digits_length..];
+
+        expect_commit(repo, &hash, spec)?;
+
+        match (operator, count) {
+            ('~', _) => {
+                for _ in 0..count {
+                    hash = nth_parent(repo, &hash, 1)?
+                        .ok_or_else(|| invalid(format!("revision '{}' goes past the root commit", spec)))?;
+                }
+            }
+            (_, 0) => {}
+            _ => {
+                hash = nth_parent(repo, &hash, count)?
+                    .ok_or_else(|| invalid(format!("revision '{}': commit has no parent {}", spec, count)))?;
+            }
+        }
+    }
+
+    match path {
+        Some(path) => {
+            expect_commit(repo, &hash, spec)?;
+            resolve_path(repo, &hash, path)?
+                .ok_or_else(|| invalid(format!("path '{}' does not exist in '{}'", path, revision)))
+        }
+        None => Ok(hash),
+    }
+}
+
+
+/// Resolves a revision expression that must name a commit.
+///
+/// # Arguments
+/// * `repo` - The repository the revision is resolved in.
+/// * `spec` - The revision, e.g. `HEAD~2`, `main^2`, `v1.0`, `65df41d`.
+///
+/// # Errors
+/// * `Error::InvalidRevision` if the revision is malformed, does not name an existing object,
+///   or names a tree or blob.
+pub fn resolve_commit(repo: &Repository, spec: &str) -> Result<String> {
+    let hash = resolve_revision(repo, spec)?;
+    expect_commit(repo, &hash, spec)?;
+    Ok(hash)
+}
+
+
+/// Parses a revision or a revision range into commits.
+///
+/// # Arguments
+/// * `repo` - The repository the range is resolved in.
+/// * `spec` - A revision, `A..B` or `A...B`; an omitted side of a range means `HEAD`.
+///
+/// # Errors
+/// * `Error::InvalidRevision` if a side of the range is malformed or does not name a commit.
+pub fn parse_range(repo: &Repository, spec: &str) -> Result<RevisionRange> {
+    let side = |revision: &str| resolve_commit(repo, if revision.is_empty() { "HEAD" } else { revision });
+
+    Ok(if let Some((left, right)) = spec.split_once("...") {
+        RevisionRange::Symmetric(side(left)?, side(right)?)
+    } else if let Some((left, right)) = spec.split_once("..") {
+        RevisionRange::Difference { exclude: side(left)?, include: side(right)? }
+    } else {
+        RevisionRange::Single(resolve_commit(repo, spec)?)
+    })
+}
+
+
+/// Resolves the starting point of a revision: `HEAD`, a ref, or a full or abbreviated hash.
+///
+/// Refs take precedence over abbreviated hashes, as in Git.
+fn resolve_base(repo: &Repository, base: &str) -> Result<String> {
+    if base.is_empty() || base.contains("..") || base.starts_with('/') {
+        return Err(Error::InvalidRevision(format!("invalid revision '{}'", base)));
+    }
+
+    if base == "HEAD" || base == "@" {
+        return match reference::get_current_commit(repo)? {
+            hash if hash.is_empty() => Err(Error::InvalidRevision("HEAD does not point to a commit yet".to_string())),
+            hash => Ok(hash),
+        };
+    }
+    if base.len() == repo.hash_algorithm().hex_length() && base.chars().all(|c| c.is_ascii_hexdigit()) {
+        let hash = base.to_ascii_lowercase();
+        return match object::object_exists(repo, &hash)? {
+            true => Ok(hash),
+            false => Err(Error::InvalidRevision(format!("{} does not match any object", base))),
+        };
+    }
+
+    let branch = base.strip_prefix("refs/heads/").unwrap_or(base);
+    let tag = base.strip_prefix("refs/tags/").unwrap_or(base);
+    if !base.starts_with("refs/heads/") {
+        if let Ok(hash) = reference::get_tag(repo, tag) {
+            return Ok(hash);
+        }
+    }
+    if !base.starts_with("refs/tags/") {
+        match reference::get_head(repo, branch) {
+            Ok(hash) if hash.is_empty() => {
+                return Err(Error::InvalidRevision(format!("branch '{}' does not have any commit yet", branch)));
+            }
+            Ok(hash) => return Ok(hash),
+            Err(_) => {}
+        }
+    }
+
+    object::resolve_object_name(repo, base).map_err(|e| match e {
+        Error::InvalidRevision(message) => Error::InvalidRevision(format!("unknown revision '{}': {}", base, message)),
+        e => e,
+    })
+}
+
+
+/// Checks that an object is a commit, for a revision that requires one.
+fn expect_commit(repo: &Repository, hash: &str, spec: &str) -> Result<()> {
+    match object::get_object_type(repo, hash)? {
+        ObjectType::Commit => Ok(()),
+        other => Err(Error::InvalidRevision(format!("revision '{}' names a {}, not a commit", spec, other.git_name()))),
+    }
+}
+
+
+/// Reads the parents of a commit, without the empty marker of root commits.
+fn read_parents(repo: &Repository, commit_hash: &str) -> Result<Vec<String>> {
+    let mut commit = Commit { hash: Some(commit_hash.to_string()), data: None };
+    commit.read_commit(repo)?;
+    Ok(commit.data.unwrap().parent_commits.into_iter().filter(|parent| !parent.is_empty()).collect())
+}
+
+
+/// Returns the n-th (1-based) parent of a commit, if it has that many.
+fn nth_parent(repo: &Repository, commit_hash: &str, n: usize) -> Result<Option<String>> {
+    Ok(read_parents(repo, commit_hash)?.into_iter().nth(n - 1))
+}
+
+
+/// Looks up a path, relative to the repository root, in the tree of a commit.
+///
+/// # Returns
+/// * `Option<String>` - Hash of the blob or tree at that path (the root tree for an empty path).
+fn resolve_path(repo: &Repository, commit_hash: &str, path: &str) -> Result<Option<String>> {
+    let mut commit = Commit { hash: Some(commit_hash.to_string()), data: None };
+    commit.read_commit(repo)?;
+    let mut hash = commit.data.unwrap().tree_hash;
+    let mut is_tree = true;
+
+    for name in path.split('/').filter(|name| !name.is_empty() && *name != ".") {
+        if !is_tree {
+            return Ok(None);
+        }
+        let mut tree = Tree { hash: Some(hash), data: None };
+        tree.read_tree(repo)?;
+        let Some(entry) = tree.data.unwrap().into_iter().find(|entry| entry.name == name) else {
+            return Ok(None);
+        };
+        is_tree = entry.entry_type == TreeEntryType::Tree;
+        hash = entry.hash;
+    }
+    Ok(Some(hash))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::core::blob::{Blob, BlobTrait};
+    use crate::core::config::RepositoryFormat;
+    use crate::core::tree::TreeEntry;
+    use crate::utils::hash::HashAlgorithm;
+
+    /// A repository holding a small merge history:
+    ///
+    /// ```text
+    /// root --- second --- merge   (master, HEAD)
+    ///     \              /
+    ///      `-- side ----'         (side)
+    /// ```
+    ///
+    /// `v1` tags `root`, whose tree holds `file` and `dir/nested`.
+    struct History {
+        repo: Repository,
+        root: String,
+        second: String,
+        side: String,
+        merge: String,
+        file: String,
+        dir: String,
+        _dir: tempfile::TempDir,
+    }
+
+    fn write_blob(repo: &Repository, data: &[u8]) -> String {
+        let mut blob = Blob { hash: None, data: Some(data.to_vec()) };
+        blob.write_blob(repo).unwrap();
+        blob.hash.unwrap()
+    }
+
+    fn write_tree(repo: &Repository, entries: Vec<(TreeEntryType, &str, &str)>) -> String {
+        let entries = entries.into_iter()
+            .map(|(entry_type, name, hash)| TreeEntry { entry_type, name: name.to_string(), hash: hash.to_string() })
+            .collect();
+        let mut tree = Tree { hash: None, data: Some(entries) };
+        tree.write_tree(repo).unwrap();
+        tree.hash.unwrap()
+    }
+
+    fn write_commit(repo: &Repository, tree_hash: &str, parents: &[&str], message: &str) -> String {
+        let data = CommitData {
+            message: message.as_bytes().to_vec(),
+            user: "user".to_string(),
+            time: "20240101000000000".to_string(),
+            tree_hash: tree_hash.to_string(),
+            parent_commits: if parents.is_empty() { vec![String::new()] } else { parents.iter().map(|p| p.to_string()).collect() },
+        };
+        let mut commit = Commit { hash: None, data: Some(data) };
+        commit.write_commit(repo).unwrap();
+        commit.hash.unwrap()
+    }
+
+    fn history() -> History {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+
+        let file = write_blob(&repo, b"file\n");
+        let nested = write_blob(&repo, b"nested\n");
+        let sub_tree = write_tree(&repo, vec![(TreeEntryType::Blob, "nested", &nested)]);
+        let tree = write_tree(&repo, vec![(TreeEntryType::Blob, "file", &file), (TreeEntryType::Tree, "dir", &sub_tree)]);
+
+        let root = write_commit(&repo, &tree, &[], "root");
+        let second = write_commit(&repo, &tree, &[&root], "second");
+        let side = write_commit(&repo, &tree, &[&root], "side");
+        let merge = write_commit(&repo, &tree, &[&second, &side], "merge");
+
+        reference::update_ref(&repo, "refs/heads/master", &merge, None).unwrap();
+        reference::update_ref(&repo, "refs/heads/side", &side, None).unwrap();
+        reference::update_ref(&repo, "refs/tags/v1", &root, None).unwrap();
+
+        History { repo, root, second, side, merge, file, dir: sub_tree, _dir: dir }
+    }
+
+    fn resolve(history: &History, spec: &str) -> String {
+        resolve_revision(&history.repo, spec).unwrap()
+    }
+
+    fn walk(history: &History, spec: &str) -> HashSet<String> {
+        let (tips, hidden) = parse_range(&history.repo, spec).unwrap().tips_and_hidden(&history.repo).unwrap();
+        CommitWalk::new(&history.repo, tips, hidden).map(|commit| commit.unwrap().0).collect()
+    }
+
+    #[test]
+    fn starting_points() {
+        let h = history();
+        for spec in ["HEAD", "@", "master", "refs/heads/master", &h.merge, &h.merge.to_uppercase(), &h.merge[..7]] {
+            assert_eq!(resolve(&h, spec), h.merge, "{}", spec);
+        }
+        assert_eq!(resolve(&h, "v1"), h.root);
+        assert_eq!(resolve(&h, "refs/tags/v1"), h.root);
+        assert_eq!(resolve(&h, "side"), h.side);
+
+        for spec in ["", "nothing", "refs/tags/master", "/master", "a..b", "abc"] {
+            assert!(matches!(resolve_revision(&h.repo, spec), Err(Error::InvalidRevision(_))), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn ancestry_suffixes() {
+        let h = history();
+        assert_eq!(resolve(&h, "HEAD~"), h.second);
+        assert_eq!(resolve(&h, "HEAD~1"), h.second);
+        assert_eq!(resolve(&h, "HEAD~2"), h.root);
+        assert_eq!(resolve(&h, "HEAD~~"), h.root);
+        assert_eq!(resolve(&h, "HEAD^"), h.second);
+        assert_eq!(resolve(&h, "HEAD^1"), h.second);
+        assert_eq!(resolve(&h, "HEAD^2"), h.side);
+        assert_eq!(resolve(&h, "HEAD^0"), h.merge);
+        assert_eq!(resolve(&h, "HEAD^2~1"), h.root);
+        assert_eq!(resolve(&h, "master^2^"), h.root);
+        assert_eq!(resolve(&h, "v1~0"), h.root);
+
+        for spec in ["HEAD~3", "HEAD^3", "v1^", "HEAD~x", "HEAD^-1"] {
+            assert!(matches!(resolve_revision(&h.repo, spec), Err(Error::InvalidRevision(_))), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn paths() {
+        let h = history();
+        assert_eq!(resolve(&h, "HEAD:file"), h.file);
+        assert_eq!(resolve(&h, "v1:dir"), h.dir);
+        assert_eq!(resolve(&h, "HEAD~2:dir/nested"), write_blob(&h.repo, b"nested\n"));
+
+        assert!(matches!(resolve_revision(&h.repo, "HEAD:missing"), Err(Error::InvalidRevision(_))));
+        assert!(matches!(resolve_revision(&h.repo, "HEAD:file/below"), Err(Error::InvalidRevision(_))));
+        // a path of a blob or tree, not of a commit
+        assert!(resolve_revision(&h.repo, &format!("{}:file", h.file)).is_err());
+        assert!(matches!(resolve_commit(&h.repo, "HEAD:file"), Err(Error::InvalidRevision(_))));
+    }
+
+    #[test]
+    fn ranges() {
+        let h = history();
+        let set = |hashes: &[&String]| hashes.iter().map(|hash| hash.to_string()).collect::<HashSet<String>>();
+
+        assert_eq!(walk(&h, "master"), set(&[&h.merge, &h.second, &h.side, &h.root]));
+        assert_eq!(walk(&h, "side..master"), set(&[&h.merge, &h.second]));
+        assert_eq!(walk(&h, "side.."), set(&[&h.merge, &h.second]));
+        assert_eq!(walk(&h, "..side"), set(&[]));
+        assert_eq!(walk(&h, "HEAD~..side"), set(&[&h.side]));
+        assert_eq!(walk(&h, "HEAD~...side"), set(&[&h.second, &h.side]));
+        assert_eq!(walk(&h, "side...HEAD~"), set(&[&h.second, &h.side]));
+        assert_eq!(walk(&h, "v1...master"), set(&[&h.merge, &h.second, &h.side]));
+
+        assert!(matches!(parse_range(&h.repo, "master..HEAD:file"), Err(Error::InvalidRevision(_))));
+        assert!(matches!(parse_range(&h.repo, "nothing...master"), Err(Error::InvalidRevision(_))));
+    }
+
+    #[test]
+    fn ambiguous_abbreviations() {
+        let h = history();
+
+        // write blobs until two of them share their first MIN_ABBREV_LENGTH digits
+        let mut prefixes: HashMap<String, String> = HashMap::new();
+        let (first, second) = (0..).find_map(|i| {
+            let hash = write_blob(&h.repo, format!("{}\n", i).as_bytes());
+            let prefix = hash[..object::MIN_ABBREV_LENGTH].to_string();
+            prefixes.insert(prefix, hash.clone()).map(|other| (other, hash))
+        }).unwrap();
+
+        let prefix = &first[..object::MIN_ABBREV_LENGTH];
+        let message = match resolve_revision(&h.repo, prefix) {
+            Err(Error::InvalidRevision(message)) => message,
+            other => panic!("{} resolved to {:?}", prefix, other.map_err(|e| e.to_string())),
+        };
+        assert!(message.contains("ambiguous"), "{}", message);
+        assert!(message.contains(&first) && message.contains(&second), "{}", message);
+
+        // a longer prefix tells them apart
+        let length = (0..).find(|&i| first.as_bytes()[i] != second.as_bytes()[i]).unwrap() + 1;
+        assert_eq!(resolve(&h, &first[..length]), first);
+        assert_eq!(resolve(&h, &second[..length]), second);
+    }
+}
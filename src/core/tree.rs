@@ -4,20 +4,48 @@
 //! Tree objects in Git are used to represent directory hierarchies, linking file names
 //! to blob hashes or other trees (subdirectories), with support for symbolic links and executable bits.
 
-use std::process;
+use std::collections::HashSet;
 
-use crate::utils::{hash, serialize};
-use super::object::*;
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+use super::{config::RepositoryFormat, object::*, odb::ObjectDatabase};
 
 /// Represents the type of a tree entry in Git.
-#[derive(PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
 pub enum TreeEntryType {
+    #[default]
     Blob,
     Tree,
     Bsym,
     Bexe,
 }
 
+impl TreeEntryType {
+    /// Returns the file mode Git records for this entry type.
+    pub fn git_mode(&self) -> &'static str {
+        match self {
+            TreeEntryType::Blob => "100644",
+            TreeEntryType::Bexe => "100755",
+            TreeEntryType::Bsym => "120000",
+            TreeEntryType::Tree => "40000",
+        }
+    }
+
+    /// Parses a file mode as recorded in Git tree objects.
+    ///
+    /// # Returns
+    /// * `Some(entry_type)` if the mode is supported, `None` otherwise.
+    pub fn from_git_mode(mode: &str) -> Option<TreeEntryType> {
+        match mode {
+            "100644" | "100664" => Some(TreeEntryType::Blob),
+            "100755" => Some(TreeEntryType::Bexe),
+            "120000" => Some(TreeEntryType::Bsym),
+            "40000" | "040000" => Some(TreeEntryType::Tree),
+            _ => None,
+        }
+    }
+}
+
 /// Represents a single entry within a Git tree object.
 #[derive(PartialEq, Eq, Hash, Clone)]
 pub struct TreeEntry {
@@ -35,124 +63,371 @@ pub struct Tree {
 }
 
 pub trait TreeTrait {
-    fn read_tree(&mut self);
-    fn write_tree(&mut self);
-    fn calculate_hash(&mut self);
+    fn read_tree(&mut self, repo: &Repository) -> Result<()>;
+    fn write_tree(&mut self, repo: &Repository) -> Result<()>;
+    fn calculate_hash(&mut self, repo: &Repository) -> Result<()>;
+    fn read_tree_from(&mut self, db: &dyn ObjectDatabase) -> Result<()>;
+    fn write_tree_to(&mut self, db: &dyn ObjectDatabase) -> Result<()>;
 }
 
 impl TreeTrait for Tree {
     
-    /// Reads the tree content from the Git object store into memory.
+    /// Reads the tree content from the repository's object store into memory.
     ///
-    /// # Panics
-    /// * If `self.hash` is `None` (i.e., no hash is set for reading).
-    /// * If `self.data` is already `Some` (i.e., data is already present).
-    /// * If the content does not start with `"TREE"` magic string.
-    /// * If any line is malformed and doesn't contain three null-separated fields.
+    /// See `read_tree_from` for the requirements.
+    fn read_tree(&mut self, repo: &Repository) -> Result<()> {
+        self.read_tree_from(&repo.odb())
+    }
+
+
+    /// Serializes and writes the current tree's data to the repository's object store.
+    ///
+    /// See `write_tree_to` for the requirements.
+    fn write_tree(&mut self, repo: &Repository) -> Result<()> {
+        if self.hash.is_none() {
+            self.calculate_hash(repo)?;
+        }
+        self.write_tree_to(&repo.odb())
+    }
+
+
+    /// Computes and sets the SHA-1 hash of the tree based on its contents.
     ///
-    /// # Exits
-    /// * If an unknown entry type is encountered, prints an error and exits.
-    fn read_tree(&mut self) {
+    /// # Errors
+    /// * `Error::InvalidArgument` if `self.data` is `None` (i.e., data must exist before hashing),
+    ///   or the entries cannot form a tree (see `serialize_entries`).
+    fn calculate_hash(&mut self, repo: &Repository) -> Result<()> {
+        let Some(data) = self.data.as_ref() else {
+            return Err(Error::InvalidArgument("calculate_hash: the tree has no data.".to_string()));
+        };
+        self.hash = Some(hash_entries(&repo.odb(), data)?);
+        Ok(())
+    }
+
 
-        assert!(self.hash.is_none() == false);
-        assert!(self.data.is_none() == true);
+    /// Reads the tree content from the given object database into memory.
+    ///
+    /// # Errors
+    /// * `Error::InvalidArgument` if `self.hash` is `None` (i.e., no hash is set for reading),
+    ///   or `self.data` is already `Some` (i.e., data is already present).
+    /// * `Error::MissingObject` if the tree does not exist.
+    /// * `Error::Corrupt` if the stored object is not a tree, or any entry is malformed.
+    fn read_tree_from(&mut self, db: &dyn ObjectDatabase) -> Result<()> {
 
-        let hash = self.hash.as_ref().unwrap();
+        let (Some(hash), None) = (self.hash.as_ref(), self.data.as_ref()) else {
+            return Err(Error::InvalidArgument("read_tree: the tree needs a hash and no data.".to_string()));
+        };
 
-        if hash == "" {
+        if hash.is_empty() {
             self.data = Some(Default::default());
-            return ;
-        }
-        
-        let raw_content = read_object_file(hash);
-        let vecu8_content = serialize::deserialize(&raw_content);
-        let full_content = std::str::from_utf8(&vecu8_content).expect("Invalid UTF-8");
-
-        assert!(full_content.len() >= 4);
-        assert!(full_content.starts_with("TREE"));
-
-        let mut entries = Vec::new();
-
-        for line in full_content[4..].lines() {
-            let parts: Vec<&str> = line.split('\0').collect();
-            assert!(parts.len() == 3);
-
-            let kind = parts[0];
-            let name = parts[1].to_string();
-            let hash = parts[2].to_string();
-
-            match kind {
-                "BLOB" => entries.push(TreeEntry { entry_type: TreeEntryType::Blob, name, hash }),
-                "BSYM" => entries.push(TreeEntry { entry_type: TreeEntryType::Bsym, name, hash }),
-                "BEXE" => entries.push(TreeEntry { entry_type: TreeEntryType::Bexe, name, hash }),
-                "TREE" => entries.push(TreeEntry { entry_type: TreeEntryType::Tree, name, hash }),
-                _ => {
-                    eprintln!("read_tree: invalid entry_type: {}", kind);
-                    process::exit(1);
-                }
-            }
+            return Ok(());
         }
 
+        let payload = read_object_payload_from(db, hash, ObjectType::Tree)?;
+
+        let entries = parse_tree_payload(db.format(), db.hash_algorithm().raw_length(), &payload)
+            .map_err(|e| Error::Corrupt(format!("corrupt tree {}: {}", hash, e)))?;
+
         self.data = Some(entries);
+        Ok(())
     }
 
 
-    /// Serializes and writes the current tree's data to the Git object store.
+    /// Serializes and writes the current tree's data to the given object database,
+    /// in the database's format.
     ///
-    /// # Panics
-    /// * If `self.data` is `None` (i.e., data must exist before writing).
+    /// # Errors
+    /// * `Error::InvalidArgument` if `self.data` is `None` (i.e., data must exist before writing),
+    ///   or the entries cannot form a tree (see `serialize_entries`).
     ///
     /// # Notes
-    /// If `self.hash` is `None`, it will be calculated first via `calculate_hash`.
-    fn write_tree(&mut self) {
-
-        assert!(self.data.is_none() == false);
+    /// If `self.hash` is `None`, it is calculated first.
+    fn write_tree_to(&mut self, db: &dyn ObjectDatabase) -> Result<()> {
 
+        let Some(entries) = self.data.as_ref() else {
+            return Err(Error::InvalidArgument("write_tree: the tree has no data.".to_string()));
+        };
         if self.hash.is_none() {
-            self.calculate_hash();
+            self.hash = Some(hash_entries(db, entries)?);
         }
 
-        let entries = self.data.as_ref().unwrap();
-        let mut data: String = Default::default();
-        for entry in entries {
-            data.push_str(&format!("{}\0{}\0{}\n",
-                        match entry.entry_type {
-                            TreeEntryType::Blob => "BLOB",
-                            TreeEntryType::Bsym => "BSYM",
-                            TreeEntryType::Bexe => "BEXE",
-                            TreeEntryType::Tree => "TREE",
-                        },
-                        entry.name,
-                        entry.hash));
+        let payload = serialize_entries(db.format(), entries)?;
+        let full_content = encode_object_as(db.format(), ObjectType::Tree, &payload);
+
+        db.put(self.hash.as_ref().unwrap(), &full_content)
+    }
+}
+
+
+/// Computes the hash of a tree with the given entries as named in the given database (see `calculate_hash`).
+fn hash_entries(db: &dyn ObjectDatabase, entries: &[TreeEntry]) -> Result<String> {
+    let algorithm = db.hash_algorithm();
+    let payload = serialize_entries(db.format(), entries)?;
+    Ok(match db.format() {
+        RepositoryFormat::Native => algorithm.digest(&payload),
+        RepositoryFormat::Git => algorithm.digest(&encode_object_as(db.format(), ObjectType::Tree, &payload)),
+    })
+}
+
+
+/// Serializes tree entries into the payload of a tree object, according to the repository format.
+///
+/// Entries are written in Git's canonical order in both formats, so that equal contents
+/// always give equal tree hashes, whatever order the entries were collected in.
+///
+/// - Native format: one `TYPE\0name\0hash\n` line per entry.
+/// - Git format: `<mode> <name>\0<binary hash>` per entry.
+///
+/// # Arguments
+/// * `format` - The format of the repository the tree belongs to.
+/// * `entries` - Entries of the tree.
+///
+/// # Returns
+/// * `Vec<u8>` - The serialized payload.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if two entries share the same name, or a hash is not hexadecimal.
+fn serialize_entries(format: RepositoryFormat, entries: &[TreeEntry]) -> Result<Vec<u8>> {
+    let sorted_entries = sort_entries(entries)?;
+
+    match format {
+        RepositoryFormat::Native => {
+            let mut data: String = Default::default();
+            for entry in sorted_entries {
+                data.push_str(&format!("{}\0{}\0{}\n",
+                            match entry.entry_type {
+                                TreeEntryType::Blob => "BLOB",
+                                TreeEntryType::Bsym => "BSYM",
+                                TreeEntryType::Bexe => "BEXE",
+                                TreeEntryType::Tree => "TREE",
+                            },
+                            entry.name,
+                            entry.hash));
+            }
+            Ok(data.into_bytes())
         }
+        RepositoryFormat::Git => {
+            let mut data: Vec<u8> = Default::default();
+            for entry in sorted_entries {
+                data.extend_from_slice(format!("{} {}\0", entry.entry_type.git_mode(), entry.name).as_bytes());
+                let raw_hash = hex::decode(&entry.hash).map_err(|_| {
+                    Error::InvalidArgument(format!("write_tree: invalid hash {} for entry {}", entry.hash, entry.name))
+                })?;
+                data.extend(raw_hash);
+            }
+            Ok(data)
+        }
+    }
+}
 
-        let full_content = "TREE".to_string() + &data;
-        let raw_content = serialize::serialize(&full_content.as_bytes());
 
-        write_object_file(self.hash.as_ref().unwrap(), &raw_content);
+/// Sorts tree entries in Git's canonical order (see `git_sort_key`).
+///
+/// # Arguments
+/// * `entries` - Entries of the tree.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if two entries share the same name, whatever their types,
+///   or a name cannot be stored in a tree (see `check_entry_name`).
+fn sort_entries(entries: &[TreeEntry]) -> Result<Vec<&TreeEntry>> {
+    for entry in entries {
+        check_entry_name(&entry.name)?;
+    }
+    if let Some(name) = find_duplicate_name(entries) {
+        return Err(Error::InvalidArgument(format!("write_tree: duplicate entry {} in tree.", name)));
     }
 
+    let mut sorted_entries: Vec<&TreeEntry> = entries.iter().collect();
+    sorted_entries.sort_by_key(|entry| git_sort_key(entry));
+    Ok(sorted_entries)
+}
 
-    /// Computes and sets the SHA-1 hash of the tree based on its contents.
-    ///
-    /// # Panics
-    /// * If `self.data` is `None` (i.e., data must exist before hashing).
-    fn calculate_hash(&mut self) {
-        assert!(self.data.is_none() == false);
-        let entries = self.data.as_ref().unwrap();
-        let mut data: String = Default::default();
-        for entry in entries {
-            data.push_str(&format!("{}\0{}\0{}\n",
-                        match entry.entry_type {
-                            TreeEntryType::Blob => "BLOB",
-                            TreeEntryType::Bsym => "BSYM",
-                            TreeEntryType::Bexe => "BEXE",
-                            TreeEntryType::Tree => "TREE",
-                        },
-                        entry.name,
-                        entry.hash));
+
+/// Checks that a name can be stored as a tree entry.
+///
+/// Native trees separate fields with NUL and entries with newlines, and neither format
+/// allows a `/` inside a single path component.
+///
+/// # Arguments
+/// * `name` - Name of the entry, i.e. one component of a path.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if the name is empty or holds a newline, a NUL or a `/`.
+pub fn check_entry_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains(['\n', '\0', '/']) {
+        return Err(Error::InvalidArgument(format!("invalid tree entry name {:?}.", name)));
+    }
+    Ok(())
+}
+
+
+/// Returns the first name shared by two tree entries, whatever their types.
+fn find_duplicate_name(entries: &[TreeEntry]) -> Option<&str> {
+    let mut names: HashSet<&str> = Default::default();
+    entries.iter().map(|entry| entry.name.as_str()).find(|name| !names.insert(name))
+}
+
+
+/// Checks that parsed tree entries have distinct names and are in Git's canonical order.
+///
+/// The parser enforces ordering for Git trees only: native trees written before entries were
+/// sorted are still read in any order, and `fsck` only warns about them.
+///
+/// # Arguments
+/// * `entries` - Entries of the tree, in stored order.
+///
+/// # Returns
+/// * `Err` describing the first duplicate name or misplaced entry.
+pub fn check_entry_order(entries: &[TreeEntry]) -> std::result::Result<(), String> {
+    if let Some(name) = find_duplicate_name(entries) {
+        return Err(format!("duplicate entry {}", name));
+    }
+    match entries.windows(2).find(|pair| git_sort_key(&pair[0]) > git_sort_key(&pair[1])) {
+        Some(pair) => Err(format!("entries not in canonical order: {} before {}", pair[0].name, pair[1].name)),
+        None => Ok(()),
+    }
+}
+
+
+/// Returns the key Git sorts tree entries by: the name, with a trailing `/` for subtrees.
+///
+/// # Arguments
+/// * `entry` - The tree entry.
+fn git_sort_key(entry: &TreeEntry) -> Vec<u8> {
+    let mut key = entry.name.as_bytes().to_vec();
+    if entry.entry_type == TreeEntryType::Tree {
+        key.push(b'/');
+    }
+    key
+}
+
+
+/// Parses the payload of a tree object.
+///
+/// # Arguments
+/// * `format` - The format of the repository the tree belongs to.
+/// * `raw_hash_length` - Length in bytes of the binary hashes of Git tree entries.
+/// * `payload` - The tree payload (header stripped).
+///
+/// # Returns
+/// * The entries of the tree, or `Err` describing the first malformed entry, or the first
+///   duplicate name (or misplaced entry in the Git format, see `check_entry_order`).
+pub fn parse_tree_payload(format: RepositoryFormat, raw_hash_length: usize, payload: &[u8]) -> std::result::Result<Vec<TreeEntry>, String> {
+    match format {
+        RepositoryFormat::Native => parse_native_entries(payload),
+        RepositoryFormat::Git => parse_git_entries(payload, raw_hash_length),
+    }
+}
+
+
+/// Parses the payload of a native tree object: one `TYPE\0name\0hash\n` line per entry.
+fn parse_native_entries(payload: &[u8]) -> std::result::Result<Vec<TreeEntry>, String> {
+    let full_content = std::str::from_utf8(payload).map_err(|_| "invalid UTF-8".to_string())?;
+
+    let mut entries = Vec::new();
+
+    for line in full_content.lines() {
+        let parts: Vec<&str> = line.split('\0').collect();
+        if parts.len() != 3 {
+            return Err(format!("malformed entry: {:?}", line));
+        }
+
+        let kind = parts[0];
+        let name = parts[1].to_string();
+        let hash = parts[2].to_string();
+
+        match kind {
+            "BLOB" => entries.push(TreeEntry { entry_type: TreeEntryType::Blob, name, hash }),
+            "BSYM" => entries.push(TreeEntry { entry_type: TreeEntryType::Bsym, name, hash }),
+            "BEXE" => entries.push(TreeEntry { entry_type: TreeEntryType::Bexe, name, hash }),
+            "TREE" => entries.push(TreeEntry { entry_type: TreeEntryType::Tree, name, hash }),
+            _ => return Err(format!("invalid entry_type: {}", kind)),
+        }
+    }
+
+    if let Some(name) = find_duplicate_name(&entries) {
+        return Err(format!("duplicate entry {}", name));
+    }
+    Ok(entries)
+}
+
+
+/// Parses the payload of a Git tree object, whose entries hold binary hashes of `raw_hash_length` bytes.
+fn parse_git_entries(payload: &[u8], raw_hash_length: usize) -> std::result::Result<Vec<TreeEntry>, String> {
+    let truncated = || "truncated entry".to_string();
+    let mut entries = Vec::new();
+    let mut pos = 0;
+
+    while pos < payload.len() {
+        let space = pos + payload[pos..].iter().position(|&b| b == b' ').ok_or_else(truncated)?;
+        let nul = space + payload[space..].iter().position(|&b| b == 0).ok_or_else(truncated)?;
+        if nul + 1 + raw_hash_length > payload.len() {
+            return Err(truncated());
         }
-        
-        self.hash = Some(hash::sha1(&data.as_bytes()));
+
+        let mode = String::from_utf8_lossy(&payload[pos..space]).to_string();
+        let name = String::from_utf8_lossy(&payload[space + 1..nul]).to_string();
+        let hash = hex::encode(&payload[nul + 1..nul + 1 + raw_hash_length]);
+
+        let entry_type = TreeEntryType::from_git_mode(&mode).ok_or_else(|| format!("invalid file mode: {}", mode))?;
+        entries.push(TreeEntry { entry_type, name, hash });
+
+        pos = nul + 1 + raw_hash_length;
     }
-}
\ No newline at end of file
+
+    check_entry_order(&entries)?;
+    Ok(entries)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::index;
+    use crate::utils::hash::HashAlgorithm;
+
+    fn entry(entry_type: TreeEntryType, name: &str) -> TreeEntry {
+        TreeEntry { entry_type, name: name.to_string(), hash: "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".to_string() }
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        for format in [RepositoryFormat::Native, RepositoryFormat::Git] {
+            let dir = tempfile::tempdir().unwrap();
+            let repo = Repository::init(&dir.path().to_string_lossy(), None, format, HashAlgorithm::Sha1).unwrap();
+
+            for name in ["a\nb.txt", "a\0b", "a/b", ""] {
+                let mut tree = Tree { hash: None, data: Some(vec![entry(TreeEntryType::Blob, name)]) };
+                assert!(matches!(tree.write_tree(&repo), Err(Error::InvalidArgument(_))), "{:?}", name);
+            }
+
+            std::fs::write(dir.path().join("a\nb.txt"), b"content\n").unwrap();
+            assert!(matches!(repo.add(&["."]), Err(Error::InvalidArgument(_))));
+            assert!(index::read_index(&repo).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn entries_are_written_in_canonical_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+
+        let entries = vec![entry(TreeEntryType::Blob, "a.b"), entry(TreeEntryType::Tree, "a"), entry(TreeEntryType::Blob, "a-b")];
+        let mut reversed = entries.clone();
+        reversed.reverse();
+
+        let mut first = Tree { hash: None, data: Some(entries) };
+        let mut second = Tree { hash: None, data: Some(reversed) };
+        first.write_tree(&repo).unwrap();
+        second.write_tree(&repo).unwrap();
+        assert_eq!(first.hash, second.hash);
+
+        let mut read = Tree { hash: first.hash, data: None };
+        read.read_tree(&repo).unwrap();
+        let names: Vec<String> = read.data.unwrap().into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, ["a-b", "a.b", "a"]);
+
+        let duplicate = vec![entry(TreeEntryType::Blob, "a"), entry(TreeEntryType::Tree, "a")];
+        assert!(matches!(Tree { hash: None, data: Some(duplicate) }.write_tree(&repo), Err(Error::InvalidArgument(_))));
+    }
+}
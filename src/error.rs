@@ -0,0 +1,118 @@
+//! Module: error
+//!
+//! Defines the error type returned by every fallible operation of the crate.
+//! Core functions never print errors or exit: they return an `Error`, and `main` turns it into
+//! a message and an exit code (see `Error::exit_code`).
+
+use std::fmt;
+use std::io;
+
+
+/// An error met while operating on a repository.
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O operation failed; the first field describes what was being done.
+    Io(String, io::Error),
+    /// No `.git` directory was found in the working directory or any of its parents.
+    NotARepository,
+    /// The repository configuration is malformed or unsupported.
+    Config(String),
+    /// An object is not in the object store.
+    MissingObject(String),
+    /// An object, a pack or the index cannot be decoded, or has an unexpected type.
+    Corrupt(String),
+    /// A branch, tag or other ref does not exist.
+    MissingRef(String),
+    /// A revision or object name is malformed, ambiguous, or names the wrong kind of object.
+    InvalidRevision(String),
+    /// A command was given invalid arguments.
+    InvalidArgument(String),
+    /// The operation is not possible in the current state of the repository.
+    InvalidState(String),
+    /// Another process holds the lock on a file.
+    Locked(String),
+    /// A ref did not hold the expected value when it was updated.
+    StaleRef(String),
+    /// The working area has uncommitted changes.
+    DirtyWorktree(String),
+    /// A merge met conflicting changes; one description per conflict.
+    Conflict(Vec<String>),
+    /// An integrity check found the given number of problems.
+    IntegrityCheckFailed(usize),
+}
+
+/// Result type of the fallible operations of the crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+
+impl Error {
+    /// Returns a function wrapping an I/O error with a description of what was being done,
+    /// to be used as `.map_err(Error::io(...))`.
+    ///
+    /// # Arguments
+    /// * `context` - What was being done, e.g. `"Failed to read index"`.
+    pub fn io(context: impl Into<String>) -> impl FnOnce(io::Error) -> Error {
+        let context = context.into();
+        move |e| Error::Io(context, e)
+    }
+
+
+    /// Returns the process exit code reporting this error.
+    ///
+    /// | Code | Error |
+    /// |------|-------|
+    /// | 1    | `Conflict`, `IntegrityCheckFailed` |
+    /// | 2    | `InvalidArgument`, `InvalidRevision` |
+    /// | 3    | `InvalidState`, `DirtyWorktree` |
+    /// | 4    | `MissingRef`, `StaleRef` |
+    /// | 5    | `Locked` |
+    /// | 6    | `MissingObject`, `Corrupt` |
+    /// | 74   | `Io` |
+    /// | 78   | `Config` |
+    /// | 128  | `NotARepository` |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Conflict(_) | Error::IntegrityCheckFailed(_) => 1,
+            Error::InvalidArgument(_) | Error::InvalidRevision(_) => 2,
+            Error::InvalidState(_) | Error::DirtyWorktree(_) => 3,
+            Error::MissingRef(_) | Error::StaleRef(_) => 4,
+            Error::Locked(_) => 5,
+            Error::MissingObject(_) | Error::Corrupt(_) => 6,
+            Error::Io(..) => 74,
+            Error::Config(_) => 78,
+            Error::NotARepository => 128,
+        }
+    }
+}
+
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(context, e) => write!(f, "{}: {}", context, e),
+            Error::NotARepository => write!(f, "Not a git repository (or any of the parent directories)"),
+            Error::Config(message) => write!(f, "Invalid configuration: {}", message),
+            Error::MissingObject(name) => write!(f, "Object {} not found.", name),
+            Error::Corrupt(message)
+            | Error::MissingRef(message)
+            | Error::InvalidRevision(message)
+            | Error::InvalidArgument(message)
+            | Error::InvalidState(message)
+            | Error::Locked(message)
+            | Error::StaleRef(message)
+            | Error::DirtyWorktree(message) => write!(f, "{}", message),
+            Error::Conflict(conflicts) => write!(f, "{}", conflicts.join("\n")),
+            Error::IntegrityCheckFailed(count) => write!(f, "Found {} integrity problem(s).", count),
+        }
+    }
+}
+
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
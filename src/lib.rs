@@ -0,0 +1,28 @@
+//! A version control system implementing a subset features of Git, written in Rust.
+//!
+//! `Repository` is the entry point of the library: it opens or creates a repository and
+//! exposes add, commit, branch, checkout, merge, log and status with typed results.
+//! Every fallible operation returns an `Error` instead of printing it.
+//!
+//! The modules implementing the objects, the index, the refs and the commands are internal:
+//! they are only public for the command line front-end, and may change at any time.
+
+#[doc(hidden)]
+pub mod commands;
+#[doc(hidden)]
+pub mod core;
+mod error;
+mod repository;
+#[doc(hidden)]
+pub mod utils;
+
+pub use commands::merge::MergeOutcome;
+pub use core::commit::CommitData;
+pub use core::config::RepositoryFormat;
+pub use core::index::{FileStat, IndexEntry};
+pub use core::reference::Head;
+pub use core::revision::CommitWalk;
+pub use core::tree::TreeEntryType;
+pub use error::{Error, Result};
+pub use repository::{AddResult, Repository, Status};
+pub use utils::hash::HashAlgorithm;
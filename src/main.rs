@@ -1,212 +1,442 @@
-//! A version control system implementing a subset features of Git, written in Rust.
-//!
-//! Supported commands include:
-//! - `init`: Initialize a new repository
-//! - `add`: Stage files into the index
-//! - `rm`: Remove files from the index or working directory
-//! - `commit`: Record staged changes with a message
-//! - `branch`: Create, delete, or list branches
-//! - `checkout`: Switch branch or commit
-//! - `merge`: Merge two branches
-//! - `status`: Show working tree status
-//! - `log`: View commit history
-//! 
-//! Support additional features include:
-//! - Detailed, beaufitied output (add `-v` or `--verbose`)
-//! - Operating multiple files or folders in one command
-//! - `git log`, `git status` and `git branch`
-//! - Out-of-folder operation, can assign a working directory by `-p` or `--path`
-//! - DETACH HEAD Mode
-//! - Operation avaliability check (check for uncommited changes/stages before checkout or merge), can be turned on by `-f false` or `--force false`
-//! 
-//! Behaviors different to Git:
-//! - Use self-designed structure and base64 encoding for object storage (binary-INcompatible with Git)
-//! - `-f` or `--force` is ON BY DEFAULT
-//! - `-v` or `--verbose` is OFF BY DEFAULT
-
-use clap::{Args, Parser, Subcommand};
-
-mod commands;
-mod utils;
-mod core;
-
-use commands::{
-    add::add,
-    branch::branch,
-    checkout::checkout,
-    commit::commit_index,
-    init::init,
-    merge::merge,
-    rm::remove,
-    status::status,
-    log::log,
-};
-
-
-#[derive(Parser)]
-#[command(name = "git")]
-#[command(version = "0.1.0")]
-#[command(about = "A version control system written in Rust", long_about = None)]
-struct Cli {
-    #[command(subcommand)]
-    command: Commands,
-}
-
-#[derive(Args)]
-struct CommonArgs {
-    /// Working directory path
-    #[arg(short = 'p', long, default_value = ".")]
-    path: String,
-
-    /// Print verbose information
-    #[arg(short = 'v', long, default_value = "true")]
-    verbose: bool,
-}
-
-#[derive(Subcommand)]
-enum Commands {
-    /// Initialize a new Git repository
-    Init {
-        /// Use the specified name for the initial branch in the newly created repository.
-        #[arg(short = 'b', long)]
-        initial_branch: Option<String>,
-
-        #[clap(flatten)]
-        common: CommonArgs,
-    },
-
-    /// Add file(s) to the index (staging area)
-    Add {
-        /// List of files to add
-        files: Vec<String>,
-
-        #[clap(flatten)]
-        common: CommonArgs,
-    },
-
-    /// Remove file(s) from the index
-    Rm {
-        /// Allow recursive removal when a leading directory name is given.
-        #[arg(short = 'r', long, default_value = "false")]
-        recursive: bool,
-
-        /// Only remove cached files (in staging area).
-        #[arg(long, default_value = "false")]
-        cached: bool,
-
-        /// List of files to remove
-        files: Vec<String>,
-
-        #[clap(flatten)]
-        common: CommonArgs,
-    },
-
-    /// Commit staged changes
-    Commit {
-        /// Commit message
-        #[arg(short, long)]
-        message: String,
-
-        #[clap(flatten)]
-        common: CommonArgs,
-    },
-
-    /// Create, list, or delete branches
-    Branch {
-        /// Branch name (if omitted, list branches)
-        name: Option<Vec<String>>,
-
-        /// Delete the specified branch
-        #[arg(short = 'd', long, default_value = "false")]
-        delete: bool,
-
-        #[clap(flatten)]
-        common: CommonArgs,
-    },
-
-    /// Switch to another branch
-    Checkout {
-        /// Target branch name
-        target: String,
-
-        /// Force checkout even there are uncommited changes.
-        #[arg(short = 'f', long, default_value = "false")]
-        force: bool,
-
-        /// Create a new branch and checkout
-        #[arg(short = 'b', long, default_value = "false")]
-        branch: bool,
-
-        #[clap(flatten)]
-        common: CommonArgs,
-    },
-
-    /// Merge the specified branch into the current one
-    Merge {
-        /// Branch to merge from
-        branch: String,
-
-        /// Force merge even there are uncommited changes.
-        #[arg(short = 'f', long, default_value = "false")]
-        force: bool,
-
-        #[clap(flatten)]
-        common: CommonArgs,
-    },
-
-    /// Displays paths that have differences between the index file and the current HEAD commit
-    Status {
-        #[clap(flatten)]
-        common: CommonArgs,
-    },
-
-    /// Displays the log of commits
-    Log {
-        #[clap(flatten)]
-        common: CommonArgs,
-    }
-}
-
-fn main() {
-    
-    let cli = Cli::parse();
-
-    match cli.command {
-        Commands::Init { initial_branch, common } => {
-            utils::utils::set_pwd(&common.path);
-            init(initial_branch);
-        }
-        Commands::Add { files, common } => {
-            utils::utils::set_pwd(&common.path);
-            add(files, common.verbose);
-        }
-        Commands::Rm { files, recursive, cached, common } => {
-            utils::utils::set_pwd(&common.path);
-            remove(files, recursive, cached, common.verbose);
-        }
-        Commands::Commit { message, common } => {
-            utils::utils::set_pwd(&common.path);
-            commit_index(message, common.verbose);
-        }
-        Commands::Branch { name, delete, common } => {
-            utils::utils::set_pwd(&common.path);
-            branch(name, delete, common.verbose);
-        }
-        Commands::Checkout { target, force, branch, common } => {
-            utils::utils::set_pwd(&common.path);
-            checkout(target, force, branch, common.verbose);
-        }
-        Commands::Merge { branch, force, common } => {
-            utils::utils::set_pwd(&common.path);
-            merge(branch, force);
-        }
-        Commands::Status { common } => {
-            utils::utils::set_pwd(&common.path);
-            status();
-        }
-        Commands::Log { common } => {
-            utils::utils::set_pwd(&common.path);
-            log();
-        }
-    }
-}
+//! A version control system implementing a subset features of Git, written in Rust.
+//!
+//! Supported commands include:
+//! - `init`: Initialize a new repository
+//! - `add`: Stage files into the index
+//! - `rm`: Remove files from the index or working directory
+//! - `commit`: Record staged changes with a message
+//! - `branch`: Create, delete, or list branches
+//! - `checkout`: Switch branch or commit
+//! - `merge`: Merge two branches
+//! - `status`: Show working tree status
+//! - `log`: View commit history, of a branch or of a revision range
+//! - `show`: Display a commit and its changes
+//! - `cat-file`: Print the type, size or content of an object
+//! - `hash-object`, `ls-tree`, `write-tree`, `commit-tree`, `update-ref`: Low-level object and ref manipulation for scripts
+//! - `repack`: Pack loose objects into a packfile
+//! - `migrate`: Upgrade the repository to the current object format version
+//! - `fsck`: Verify the integrity of objects and refs
+//! - `gc` (or `prune`): Delete unreachable objects
+//! 
+//! Support additional features include:
+//! - Detailed, beaufitied output (add `-v` or `--verbose`)
+//! - Operating multiple files or folders in one command
+//! - `git log`, `git status` and `git branch`
+//! - Out-of-folder operation, can assign a working directory by `-p` or `--path`
+//! - DETACH HEAD Mode
+//! - Operation avaliability check (check for uncommited changes/stages before checkout or merge), can be turned on by `-f false` or `--force false`
+//! - Git-compatible repository format, chosen by `init --format git`
+//! - SHA-256 object IDs, chosen by `init --object-format sha256`
+//! - Revision expressions (`HEAD~2`, `main^2`, `rev:path`, `A..B`, `A...B`) and abbreviated hashes
+//! 
+//! Behaviors different to Git:
+//! - By default, use self-designed structure and zlib compression for object storage (binary-INcompatible with Git)
+//! - `-f` or `--force` is ON BY DEFAULT
+//! - `-v` or `--verbose` is OFF BY DEFAULT
+
+use std::process;
+
+use clap::{Args, Parser, Subcommand};
+
+use rust_git::commands::{
+    add::add,
+    branch::branch,
+    cat_file::{cat_file, CatFileMode},
+    checkout::checkout,
+    commit::commit_index,
+    commit_tree::commit_tree,
+    fsck::fsck,
+    gc::gc,
+    hash_object::hash_object,
+    init::init,
+    ls_tree::ls_tree,
+    merge::merge,
+    migrate::migrate,
+    repack::repack,
+    rm::remove,
+    show::show,
+    status::status,
+    update_ref::update_ref,
+    write_tree::write_tree,
+    log::log,
+};
+use rust_git::{Repository, Result};
+
+
+#[derive(Parser)]
+#[command(name = "git")]
+#[command(version = "0.1.0")]
+#[command(about = "A version control system written in Rust", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Args)]
+struct CommonArgs {
+    /// Working directory path
+    #[arg(short = 'p', long, default_value = ".")]
+    path: String,
+
+    /// Print verbose information
+    #[arg(short = 'v', long, default_value = "true")]
+    verbose: bool,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Initialize a new Git repository
+    Init {
+        /// Use the specified name for the initial branch in the newly created repository.
+        #[arg(short = 'b', long)]
+        initial_branch: Option<String>,
+
+        /// Repository format: `native`, or `git` for objects and index readable by Git.
+        #[arg(long, default_value = "native", value_parser = ["native", "git"])]
+        format: String,
+
+        /// Hash algorithm naming the objects: `sha1` or `sha256`.
+        #[arg(long, default_value = "sha1", value_parser = ["sha1", "sha256"])]
+        object_format: String,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Add file(s) to the index (staging area)
+    Add {
+        /// List of files to add
+        files: Vec<String>,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Remove file(s) from the index
+    Rm {
+        /// Allow recursive removal when a leading directory name is given.
+        #[arg(short = 'r', long, default_value = "false")]
+        recursive: bool,
+
+        /// Only remove cached files (in staging area).
+        #[arg(long, default_value = "false")]
+        cached: bool,
+
+        /// List of files to remove
+        files: Vec<String>,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Commit staged changes
+    Commit {
+        /// Commit message
+        #[arg(short, long)]
+        message: String,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Create, list, or delete branches
+    Branch {
+        /// Branch name (if omitted, list branches), optionally followed by the start point revision
+        name: Option<Vec<String>>,
+
+        /// Delete the specified branch
+        #[arg(short = 'd', long, default_value = "false")]
+        delete: bool,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Switch to another branch
+    Checkout {
+        /// Target branch name or revision (e.g. `HEAD~2`, `65df41d`)
+        target: String,
+
+        /// Force checkout even there are uncommited changes.
+        #[arg(short = 'f', long, default_value = "false")]
+        force: bool,
+
+        /// Create a new branch and checkout
+        #[arg(short = 'b', long, default_value = "false")]
+        branch: bool,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Merge the specified branch into the current one
+    Merge {
+        /// Branch or revision to merge from
+        branch: String,
+
+        /// Force merge even there are uncommited changes.
+        #[arg(short = 'f', long, default_value = "false")]
+        force: bool,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Displays the current branch, staged and unstaged changes, and untracked files
+    Status {
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Displays the log of commits
+    Log {
+        /// Revision or range (`A..B`, `A...B`) to display (defaults to the current branch)
+        revision: Option<String>,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Display a commit and the files it changed
+    Show {
+        /// Revision of the commit to display
+        #[arg(default_value = "HEAD")]
+        target: String,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Print the type, size or content of an object
+    #[command(group = clap::ArgGroup::new("mode").required(true))]
+    CatFile {
+        /// Print the type of the object
+        #[arg(short = 't', group = "mode")]
+        object_type: bool,
+
+        /// Print the size of the object's content
+        #[arg(short = 's', group = "mode")]
+        size: bool,
+
+        /// Pretty-print the content of the object
+        #[arg(short = 'p', group = "mode")]
+        pretty: bool,
+
+        /// Revision naming the object (e.g. `HEAD`, `65df41d`, `HEAD:src/main.rs`)
+        object: String,
+
+        /// Working directory path (`-p` is taken by pretty-printing)
+        #[arg(long, default_value = ".")]
+        path: String,
+    },
+
+    /// Compute the object hash of files, optionally storing them as blobs
+    HashObject {
+        /// Files to hash
+        files: Vec<String>,
+
+        /// Read the content to hash from stdin instead of files
+        #[arg(long, conflicts_with = "files")]
+        stdin: bool,
+
+        /// Store the content as blobs in the object store
+        #[arg(short = 'w')]
+        write: bool,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// List the entries of a tree
+    LsTree {
+        /// Revision naming a tree, or a commit whose tree to list
+        tree_ish: String,
+
+        /// Recurse into subtrees
+        #[arg(short = 'r')]
+        recursive: bool,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Write the index as trees and print the root tree hash, without committing
+    WriteTree {
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Create a commit object from a tree, without touching the index or HEAD
+    CommitTree {
+        /// Revision naming the tree of the commit
+        tree: String,
+
+        /// Parent commit (repeat for merge commits; omit for a root commit)
+        #[arg(short = 'p')]
+        parents: Vec<String>,
+
+        /// Commit message
+        #[arg(short, long)]
+        message: String,
+
+        /// Working directory path (`-p` is taken by parents)
+        #[arg(long, default_value = ".")]
+        path: String,
+    },
+
+    /// Point a ref to an object, optionally only if it still holds an expected value
+    UpdateRef {
+        /// Full name of the ref (e.g. `refs/heads/main`), or `HEAD`
+        ref_name: String,
+
+        /// Revision of the object to store
+        new_value: String,
+
+        /// Revision the ref must currently point to (all zeros: the ref must not exist)
+        old_value: Option<String>,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Pack all objects into a single packfile and remove the loose objects
+    Repack {
+        /// Maximum delta chain depth (defaults to `pack.depth`, 0 disables deltas).
+        #[arg(long)]
+        depth: Option<u32>,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Upgrade the repository to the current object format version
+    Migrate {
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Verify the integrity of objects, refs and the index
+    Fsck {
+        #[clap(flatten)]
+        common: CommonArgs,
+    },
+
+    /// Delete objects unreachable from the branches, HEAD and the index
+    #[command(alias = "prune")]
+    Gc {
+        /// Minimum age, in seconds, of an unreachable object for it to be deleted (default: two weeks).
+        #[arg(long, default_value_t = 14 * 24 * 60 * 60)]
+        grace_period: u64,
+
+        /// Only list the objects that would be deleted.
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+
+        #[clap(flatten)]
+        common: CommonArgs,
+    }
+}
+
+fn main() {
+    
+    let cli = Cli::parse();
+
+    if let Err(e) = run(cli) {
+        eprintln!("{}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+
+/// Runs the command selected on the command line.
+///
+/// # Errors
+/// * Any error of the command, to be reported by `main`.
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Commands::Init { initial_branch, format, object_format, common } => {
+            init(&common.path, initial_branch, format, object_format)
+        }
+        Commands::Add { files, common } => {
+            let repo = Repository::open(&common.path)?;
+            add(&repo, files, common.verbose)
+        }
+        Commands::Rm { files, recursive, cached, common } => {
+            let repo = Repository::open(&common.path)?;
+            remove(&repo, files, recursive, cached, common.verbose)
+        }
+        Commands::Commit { message, common } => {
+            let repo = Repository::open(&common.path)?;
+            commit_index(&repo, message, common.verbose)
+        }
+        Commands::Branch { name, delete, common } => {
+            let repo = Repository::open(&common.path)?;
+            branch(&repo, name, delete, common.verbose)
+        }
+        Commands::Checkout { target, force, branch, common } => {
+            let repo = Repository::open(&common.path)?;
+            checkout(&repo, target, force, branch, common.verbose)
+        }
+        Commands::Merge { branch, force, common } => {
+            let repo = Repository::open(&common.path)?;
+            merge(&repo, branch, force)
+        }
+        Commands::Status { common } => {
+            let repo = Repository::open(&common.path)?;
+            status(&repo)
+        }
+        Commands::Log { revision, common } => {
+            let repo = Repository::open(&common.path)?;
+            log(&repo, revision)
+        }
+        Commands::Show { target, common } => {
+            let repo = Repository::open(&common.path)?;
+            show(&repo, target)
+        }
+        Commands::CatFile { object_type, size, pretty: _, object, path } => {
+            let repo = Repository::open(&path)?;
+            let mode = match (object_type, size) {
+                (true, _) => CatFileMode::Type,
+                (_, true) => CatFileMode::Size,
+                _ => CatFileMode::Pretty,
+            };
+            cat_file(&repo, mode, object)
+        }
+        Commands::HashObject { files, stdin, write, common } => {
+            let repo = Repository::open(&common.path)?;
+            hash_object(&repo, files, stdin, write)
+        }
+        Commands::LsTree { tree_ish, recursive, common } => {
+            let repo = Repository::open(&common.path)?;
+            ls_tree(&repo, tree_ish, recursive)
+        }
+        Commands::WriteTree { common } => {
+            let repo = Repository::open(&common.path)?;
+            write_tree(&repo)
+        }
+        Commands::CommitTree { tree, parents, message, path } => {
+            let repo = Repository::open(&path)?;
+            commit_tree(&repo, tree, parents, message)
+        }
+        Commands::UpdateRef { ref_name, new_value, old_value, common } => {
+            let repo = Repository::open(&common.path)?;
+            update_ref(&repo, ref_name, new_value, old_value, common.verbose)
+        }
+        Commands::Repack { depth, common } => {
+            let repo = Repository::open(&common.path)?;
+            repack(&repo, depth, common.verbose)
+        }
+        Commands::Migrate { common } => {
+            let repo = Repository::open(&common.path)?;
+            migrate(&repo, common.verbose)
+        }
+        Commands::Fsck { common } => {
+            let repo = Repository::open(&common.path)?;
+            fsck(&repo, common.verbose)
+        }
+        Commands::Gc { grace_period, dry_run, common } => {
+            let repo = Repository::open(&common.path)?;
+            gc(&repo, grace_period, dry_run, common.verbose)
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Module: repository
+//!
+//! Defines `Repository`, the entry point of the library. It exposes the porcelain operations
+//! of the command line front-end (add, commit, branch, checkout, merge, log, status), returning
+//! their results as values instead of printing them.
+//!
+//! A `Repository` is also the context the lower-level functions of `core` and `commands` run
+//! in: it holds the paths of the working area and of the `.git` directory, the configuration,
+//! and the cache of pack indexes. Nothing is stored process-wide, so a process can open several
+//! repositories at once.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::commands::{add, branch, checkout, commit, init, merge, status};
+use crate::commands::merge::MergeOutcome;
+use crate::core::config::{self, Config, RepositoryFormat};
+use crate::core::index::{self, IndexEntry};
+use crate::core::odb::LooseObjectDatabase;
+use crate::core::pack::PackCache;
+use crate::core::reference::{self, Head};
+use crate::core::revision::{self, CommitWalk};
+use crate::error::{Error, Result};
+use crate::utils::{hash::HashAlgorithm, utils};
+
+
+/// A repository opened by the current process.
+pub struct Repository {
+    /// Absolute path of the working area.
+    path: String,
+    /// Absolute path of the `.git` directory.
+    git_dir: String,
+    /// Settings read from `.git/config` when the repository was opened.
+    config: Config,
+    /// Indexes of the packs, loaded on first lookup.
+    packs: PackCache,
+}
+
+
+/// Changes made to the index by `Repository::add`, each sorted by path.
+#[derive(Debug, Clone, Default)]
+pub struct AddResult {
+    /// Files that were not in the index.
+    pub added: Vec<IndexEntry>,
+    /// Files that were in the index but no longer exist.
+    pub removed: Vec<IndexEntry>,
+    /// Files whose content changed, with their new hash.
+    pub modified: Vec<IndexEntry>,
+}
+
+
+/// Differences between the current commit, the index and the working area, each sorted by path.
+#[derive(Debug, Clone)]
+pub struct Status {
+    /// What HEAD points to.
+    pub head: Head,
+    /// Files in the index but not in the commit.
+    pub added: Vec<IndexEntry>,
+    /// Files in the commit but not in the index.
+    pub removed: Vec<IndexEntry>,
+    /// Files whose content or mode differs, as `(in the commit, in the index)`.
+    pub modified: Vec<(IndexEntry, IndexEntry)>,
+    /// Files in the index but missing from the working area.
+    pub deleted: Vec<IndexEntry>,
+    /// Files whose content or mode differs, as `(in the index, in the working area)`.
+    pub changed: Vec<(IndexEntry, IndexEntry)>,
+    /// Paths of the files in the working area but not in the index.
+    pub untracked: Vec<String>,
+}
+
+impl Status {
+    /// Tells whether the index and the working area both match the current commit.
+    pub fn is_clean(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+            && self.deleted.is_empty() && self.changed.is_empty() && self.untracked.is_empty()
+    }
+}
+
+
+impl Repository {
+    /// Opens the repository holding a directory.
+    ///
+    /// # Arguments
+    /// * `path` - The working area, or any directory inside it.
+    ///
+    /// # Errors
+    /// * `Error::Io` if the path does not exist.
+    /// * `Error::NotARepository` if no `.git` directory is found in `path` or its parents.
+    /// * `Error::Config` if the repository configuration is invalid.
+    pub fn open(path: &str) -> Result<Repository> {
+        let abs_path = Path::new(path)
+            .canonicalize()
+            .map_err(Error::io(format!("Error when canonicalizing path {}", path)))?
+            .to_string_lossy()
+            .into_owned();
+
+        let git_dir = utils::find_git_directory(&abs_path)?;
+        let config = config::read_config(&git_dir)?;
+        let path = utils::split_path_by_last(&git_dir).0;
+        Ok(Repository { path, git_dir, config, packs: Default::default() })
+    }
+
+
+    /// Creates a repository in a directory.
+    ///
+    /// An existing repository is never replaced; only the command line `init` reinitializes one.
+    ///
+    /// # Arguments
+    /// * `path` - The working area.
+    /// * `initial_branch` - Optional name of the initial branch (defaults to "master").
+    /// * `format` - Repository format.
+    /// * `hash_algorithm` - Hash algorithm of the object IDs.
+    ///
+    /// # Errors
+    /// * `Error::Io` if the path does not exist.
+    /// * `Error::InvalidState` if the directory already holds a repository.
+    /// * If the repository files cannot be written.
+    pub fn init(
+        path: &str,
+        initial_branch: Option<&str>,
+        format: RepositoryFormat,
+        hash_algorithm: HashAlgorithm
+    ) -> Result<Repository> {
+        let (repo, _) = init::init_core(path, initial_branch.map(str::to_string), format, hash_algorithm, false)?;
+        Ok(repo)
+    }
+
+
+    /// Returns the absolute path of the working area.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+
+    /// Returns the absolute path of the `.git` directory.
+    pub fn git_dir(&self) -> &str {
+        &self.git_dir
+    }
+
+
+    /// Returns the settings of the repository, as read when it was opened.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+
+    /// Returns the hash algorithm naming the objects of the repository.
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.config.hash_algorithm
+    }
+
+
+    /// Returns the object store of the repository.
+    pub fn odb(&self) -> LooseObjectDatabase<'_> {
+        LooseObjectDatabase::new(self)
+    }
+
+
+    /// Returns the cache of the pack indexes of the repository.
+    pub(crate) fn packs(&self) -> &PackCache {
+        &self.packs
+    }
+
+
+    /// Stages files or directories (recursively) into the index.
+    ///
+    /// # Arguments
+    /// * `paths` - Paths to stage, relative to the working area or absolute.
+    ///
+    /// # Errors
+    /// * `Error::Locked` if another process holds the index lock.
+    /// * `Error::InvalidArgument` if a path is outside the working area, or a file name
+    ///   cannot be stored in a tree.
+    /// * If a path does not exist, or a file cannot be hashed or stored.
+    pub fn add(&self, paths: &[&str]) -> Result<AddResult> {
+        let files: Vec<String> = paths.iter().map(|path| self.full_path(path)).collect();
+        let lock = index::lock_index(self)?;
+        let (index, added, removed, modified) = add::add_core(self, &files)?;
+        index::write_locked_index(self, lock, &index)?;
+        Ok(AddResult { added: sorted(added), removed: sorted(removed), modified: sorted(modified) })
+    }
+
+
+    /// Commits the index on the current branch.
+    ///
+    /// # Returns
+    /// * `String` - Hash of the new commit.
+    ///
+    /// # Errors
+    /// * `Error::InvalidState` if HEAD is detached.
+    pub fn commit(&self, message: &str) -> Result<String> {
+        let (_, commit_hash) = commit::commit_index_core(self, message.to_string())?;
+        Ok(commit_hash)
+    }
+
+
+    /// Returns what HEAD points to.
+    ///
+    /// # Errors
+    /// * `Error::Corrupt` if `.git/HEAD` is malformed.
+    pub fn head(&self) -> Result<Head> {
+        match reference::get_current_branch(self)? {
+            Some(branch) => Ok(Head::Branch(branch)),
+            None => Ok(Head::Detached(reference::get_current_commit(self)?)),
+        }
+    }
+
+
+    /// Lists the names of the branches.
+    pub fn branches(&self) -> Result<Vec<String>> {
+        let mut branches = reference::get_all_heads(self)?;
+        branches.sort();
+        Ok(branches)
+    }
+
+
+    /// Creates a branch.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the new branch.
+    /// * `start_point` - Revision of the commit to create the branch at; the current branch
+    ///   if omitted.
+    ///
+    /// # Returns
+    /// * `String` - Hash of the commit the branch points to.
+    ///
+    /// # Errors
+    /// * See `branch::create_branch`.
+    pub fn create_branch(&self, name: &str, start_point: Option<&str>) -> Result<String> {
+        branch::create_branch(self, name, start_point)
+    }
+
+
+    /// Deletes a branch, which must be an ancestor of the current branch.
+    ///
+    /// # Errors
+    /// * See `branch::delete_branches`.
+    pub fn delete_branch(&self, name: &str) -> Result<()> {
+        branch::delete_branches(self, &[name.to_string()])
+    }
+
+
+    /// Switches the working area, the index and HEAD to a branch or a commit.
+    ///
+    /// # Arguments
+    /// * `target` - Name of a branch, or revision of a commit (which detaches HEAD).
+    /// * `force` - If true, discards uncommitted changes instead of failing.
+    ///
+    /// # Errors
+    /// * See `checkout::checkout_core`.
+    pub fn checkout(&self, target: &str, force: bool) -> Result<Head> {
+        checkout::checkout_core(self, target, force)
+    }
+
+
+    /// Merges a branch or revision into the current branch.
+    ///
+    /// # Arguments
+    /// * `revision` - Name of a branch, or revision of a commit.
+    /// * `force` - If true, merges even if there are uncommitted changes.
+    ///
+    /// # Errors
+    /// * `Error::Conflict` if both sides changed the same files; nothing is changed then.
+    /// * See `merge::merge_core`.
+    pub fn merge(&self, revision: &str, force: bool) -> Result<MergeOutcome> {
+        merge::merge_core(self, revision, force)
+    }
+
+
+    /// Iterates over the history of HEAD, or of a revision or range (`A..B`, `A...B`).
+    ///
+    /// # Errors
+    /// * `Error::InvalidRevision` if the revision does not name a commit.
+    pub fn log(&self, revision: Option<&str>) -> Result<CommitWalk<'_>> {
+        let (tips, hidden) = match revision {
+            Some(spec) => revision::parse_range(self, spec)?.tips_and_hidden(self)?,
+            None => (vec![reference::get_current_commit(self)?], HashSet::new()),
+        };
+        Ok(CommitWalk::new(self, tips, hidden))
+    }
+
+
+    /// Compares the current commit, the index and the working area.
+    ///
+    /// # Errors
+    /// * If the index or the current commit cannot be read, or a file cannot be stat-ed or read.
+    pub fn status(&self) -> Result<Status> {
+        status::status_core(self)
+    }
+
+
+    /// Resolves a revision expression (e.g. `HEAD~2`, `main:src/lib.rs`) into an object hash.
+    ///
+    /// # Errors
+    /// * `Error::InvalidRevision` if the revision does not name an existing object.
+    pub fn resolve(&self, revision: &str) -> Result<String> {
+        revision::resolve_revision(self, revision)
+    }
+
+
+    /// Returns a path relative to the working area as an absolute path.
+    fn full_path(&self, path: &str) -> String {
+        Path::new(&self.path).join(path).to_string_lossy().into_owned()
+    }
+}
+
+
+/// Collects index entries sorted by path.
+fn sorted(entries: HashSet<IndexEntry>) -> Vec<IndexEntry> {
+    let mut entries: Vec<IndexEntry> = entries.into_iter().collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::compress;
+
+    #[test]
+    fn init_never_replaces_a_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().into_owned();
+        let repo = Repository::init(&path, None, RepositoryFormat::Native, HashAlgorithm::Sha1).unwrap();
+        std::fs::write(dir.path().join("file"), b"content\n").unwrap();
+        repo.add(&["file"]).unwrap();
+        let commit_hash = repo.commit("first").unwrap();
+
+        assert!(matches!(
+            Repository::init(&path, Some("main"), RepositoryFormat::Git, HashAlgorithm::Sha256),
+            Err(Error::InvalidState(_))
+        ));
+        let repo = Repository::open(&path).unwrap();
+        assert_eq!(repo.config().format, RepositoryFormat::Native);
+        assert_eq!(repo.resolve("HEAD").unwrap(), commit_hash);
+    }
+
+    #[test]
+    fn git_repositories_store_git_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(&dir.path().to_string_lossy(), None, RepositoryFormat::Git, HashAlgorithm::Sha1).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join(".git/HEAD")).unwrap().trim_end(), "ref: refs/heads/master");
+        assert!(dir.path().join(".git/objects").is_dir() && dir.path().join(".git/refs/heads").is_dir());
+
+        std::fs::write(dir.path().join("hello"), b"hello\n").unwrap();
+        repo.add(&["hello"]).unwrap();
+        let commit_hash = repo.commit("greeting").unwrap();
+
+        // loose objects are zlib-compressed `<type> <length>\0<payload>`, named after all of it
+        let read_loose = |hash: &str| {
+            let raw_content = std::fs::read(dir.path().join(format!(".git/objects/{}/{}", &hash[..2], &hash[2..]))).unwrap();
+            compress::decompress(&raw_content).unwrap()
+        };
+        assert_eq!(read_loose("ce013625030ba8dba906f756967f9e9ca394464a"), b"blob 6\0hello\n");
+        let tree_hash = "b4d01e9b0c4a9356736dfddf8830ba9a54f5271c";
+        assert_eq!(repo.resolve("HEAD:").unwrap(), tree_hash);
+
+        let commit = read_loose(&commit_hash);
+        assert_eq!(HashAlgorithm::Sha1.digest(&commit), commit_hash);
+        let commit = String::from_utf8(commit).unwrap();
+        let (header, payload) = commit.split_once('\0').unwrap();
+        assert_eq!(header, format!("commit {}", payload.len()));
+        let lines: Vec<&str> = payload.lines().collect();
+        assert_eq!(lines[0], format!("tree {}", tree_hash));
+        assert!(lines[1].starts_with("author ") && lines[2].starts_with("committer "));
+        assert_eq!(&lines[3..], ["", "greeting"]);
+        assert_eq!(std::fs::read_to_string(dir.path().join(".git/refs/heads/master")).unwrap().trim_end(), commit_hash);
+    }
+}
@@ -0,0 +1,58 @@
+//! Module: compress
+//!
+//! Provides zlib (deflate) compression utilities for object storage.
+//! Objects are stored compressed on disk; legacy repositories stored them as base64 text,
+//! which can be told apart from zlib data by inspecting the stream header.
+
+use std::io::{self, Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+
+/// Compresses a byte slice into a zlib stream.
+///
+/// # Arguments
+/// * `data` - Raw binary data to compress.
+///
+/// # Returns
+/// * A `Vec<u8>` containing the zlib-compressed data.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // deflating into memory never fails
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).expect("deflating into a Vec cannot fail");
+    encoder.finish().expect("deflating into a Vec cannot fail")
+}
+
+
+/// Decompresses a zlib stream back into raw bytes.
+///
+/// # Arguments
+/// * `data` - zlib-compressed data.
+///
+/// # Returns
+/// * The decompressed data, or the error met while inflating it.
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoder = ZlibDecoder::new(data);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+
+/// Checks whether the given data starts with a valid zlib header.
+///
+/// A zlib header consists of a CMF byte (deflate method with a 32K window, `0x78`)
+/// and a FLG byte such that `(CMF << 8 | FLG)` is a multiple of 31.
+/// Base64 text never starts this way for stored objects, so this distinguishes
+/// compressed objects from legacy ones.
+///
+/// # Arguments
+/// * `data` - Raw bytes read from an object file.
+///
+/// # Returns
+/// * `true` if `data` looks like a zlib stream.
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0] == 0x78 && ((data[0] as u16) << 8 | data[1] as u16).is_multiple_of(31)
+}
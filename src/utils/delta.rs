@@ -0,0 +1,348 @@
+//! Module: delta
+//!
+//! Provides creation and application of binary deltas in Git's pack delta format.
+//! A delta describes a target buffer as a sequence of instructions against a base buffer:
+//! - copy: take `size` bytes starting at `offset` from the base,
+//! - insert: take the next 1 to 127 literal bytes from the delta itself.
+//!
+//! The instructions are preceded by the sizes of the base and of the target, both encoded
+//! as little-endian base-128 varints.
+
+use std::collections::HashMap;
+
+/// Length of the blocks of the base that are indexed to find matches.
+const BLOCK_LENGTH: usize = 16;
+/// Largest size a single copy instruction can describe.
+const MAX_COPY_SIZE: usize = 0xff_ffff;
+/// Largest number of literal bytes a single insert instruction can carry.
+const MAX_INSERT_SIZE: usize = 0x7f;
+/// Largest target buffer reserved before applying a delta, whose declared size may be bogus.
+const MAX_PREALLOCATION: usize = 1 << 20;
+
+
+/// Appends `value` as a little-endian base-128 varint.
+fn push_size(delta: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            delta.push(byte);
+            break;
+        }
+        delta.push(byte | 0x80);
+    }
+}
+
+
+/// Reads a little-endian base-128 varint starting at `*pos`, advancing `*pos`.
+fn read_size(delta: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut value: usize = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        if shift > 63 {
+            return None;
+        }
+    }
+}
+
+
+/// Appends insert instructions carrying `literal`.
+fn push_insert(delta: &mut Vec<u8>, literal: &[u8]) {
+    for chunk in literal.chunks(MAX_INSERT_SIZE) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
+
+
+/// Appends copy instructions for `size` bytes of the base starting at `offset`.
+fn push_copy(delta: &mut Vec<u8>, mut offset: usize, mut size: usize) {
+    while size > 0 {
+        let chunk = size.min(MAX_COPY_SIZE);
+        let mut command: u8 = 0x80;
+        let mut operands = Vec::with_capacity(7);
+        for i in 0..4 {
+            let byte = ((offset >> (i * 8)) & 0xff) as u8;
+            if byte != 0 {
+                command |= 1 << i;
+                operands.push(byte);
+            }
+        }
+        for i in 0..3 {
+            let byte = ((chunk >> (i * 8)) & 0xff) as u8;
+            if byte != 0 {
+                command |= 1 << (4 + i);
+                operands.push(byte);
+            }
+        }
+        delta.push(command);
+        delta.extend(operands);
+        offset += chunk;
+        size -= chunk;
+    }
+}
+
+
+/// Computes a delta that rebuilds `target` from `base`.
+///
+/// Blocks of `BLOCK_LENGTH` bytes of the base are indexed; the target is scanned for
+/// occurrences of those blocks, which are extended as far as possible and emitted as copies.
+/// Everything else is emitted as literal inserts.
+///
+/// # Arguments
+/// * `base` - The buffer the delta refers to.
+/// * `target` - The buffer the delta describes.
+///
+/// # Returns
+/// * `Vec<u8>` - The encoded delta.
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    push_size(&mut delta, base.len());
+    push_size(&mut delta, target.len());
+
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    if base.len() >= BLOCK_LENGTH {
+        for start in (0..base.len() - BLOCK_LENGTH + 1).step_by(BLOCK_LENGTH) {
+            blocks.entry(&base[start..start + BLOCK_LENGTH]).or_insert(start);
+        }
+    }
+
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while pos + BLOCK_LENGTH <= target.len() {
+        let Some(&base_start) = blocks.get(&target[pos..pos + BLOCK_LENGTH]) else {
+            pos += 1;
+            continue;
+        };
+
+        // extend the match backwards over pending literals, then forwards
+        let mut match_start = pos;
+        let mut match_base = base_start;
+        while match_start > literal_start && match_base > 0 && target[match_start - 1] == base[match_base - 1] {
+            match_start -= 1;
+            match_base -= 1;
+        }
+        let mut match_end = pos + BLOCK_LENGTH;
+        let mut match_base_end = base_start + BLOCK_LENGTH;
+        while match_end < target.len() && match_base_end < base.len() && target[match_end] == base[match_base_end] {
+            match_end += 1;
+            match_base_end += 1;
+        }
+
+        push_insert(&mut delta, &target[literal_start..match_start]);
+        push_copy(&mut delta, match_base, match_end - match_start);
+        pos = match_end;
+        literal_start = match_end;
+    }
+    push_insert(&mut delta, &target[literal_start..]);
+
+    delta
+}
+
+
+/// Reads the size of the target a delta produces, from the header at its start.
+///
+/// # Arguments
+/// * `delta` - The encoded delta, or at least its first 20 bytes.
+///
+/// # Returns
+/// * `Some(size)`, or `None` if the header is truncated.
+pub fn read_target_size(delta: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+    read_size(delta, &mut pos)?;
+    read_size(delta, &mut pos)
+}
+
+
+/// Rebuilds a target buffer by applying a delta to its base.
+///
+/// # Arguments
+/// * `base` - The buffer the delta refers to.
+/// * `delta` - The encoded delta.
+///
+/// # Returns
+/// * `Some(target)` if the delta is well-formed and matches the base, `None` otherwise,
+///   including when its instructions produce more or fewer bytes than the declared target size.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut pos = 0;
+    if read_size(delta, &mut pos)? != base.len() {
+        return None;
+    }
+    let target_size = read_size(delta, &mut pos)?;
+    let mut target = Vec::with_capacity(target_size.min(MAX_PREALLOCATION));
+
+    while pos < delta.len() {
+        let command = delta[pos];
+        pos += 1;
+        if command & 0x80 != 0 {
+            let mut offset: usize = 0;
+            let mut size: usize = 0;
+            for i in 0..4 {
+                if command & (1 << i) != 0 {
+                    offset |= (*delta.get(pos)? as usize) << (i * 8);
+                    pos += 1;
+                }
+            }
+            for i in 0..3 {
+                if command & (1 << (4 + i)) != 0 {
+                    size |= (*delta.get(pos)? as usize) << (i * 8);
+                    pos += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            if size > target_size - target.len() {
+                return None;
+            }
+            target.extend_from_slice(base.get(offset..offset.checked_add(size)?)?);
+        } else if command != 0 {
+            let size = command as usize;
+            if size > target_size - target.len() {
+                return None;
+            }
+            target.extend_from_slice(delta.get(pos..pos + size)?);
+            pos += size;
+        } else {
+            return None;
+        }
+    }
+
+    if target.len() == target_size { Some(target) } else { None }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random bytes.
+    fn noise(length: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..length).map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        }).collect()
+    }
+
+    fn header(base_size: usize, target_size: usize) -> Vec<u8> {
+        let mut delta = Vec::new();
+        push_size(&mut delta, base_size);
+        push_size(&mut delta, target_size);
+        delta
+    }
+
+    fn assert_round_trip(base: &[u8], target: &[u8]) {
+        let delta = create_delta(base, target);
+        assert_eq!(apply_delta(base, &delta).as_deref(), Some(target));
+    }
+
+    #[test]
+    fn round_trips() {
+        let base = noise(5000, 1);
+        let mut edited = base.clone();
+        edited.splice(1000..1010, noise(300, 2));
+        edited.truncate(4500);
+        edited.extend(noise(200, 3));
+
+        assert_round_trip(b"", b"");
+        assert_round_trip(b"", &noise(1000, 4));
+        assert_round_trip(&base, b"");
+        assert_round_trip(&base, &base);
+        assert_round_trip(&base, &edited);
+        assert_round_trip(&edited, &base);
+        assert_round_trip(&base, &noise(5000, 5));
+    }
+
+    #[test]
+    fn identical_buffers_are_copied() {
+        let base = noise(200_000, 6);
+        let delta = create_delta(&base, &base);
+        assert!(delta.len() < 20);
+        assert_eq!(apply_delta(&base, &delta), Some(base));
+    }
+
+    #[test]
+    fn long_literals_are_split_into_inserts() {
+        let literal = noise(300, 7);
+        let mut delta = Vec::new();
+        push_insert(&mut delta, &literal);
+        // 127 + 127 + 46 bytes, each behind its length
+        assert_eq!(delta.len(), 303);
+        assert_eq!((delta[0], delta[128], delta[256]), (127, 127, 46));
+
+        let mut full = header(0, literal.len());
+        full.extend(delta);
+        assert_eq!(apply_delta(b"", &full), Some(literal));
+    }
+
+    #[test]
+    fn copy_operands_skip_zero_bytes() {
+        let base = noise(0x10100, 8);
+        let mut delta = header(base.len(), 0x20);
+        push_copy(&mut delta, 0x010001, 0x20);
+        // offset bytes 1 and 3, size byte 1
+        assert_eq!(&delta[delta.len() - 4..], &[0x80 | 0x01 | 0x04 | 0x10, 0x01, 0x01, 0x20]);
+        assert_eq!(apply_delta(&base, &delta).as_deref(), Some(&base[0x010001..0x010021]));
+    }
+
+    #[test]
+    fn copy_without_size_means_0x10000_bytes() {
+        let base = noise(0x10000, 9);
+        let mut delta = header(base.len(), base.len());
+        delta.push(0x80);
+        assert_eq!(apply_delta(&base, &delta).as_deref(), Some(&base[..]));
+
+        let mut encoded = header(base.len(), base.len());
+        push_copy(&mut encoded, 0, 0x10000);
+        assert_eq!(apply_delta(&base, &encoded).as_deref(), Some(&base[..]));
+    }
+
+    #[test]
+    fn malformed_deltas_are_rejected() {
+        let base = noise(100, 10);
+        let valid = create_delta(&base, &base[10..90]);
+        assert!(apply_delta(&base, &valid).is_some());
+
+        // wrong base
+        assert_eq!(apply_delta(&base[1..], &valid), None);
+        // truncated instructions
+        assert_eq!(apply_delta(&base, &valid[..valid.len() - 1]), None);
+
+        // reserved command 0
+        let mut delta = header(base.len(), 1);
+        delta.push(0);
+        assert_eq!(apply_delta(&base, &delta), None);
+
+        // copy outside the base
+        let mut delta = header(base.len(), 10);
+        push_copy(&mut delta, 95, 10);
+        assert_eq!(apply_delta(&base, &delta), None);
+
+        // insert running past the end of the delta
+        let mut delta = header(base.len(), 10);
+        delta.extend([10, 1, 2, 3]);
+        assert_eq!(apply_delta(&base, &delta), None);
+
+        // more or fewer bytes than declared
+        let mut delta = header(base.len(), 5);
+        push_copy(&mut delta, 0, 10);
+        assert_eq!(apply_delta(&base, &delta), None);
+        let mut delta = header(base.len(), 20);
+        push_copy(&mut delta, 0, 10);
+        assert_eq!(apply_delta(&base, &delta), None);
+    }
+
+    #[test]
+    fn huge_declared_sizes_are_not_reserved() {
+        let delta = header(0, 1 << 60);
+        assert_eq!(apply_delta(b"", &delta), None);
+    }
+}
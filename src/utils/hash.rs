@@ -1,25 +1,116 @@
 //! Module: hash
 //!
 //! Provides hashing utilities used throughout the Git implementation.
-//! Currently, it uses SHA-1 as the hashing algorithm to compute object identifiers,
-//! consistent with Git's internal use of SHA-1 for commit, tree, and blob hashes.
+//! Object identifiers are computed with the hash algorithm of the repository, chosen at
+//! `init` time: SHA-1 (the default, as in Git) or SHA-256.
 
 use sha1::{Sha1, Digest};
+use sha2::Sha256;
 use hex;
 
-/// The length (in characters) of a hexadecimal-encoded SHA-1 hash string.
-pub const HASH_LENGTH: usize = 40;
 /// The length (in characters) used for the first level of folder sharding in the object store.
 pub const FOLDER_LENGTH: usize = 2;
 
+/// Hash algorithm used to name the objects of a repository.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+}
 
-/// Computes the SHA-1 hash of the provided byte slice and returns the hexadecimal representation as a string.
-///
-/// # Arguments
-/// * `text` - A byte slice containing the data to be hashed.
-///
-/// # Returns
-/// * A `String` representing the SHA-1 hash in hexadecimal format (40 lowercase hex characters).
-pub fn sha1(text: &[u8]) -> String {
-    hex::encode(Sha1::digest(text))
-}
\ No newline at end of file
+impl HashAlgorithm {
+    /// Returns the name of the algorithm as written in `.git/config` (`extensions.objectformat`).
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// Parses an algorithm name as written in `.git/config` or given on the command line.
+    ///
+    /// # Returns
+    /// * `Some(algorithm)` if the name is recognized, `None` otherwise.
+    pub fn from_name(name: &str) -> Option<HashAlgorithm> {
+        match name {
+            "sha1" => Some(HashAlgorithm::Sha1),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    /// Returns the length (in characters) of a hexadecimal-encoded hash.
+    pub fn hex_length(&self) -> usize {
+        self.raw_length() * 2
+    }
+
+    /// Returns the length (in bytes) of a binary hash.
+    pub fn raw_length(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => 20,
+            HashAlgorithm::Sha256 => 32,
+        }
+    }
+
+    /// Computes the hash of the provided byte slice and returns its hexadecimal representation.
+    ///
+    /// # Arguments
+    /// * `text` - A byte slice containing the data to be hashed.
+    pub fn digest(&self, text: &[u8]) -> String {
+        match self {
+            HashAlgorithm::Sha1 => hex::encode(Sha1::digest(text)),
+            HashAlgorithm::Sha256 => hex::encode(Sha256::digest(text)),
+        }
+    }
+}
+
+
+/// Incremental hasher, for data that is too large to be held in memory at once.
+pub enum StreamHasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl StreamHasher {
+    /// Creates a hasher with no data fed yet.
+    ///
+    /// # Arguments
+    /// * `algorithm` - The hash algorithm to use.
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha1 => StreamHasher::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => StreamHasher::Sha256(Sha256::new()),
+        }
+    }
+
+    /// Feeds a chunk of data into the hasher.
+    ///
+    /// # Arguments
+    /// * `data` - The next chunk of the hashed content.
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            StreamHasher::Sha1(hasher) => hasher.update(data),
+            StreamHasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Consumes the hasher and returns the hexadecimal hash of all data fed.
+    pub fn finish(self) -> String {
+        match self {
+            StreamHasher::Sha1(hasher) => hex::encode(hasher.finalize()),
+            StreamHasher::Sha256(hasher) => hex::encode(hasher.finalize()),
+        }
+    }
+}
+
+impl std::io::Write for StreamHasher {
+    /// Feeds a chunk of data into the hasher, so that content can be copied into it.
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
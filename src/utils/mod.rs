@@ -1,4 +1,7 @@
 pub mod hash;
 pub mod storage;
+#[allow(clippy::module_inception)]
 pub mod utils;
-pub mod serialize;
\ No newline at end of file
+pub mod serialize;
+pub mod compress;
+pub mod delta;
\ No newline at end of file
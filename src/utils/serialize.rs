@@ -4,8 +4,6 @@
 //! These functions are used throughout the Git implementation for serializing and deserializing
 //! object contents when interacting with storage.
 
-use std::process;
-
 use base64::{engine::general_purpose, Engine as _};
 
 
@@ -27,16 +25,7 @@ pub fn serialize(data: &[u8]) -> String {
 /// * `text` - Base64-encoded string to decode.
 ///
 /// # Returns
-/// * A `Vec<u8>` containing the decoded binary data.
-///
-/// # Exits
-/// * If the input is not valid base64, prints an error and exits the process.
-pub fn deserialize(text: &str) -> Vec<u8> {
-    match general_purpose::STANDARD.decode(text) {
-        Ok(decoded_bytes) => decoded_bytes,
-        Err(e) => {
-            eprintln!("Deserialize error when decoding base64: {}", e);
-            process::exit(1)
-        }
-    }
-}
\ No newline at end of file
+/// * The decoded binary data, or the decoding error.
+pub fn deserialize(text: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    general_purpose::STANDARD.decode(text)
+}
@@ -6,10 +6,11 @@
 
 use std::collections::HashMap;
 use std::fs;
-use std::io;
+use std::io::{self, Write};
 use std::path::Path;
 use std::path::PathBuf;
 use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::os::unix::fs::PermissionsExt;
 use std::os::unix::fs::symlink;
 
@@ -22,7 +23,10 @@ use crate::core::tree::Tree;
 use crate::core::tree::TreeTrait;
 use crate::core::tree::TreeEntryType;
 use crate::core::index;
+use crate::core::object;
 use crate::core::index::Index;
+use crate::error::{Error, Result};
+use crate::repository::Repository;
 
 use super::utils;
 
@@ -34,12 +38,20 @@ use super::utils;
 ///
 /// # Returns
 /// * `Result<Vec<u8>, io::Error>` - The content of the file or an I/O error.
-pub fn read_file(file_name: &str) -> Result<Vec<u8>, io::Error> {
+pub fn read_file(file_name: &str) -> io::Result<Vec<u8>> {
     fs::read(file_name)
 }
 
 
-/// Writes a byte slice to a file, creating parent directories if necessary.
+/// Counter making the names of temporary files unique within the process.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Writes a byte slice to a file atomically, creating parent directories if necessary.
+///
+/// The content is written to a temporary file next to the target, flushed to disk, then
+/// renamed over the target, so that readers never see a partially written file, even if
+/// the process is interrupted.
 ///
 /// # Arguments
 /// * `file_name` - Path to the file to write.
@@ -50,9 +62,124 @@ pub fn read_file(file_name: &str) -> Result<Vec<u8>, io::Error> {
 pub fn write_file(file_name: &str, contents: &[u8]) -> io::Result<()> {
     let path = Path::new(file_name);
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).expect("write_file: failed to create parent folder.");
+        fs::create_dir_all(parent)?;
+    }
+
+    let counter = TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let temp_path = format!("{}.tmp-{}-{}", file_name, process::id(), counter);
+    let mut temp_file = fs::OpenOptions::new().write(true).create_new(true).open(&temp_path)?;
+
+    let result = write_synced(&mut temp_file, contents)
+        .and_then(|_| fs::rename(&temp_path, path))
+        .and_then(|_| sync_parent_directory(path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+
+/// Moves a fully written temporary file to its final path, with the same durability as `write_file`.
+///
+/// The content is flushed to disk before the rename and the directory entry after it, so that
+/// a crash never leaves an empty or truncated file at `file_name`. Temporary files are created
+/// readable by their owner only, so the file is made readable by everyone (`0644`) first, as
+/// files written by `write_file` are.
+///
+/// # Arguments
+/// * `temp_file` - The temporary file, in the same filesystem as `file_name`.
+/// * `file_name` - Final path of the file.
+///
+/// # Returns
+/// * `io::Result<()>` - Ok(()) if successful, or an I/O error; the temporary file is removed then.
+pub fn persist_file(temp_file: tempfile::NamedTempFile, file_name: &str) -> io::Result<()> {
+    let path = Path::new(file_name);
+    temp_file.as_file().set_permissions(fs::Permissions::from_mode(0o644))?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(path).map_err(|e| e.error)?;
+    sync_parent_directory(path)
+}
+
+
+/// Writes the whole content into a file and flushes it to disk.
+fn write_synced(file: &mut fs::File, contents: &[u8]) -> io::Result<()> {
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+
+/// Flushes the directory entry of a renamed file to disk, so that the rename survives a crash.
+fn sync_parent_directory(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::File::open(parent)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+
+/// An exclusive lock on a repository file, held as long as the `<file>.lock` sibling exists.
+///
+/// The new content of the file is written into the lock file, which is then renamed over
+/// the file. A second process trying to lock the same file fails instead of interleaving
+/// its writes. Dropping the lock without committing leaves the file untouched.
+pub struct LockFile {
+    path: String,
+    lock_path: String,
+    file: fs::File,
+    committed: bool,
+}
+
+impl LockFile {
+    /// Takes the lock on a file, creating parent directories if necessary.
+    ///
+    /// # Arguments
+    /// * `file_name` - Path of the file to lock; it does not need to exist.
+    ///
+    /// # Errors
+    /// * `Error::Locked` if another process holds the lock, `Error::Io` if it cannot be created.
+    pub fn acquire(file_name: &str) -> Result<LockFile> {
+        let lock_path = format!("{}.lock", file_name);
+        let path = Path::new(file_name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(Error::io(format!("unable to create '{}'", lock_path)))?;
+        }
+
+        let file = fs::OpenOptions::new().write(true).create_new(true).open(&lock_path).map_err(|e| {
+            match e.kind() {
+                io::ErrorKind::AlreadyExists => Error::Locked(format!(
+                    "unable to create '{}': file exists. Another process seems to be running in this \
+                     repository; if not, remove the file and try again",
+                    lock_path
+                )),
+                _ => Error::Io(format!("unable to create '{}'", lock_path), e),
+            }
+        })?;
+
+        Ok(LockFile { path: file_name.to_string(), lock_path, file, committed: false })
+    }
+
+    /// Replaces the locked file with new content and releases the lock.
+    ///
+    /// # Arguments
+    /// * `contents` - Raw bytes to write to the file.
+    ///
+    /// # Errors
+    /// * `Error::Io` if the content cannot be written; the file is untouched then.
+    pub fn commit(mut self, contents: &[u8]) -> Result<()> {
+        let io_error = || Error::io(format!("Failed to write {}", self.path));
+        write_synced(&mut self.file, contents).map_err(io_error())?;
+        fs::rename(&self.lock_path, &self.path).map_err(io_error())?;
+        self.committed = true;
+        sync_parent_directory(Path::new(&self.path)).map_err(io_error())
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.lock_path);
+        }
     }
-    fs::write(path, contents)
 }
 
 
@@ -63,12 +190,12 @@ pub fn write_file(file_name: &str, contents: &[u8]) -> io::Result<()> {
 ///
 /// # Returns
 /// * `Result<String, io::Error>` - The content of the file as a UTF-8 string or an I/O error.
-pub fn read_text_file(file_name: &str) -> Result<String, io::Error> {
+pub fn read_text_file(file_name: &str) -> io::Result<String> {
     fs::read_to_string(file_name)
 }
 
 
-/// Writes a string to a text file, creating parent directories if necessary.
+/// Writes a string to a text file atomically, creating parent directories if necessary.
 ///
 /// # Arguments
 /// * `file_name` - Path to the file to write.
@@ -77,11 +204,7 @@ pub fn read_text_file(file_name: &str) -> Result<String, io::Error> {
 /// # Returns
 /// * `io::Result<()>` - Ok(()) if successful, or an I/O error.
 pub fn write_text_file(file_name: &str, contents: &str) -> io::Result<()> {
-    let path = Path::new(file_name);
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).expect("write_file: failed to create parent folder.");
-    }
-    fs::write(path, contents)
+    write_file(file_name, contents.as_bytes())
 }
 
 
@@ -90,13 +213,10 @@ pub fn write_text_file(file_name: &str, contents: &str) -> io::Result<()> {
 /// # Arguments
 /// * `directory_name` - Path of the directory to create.
 ///
-/// # Exits
-/// * If directory creation fails, prints an error and exits.
-pub fn create_directory(directory_name: &str) {
-    if let Err(e) = fs::create_dir_all(directory_name) {
-        eprintln!("Failed to create directory '{}': {}.", directory_name, e);
-        process::exit(1);
-    }
+/// # Errors
+/// * `Error::Io` if directory creation fails.
+pub fn create_directory(directory_name: &str) -> Result<()> {
+    fs::create_dir_all(directory_name).map_err(Error::io(format!("Failed to create directory '{}'", directory_name)))
 }
 
 
@@ -105,13 +225,12 @@ pub fn create_directory(directory_name: &str) {
 /// # Arguments
 /// * `file_name` - Path of the file to create.
 ///
-/// # Exits
-/// * If file creation fails, prints an error and exits.
-pub fn create_file(file_name: &str) {
-    if let Err(e) = fs::File::create(file_name) {
-        eprintln!("Failed to create file '{}': {}.", file_name, e);
-        process::exit(1);
-    }
+/// # Errors
+/// * `Error::Io` if file creation fails.
+pub fn create_file(file_name: &str) -> Result<()> {
+    fs::File::create(file_name)
+        .map(|_| ())
+        .map_err(Error::io(format!("Failed to create file '{}'", file_name)))
 }
 
 
@@ -120,69 +239,58 @@ pub fn create_file(file_name: &str) {
 /// # Arguments
 /// * `file_name` - Path of the file to remove.
 ///
-/// # Exits
-/// * If file removal fails, prints an error and exits.
-pub fn remove_file(file_name: &str) {
-    if let Err(e) = fs::remove_file(file_name) {
-        eprintln!("Failed to remove file '{}': {}.", file_name, e);
-        process::exit(1);
-    }
+/// # Errors
+/// * `Error::Io` if file removal fails.
+pub fn remove_file(file_name: &str) -> Result<()> {
+    fs::remove_file(file_name).map_err(Error::io(format!("Failed to remove file '{}'", file_name)))
 }
 
 
 /// Removes a file or directory (optionally recursively).
 ///
 /// # Arguments
+/// * `repo` - The repository whose working area holds the path.
 /// * `path` - Path to remove.
 /// * `recursive` - Whether to remove directories and their contents recursively.
 ///
 /// # Notes
-/// * If path is current working directory and recursive is true, only clears contents, not the directory itself.
-pub fn remove_path(path: &str, recursive: bool) {
+/// * If path is the repository root and recursive is true, only clears contents, not the directory itself.
+///
+/// # Errors
+/// * `Error::InvalidArgument` if the path does not exist, or is a directory and `recursive` is false.
+/// * `Error::Io` if a file or directory cannot be removed.
+pub fn remove_path(repo: &Repository, path: &str, recursive: bool) -> Result<()> {
     let p = Path::new(path);
 
     if !p.exists() {
-        eprintln!("Error: Path '{}' does not exist.", path);
-        return;
+        return Err(Error::InvalidArgument(format!("Path '{}' does not exist.", path)));
     }
 
     if p.is_file() {
-        if let Err(e) = fs::remove_file(p) {
-            eprintln!("Error removing file '{}': {}", path, e);
-        }
+        fs::remove_file(p).map_err(Error::io(format!("Error removing file '{}'", path)))
     } else if p.is_dir() {
-        if recursive {
-            if path == utils::pwd() {
-                match fs::read_dir(p) {
-                    Ok(entries) => {
-                        for entry in entries {
-                            if let Ok(entry) = entry {
-                                let sub_path = entry.path();
-                                let result = if sub_path.is_dir() {
-                                    fs::remove_dir_all(&sub_path)
-                                } else {
-                                    fs::remove_file(&sub_path)
-                                };
-                                if let Err(e) = result {
-                                    eprintln!("Error removing '{}': {}", sub_path.display(), e);
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Error reading directory '{}': {}", path, e);
-                    }
-                }
-            } else {
-                if let Err(e) = fs::remove_dir_all(p) {
-                    eprintln!("Error recursively removing directory '{}': {}", path, e);
-                }
+        if !recursive {
+            return Err(Error::InvalidArgument(format!(
+                "'{}' is a directory. Set recursive = true to remove directories.", path
+            )));
+        }
+        if path == repo.path() {
+            let entries = fs::read_dir(p).map_err(Error::io(format!("Error reading directory '{}'", path)))?;
+            for entry in entries.flatten() {
+                let sub_path = entry.path();
+                let result = if sub_path.is_dir() {
+                    fs::remove_dir_all(&sub_path)
+                } else {
+                    fs::remove_file(&sub_path)
+                };
+                result.map_err(Error::io(format!("Error removing '{}'", sub_path.display())))?;
             }
+            Ok(())
         } else {
-            eprintln!("Error: '{}' is a directory. Set recursive = true to remove directories.", path);
+            fs::remove_dir_all(p).map_err(Error::io(format!("Error recursively removing directory '{}'", path)))
         }
     } else {
-        eprintln!("Error: '{}' is neither a file nor a directory.", path);
+        Err(Error::InvalidArgument(format!("'{}' is neither a file nor a directory.", path)))
     }
 }
 
@@ -192,21 +300,16 @@ pub fn remove_path(path: &str, recursive: bool) {
 /// # Arguments
 /// * `directory_name` - Path of the directory to create.
 ///
-/// # Exits
-/// * If the directory already exists or creation fails.
-pub fn create_nonexist_directory(directory_name: &str) {
-    match fs::exists(&directory_name) {
-        Ok(res) => if res == true {
-            eprintln!("Directory {} already exists.", directory_name);
-            process::exit(1);
-        }
-        Err(e) => {
-            eprintln!("Error checking the exsitence of directory {}: {}.", directory_name, e);
-            process::exit(1);
-        }
+/// # Errors
+/// * `Error::InvalidState` if the directory already exists, `Error::Io` if creation fails.
+pub fn create_nonexist_directory(directory_name: &str) -> Result<()> {
+    let exists = fs::exists(directory_name)
+        .map_err(Error::io(format!("Error checking the existence of directory {}", directory_name)))?;
+    if exists {
+        return Err(Error::InvalidState(format!("Directory {} already exists.", directory_name)));
     }
 
-    create_directory(&directory_name);
+    create_directory(directory_name)
 }
 
 
@@ -215,21 +318,16 @@ pub fn create_nonexist_directory(directory_name: &str) {
 /// # Arguments
 /// * `file_name` - Path of the file to create.
 ///
-/// # Exits
-/// * If the file already exists or creation fails.
-pub fn create_nonexist_file(file_name: &str) {
-    match fs::exists(&file_name) {
-        Ok(res) => if res == true {
-            eprintln!("File {} already exists", file_name);
-            process::exit(1);
-        }
-        Err(e) => {
-            eprintln!("Error checking the exsitence of file {}: {}.", file_name, e);
-            process::exit(1);
-        }
+/// # Errors
+/// * `Error::InvalidState` if the file already exists, `Error::Io` if creation fails.
+pub fn create_nonexist_file(file_name: &str) -> Result<()> {
+    let exists = fs::exists(file_name)
+        .map_err(Error::io(format!("Error checking the existence of file {}", file_name)))?;
+    if exists {
+        return Err(Error::InvalidState(format!("File {} already exists", file_name)));
     }
 
-    create_file(&file_name);
+    create_file(file_name)
 }
 
 
@@ -237,52 +335,61 @@ pub fn create_nonexist_file(file_name: &str) {
 ///
 /// # Notes
 /// * Does not remove untracked files or directories.
-pub fn clear_working_area() {
-    let repo_path = utils::pwd();
+///
+/// # Arguments
+/// * `repo` - The repository whose working area is cleared.
+///
+/// # Errors
+/// * If the index cannot be read, or a file cannot be removed.
+pub fn clear_working_area(repo: &Repository) -> Result<()> {
+    let repo_path = repo.path();
 
-    let index_entries: Index = index::read_index();
+    let index_entries: Index = index::read_index(repo)?;
     for entry in &index_entries {
         let path = PathBuf::from(format!("{}/{}", repo_path, entry.0));
-        fs::remove_file(&path).unwrap_or_else(|e| {
-            eprintln!("Failed to remove directory {}: {}", path.display(), e);
-            std::process::exit(1);
-        });
-    } 
+        fs::remove_file(&path).map_err(Error::io(format!("Failed to remove file {}", path.display())))?;
+    }
+    Ok(())
 }
 
 
 /// Restores the working directory from a given commit's tree.
 ///
 /// # Arguments
+/// * `repo` - The repository whose working area is restored.
 /// * `commit_hash` - SHA-1 hash of the commit whose tree to restore.
-pub fn restore_working_area(commit_hash: &str) {
+///
+/// # Errors
+/// * If an object cannot be read, or a file cannot be written.
+pub fn restore_working_area(repo: &Repository, commit_hash: &str) -> Result<()> {
 
-    if commit_hash == "" {
-        return ;
+    if commit_hash.is_empty() {
+        return Ok(());
     }
 
     let mut commit = Commit { hash: Some(commit_hash.to_owned()), data: None };
-    commit.read_commit();
+    commit.read_commit(repo)?;
     let root_hash = commit.data.unwrap().tree_hash;
 
-    restore_tree(&root_hash, &utils::pwd());
+    restore_tree(repo, &root_hash, repo.path())
 }
 
 
 /// Recursively restores a tree object into the file system.
 ///
 /// # Arguments
+/// * `repo` - The repository holding the tree.
 /// * `tree_hash` - SHA-1 hash of the tree to restore.
 /// * `tree_path` - Filesystem (absolute) path where the tree should be restored.
 /// 
 /// # Notes
 /// * before calling `restore_tree(tree_hash, tree_path)`, this tree itself must have been built.
-fn restore_tree(tree_hash: &str, tree_path: &str) {
+fn restore_tree(repo: &Repository, tree_hash: &str, tree_path: &str) -> Result<()> {
 
     // tree_path is real path, e.g. /mnt/repo/A/B/C
 
     let mut tree = Tree { hash: Some(tree_hash.to_owned()), data: None};
-    tree.read_tree();
+    tree.read_tree(repo)?;
 
     let entries = tree.data.as_ref().unwrap();
     for entry in entries {
@@ -293,61 +400,70 @@ fn restore_tree(tree_hash: &str, tree_path: &str) {
         
         match &entry.entry_type {
             TreeEntryType::Tree => {
-                create_directory(&son_path);
-                restore_tree(&entry.hash, &son_path);
+                create_directory(&son_path)?;
+                restore_tree(repo, &entry.hash, &son_path)?;
             }
             TreeEntryType::Blob => {
-                create_file(&son_path);
-                let mut blob = Blob { hash: Some(entry.hash.clone()), data: None };
-                blob.read_blob();
-                if let Err(e) = write_file(&son_path, &blob.data.unwrap()) {
-                    eprintln!("Error when restoring blob {} : {}", son_path, e);
-                    process::exit(1);
-                }
+                restore_blob(repo, &entry.hash, &son_path)?;
             }
             TreeEntryType::Bexe => {
-                create_file(&son_path);
-                let mut blob = Blob { hash: Some(entry.hash.clone()), data: None };
-                blob.read_blob();
-                if let Err(e) = write_file(&son_path, &blob.data.unwrap()) {
-                    eprintln!("Error when restoring bexe {} : {}", son_path, e);
-                    process::exit(1);
-                }
-                
+                restore_blob(repo, &entry.hash, &son_path)?;
+
                 // add +x permission
-                let mut perms = fs::metadata(&son_path).unwrap().permissions();
-                perms.set_mode(0o755);  // rwxr-xr-x
-                fs::set_permissions(&son_path, perms).unwrap();
+                let permissions = fs::Permissions::from_mode(0o755);  // rwxr-xr-x
+                fs::set_permissions(&son_path, permissions)
+                    .map_err(Error::io(format!("Error when restoring bexe {}", son_path)))?;
             }
             TreeEntryType::Bsym => {
                 let mut blob = Blob { hash: Some(entry.hash.clone()), data: None };
-                blob.read_blob();
-                let target = String::from_utf8(blob.data.unwrap()).unwrap();  // symlink target
-                if let Err(e) = symlink(&target, &son_path) {
-                    eprintln!("Error when restoring symlink {} -> {} : {}", son_path, target, e);
-                    process::exit(1);
-                }
+                blob.read_blob(repo)?;
+                let target = String::from_utf8(blob.data.unwrap())  // symlink target
+                    .map_err(|_| Error::Corrupt(format!("symlink {} has a non UTF-8 target", son_path)))?;
+                symlink(&target, &son_path)
+                    .map_err(Error::io(format!("Error when restoring symlink {} -> {}", son_path, target)))?;
             }
         }
     }
 
+    Ok(())
+}
+
+
+/// Writes the content of a blob into a file, streaming it from the object store.
+///
+/// # Arguments
+/// * `repo` - The repository holding the blob.
+/// * `blob_hash` - SHA-1 hash of the blob to restore.
+/// * `file_path` - Filesystem (absolute) path of the file to create.
+///
+/// # Errors
+/// * If the blob cannot be read, or the file cannot be written.
+fn restore_blob(repo: &Repository, blob_hash: &str, file_path: &str) -> Result<()> {
+    let io_error = || Error::io(format!("Error when restoring blob {}", file_path));
+    let mut writer = io::BufWriter::new(fs::File::create(file_path).map_err(io_error())?);
+    object::write_object_payload_to(repo, blob_hash, object::ObjectType::Blob, &mut writer)?;
+    writer.flush().map_err(io_error())
 }
 
 
 /// Recursively builds an index by restoring a tree and collecting entries.
 ///
 /// # Arguments
+/// * `repo` - The repository holding the tree.
 /// * `tree_hash` - SHA-1 hash of the tree to restore.
 /// * `tree_path` - Filesystem (absolute) path where the tree would be restored.
 /// * `all_entries` - A map to collect index entries during restoration.
-pub fn restore_index_by_tree(tree_hash: &str, tree_path: &str, all_entries: &mut HashMap<String, IndexEntry>) {
-    
+///
+/// # Errors
+/// * If a tree cannot be read.
+pub fn restore_index_by_tree(repo: &Repository, tree_hash: &str, tree_path: &str, all_entries: &mut HashMap<String, IndexEntry>) -> Result<()> {
+
     // tree_path is real path, e.g. /mnt/repo/A/B/C
 
     let mut tree = Tree { hash: Some(tree_hash.to_owned()), data: None};
-    tree.read_tree();
+    tree.read_tree(repo)?;
 
-    let repo_path = utils::pwd();
+    let repo_path = repo.path();
 
     let entries = tree.data.as_ref().unwrap();
     for entry in entries {
@@ -358,15 +474,19 @@ pub fn restore_index_by_tree(tree_hash: &str, tree_path: &str, all_entries: &mut
         
         match &entry.entry_type {
             TreeEntryType::Tree => {
-                restore_index_by_tree(&entry.hash, &son_path, all_entries);
+                restore_index_by_tree(repo, &entry.hash, &son_path, all_entries)?;
             }
             _ => {
                 let this_entry = IndexEntry {
-                    path: utils::get_relative_path(&repo_path, &son_path),
-                    hash: entry.hash.clone()
+                    path: utils::get_relative_path(repo_path, &son_path)?,
+                    hash: entry.hash.clone(),
+                    entry_type: entry.entry_type.clone(),
+                    stat: Default::default(),
                 };
                 all_entries.insert(son_path, this_entry);
             }
         }
     }
+
+    Ok(())
 }
\ No newline at end of file
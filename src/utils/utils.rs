@@ -1,70 +1,35 @@
 //! Module: utils
 //!
 //! Provides utility functions for path manipulation, environment handling,
-//! time formatting, and working directory management used throughout the Git implementation.
+//! time formatting, and repository discovery used throughout the Git implementation.
 
 use std::path::{Path, PathBuf};
-use std::{env, process};
-use std::sync::OnceLock;
+use std::env;
 
-use chrono::Local;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
 
-static PWD: OnceLock<String> = OnceLock::new();
+use crate::error::{Error, Result};
 
-
-/// Sets the current working directory (PWD) to the absolute path of the given directory.
+/// Locates the `.git` directory of the repository holding a directory, searching from it upward.
 ///
 /// # Arguments
-/// * `path` - The directory path to set as the working directory.
-///
-/// # Exits
-/// * If canonicalization fails or if PWD was already set.
-pub fn set_pwd(path: &str) {
-    let abs_path = match Path::new(path).canonicalize() {
-        Ok(p) => p.to_string_lossy().into_owned(),
-        Err(e) => {
-            eprintln!("Error when canonicalizing path {}: {}", path, e);
-            process::exit(1)
-        }
-    };
-
-    if PWD.set(abs_path).is_err() {
-        eprintln!("PWD has already been set.");
-        process::exit(1);
-    }
-}
-
-
-/// Returns the current working directory (PWD).
+/// * `path` - Absolute path of the working area, or of any directory inside it.
 ///
-/// # Exits
-/// * If PWD has not been set yet.
-pub fn pwd() -> String {
-    match PWD.get() {
-        Some(res) => res.clone(),
-        None => {
-            eprintln!("PWD not set");
-            process::exit(1);
-        }
-    }
-}
-
-
-/// Locates and returns the absolute path to the `.git` directory by searching from the current directory upward.
+/// # Returns
+/// * `String` - Absolute path of the `.git` directory.
 ///
-/// # Exits
-/// * If no `.git` directory is found in the current or any parent directory.
-pub fn get_git_directory() -> String {
-    let mut path = PathBuf::from(pwd());
+/// # Errors
+/// * `Error::NotARepository` if no `.git` directory is found in `path` or any parent directory.
+pub fn find_git_directory(path: &str) -> Result<String> {
+    let mut path = PathBuf::from(path);
 
     loop {
         let git_path = path.join(".git");//path.join(".mygit");
         if git_path.exists() {
-            return git_path.to_string_lossy().into_owned();
+            return Ok(git_path.to_string_lossy().into_owned());
         }
         if !path.pop() {
-            eprintln!("Not a git repository (or any of the parent directories)");
-            process::exit(1)
+            return Err(Error::NotARepository);
         }
     }
 }
@@ -90,27 +55,6 @@ pub fn is_subpath(parent: &str, child: &str) -> bool {
 }
 
 
-/// Returns the relative path from the `parent` to the `child`.
-///
-/// # Arguments
-/// * `parent` - Base path.
-/// * `child` - Full path to derive the relative part from.
-///
-/// # Panics
-/// * If `child` is not under `parent`.
-pub fn relative_path(parent: &str, child: &str) -> String {
-    let parent = Path::new(parent);
-    let child = Path::new(child);
-
-    match child.strip_prefix(parent) {
-        Ok(rel) => rel.to_string_lossy().into_owned(),
-        Err(_) => {
-            panic!("child is not under parent");
-        }
-    }
-}
-
-
 /// Splits a path into the first component and the rest.
 ///
 /// # Arguments
@@ -160,6 +104,37 @@ pub fn get_time_string() -> String {
 }
 
 
+/// Converts a timestamp produced by `get_time_string` into Git's `<seconds> <+hhmm>` form.
+///
+/// # Arguments
+/// * `time` - Timestamp in `YYYYMMDDHHMMSSmmm` format, in local time.
+///
+/// # Returns
+/// * `String` - Unix seconds and the local UTC offset, or `0 +0000` if `time` is malformed.
+pub fn time_string_to_git(time: &str) -> String {
+    NaiveDateTime::parse_from_str(time, "%Y%m%d%H%M%S%3f")
+        .ok()
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(|local| local.format("%s %z").to_string())
+        .unwrap_or_else(|| "0 +0000".to_string())
+}
+
+
+/// Converts a Git `<seconds> <+hhmm>` timestamp into the `YYYYMMDDHHMMSSmmm` format.
+///
+/// # Arguments
+/// * `time` - Unix seconds followed by a UTC offset.
+///
+/// # Returns
+/// * `String` - Formatted timestamp in the recorded offset, or the input unchanged if it is malformed.
+pub fn git_to_time_string(time: &str) -> String {
+    match DateTime::parse_from_str(time, "%s %z") {
+        Ok(date_time) => date_time.format("%Y%m%d%H%M%S%3f").to_string(),
+        Err(_) => time.to_string(),
+    }
+}
+
+
 /// Returns the current user's username from environment variables.
 ///
 /// Tries `USER` first, then `USERNAME`.
@@ -182,17 +157,14 @@ pub fn get_username() -> String {
 /// * `parent_path` - Base directory.
 /// * `path` - Full path to convert to relative.
 ///
-/// # Exits
-/// * If `path` is not under `parent_path`.
-pub fn get_relative_path(parent_path: &str, path: &str) -> String {
+/// # Errors
+/// * `Error::InvalidArgument` if `path` is not under `parent_path`.
+pub fn get_relative_path(parent_path: &str, path: &str) -> Result<String> {
     let parent = Path::new(parent_path);
     let full = Path::new(path);
 
     match full.strip_prefix(parent) {
-        Ok(rel_path) => rel_path.to_string_lossy().to_string(),
-        Err(_) => {
-            eprintln!("utils::get_relative_path: not a subpath.");
-            process::exit(1);
-        }
+        Ok(rel_path) => Ok(rel_path.to_string_lossy().to_string()),
+        Err(_) => Err(Error::InvalidArgument(format!("'{}' is outside repository at '{}'", path, parent_path))),
     }
 }
\ No newline at end of file